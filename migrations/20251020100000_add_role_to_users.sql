@@ -0,0 +1,4 @@
+-- Add migration script here
+-- 'owner'可以管理所有用户(例如撤销其他用户的会话)，'admin'只能管理自己
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'admin';
+UPDATE users SET role = 'owner' WHERE user_id = 'fee087a1-efe5-44bb-bd1c-4033883f1173';
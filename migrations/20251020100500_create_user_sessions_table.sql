@@ -0,0 +1,11 @@
+-- Add migration script here
+-- 每个登录会话一行，会话被撤销时直接删除对应的行
+CREATE TABLE user_sessions (
+    session_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users(user_id),
+    created_at timestamptz NOT NULL,
+    last_active_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
@@ -0,0 +1,20 @@
+-- Add migration script here
+-- 每次发布的内容，以及每个订阅者的投递结果，用于查询投递状态
+CREATE TABLE newsletter_issues (
+    newsletter_issue_id uuid PRIMARY KEY,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    published_by uuid NOT NULL REFERENCES users(user_id),
+    published_at timestamptz NOT NULL
+);
+
+-- status: 'sent' 或 'failed'
+CREATE TABLE issue_deliveries (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(newsletter_issue_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    error_message TEXT NULL,
+    attempted_at timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
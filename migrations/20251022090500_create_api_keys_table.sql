@@ -0,0 +1,13 @@
+-- Add migration script here
+-- 只保存API密钥的SHA-256哈希值；key_prefix用于在管理页面中辨认密钥
+CREATE TABLE api_keys (
+    api_key_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users(user_id),
+    name TEXT NOT NULL,
+    key_prefix TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL
+);
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- 用于将OIDC身份映射到已有用户：按邮箱(不区分大小写)或按签发者+subject
+ALTER TABLE users ADD COLUMN email TEXT NULL;
+ALTER TABLE users ADD COLUMN oidc_issuer TEXT NULL;
+ALTER TABLE users ADD COLUMN oidc_subject TEXT NULL;
+CREATE UNIQUE INDEX users_email_idx ON users (lower(email));
+CREATE UNIQUE INDEX users_oidc_identity_idx ON users (oidc_issuer, oidc_subject);
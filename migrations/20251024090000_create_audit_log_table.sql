@@ -0,0 +1,26 @@
+-- Add migration script here
+-- 管理操作的审计日志，只允许追加
+-- actor_user_id不设外键，删除用户后仍然保留其操作记录
+CREATE TABLE audit_log (
+    audit_log_id uuid PRIMARY KEY,
+    actor_user_id uuid NULL,
+    action TEXT NOT NULL,
+    target TEXT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at DESC);
+
+CREATE FUNCTION reject_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_log_changes();
+CREATE TRIGGER audit_log_no_truncate
+    BEFORE TRUNCATE ON audit_log
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_audit_log_changes();
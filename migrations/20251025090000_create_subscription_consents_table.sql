@@ -0,0 +1,16 @@
+-- Add migration script here
+-- 订阅者同意接收邮件的证据：每次注册记录一条，确认订阅时补全确认信息
+-- source: 提交注册的表单或渠道；consent_text_version: 注册时展示的同意声明的版本
+CREATE TABLE subscription_consents (
+    consent_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id) ON DELETE CASCADE,
+    source TEXT NOT NULL,
+    consent_text_version TEXT NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    given_at timestamptz NOT NULL,
+    confirmed_at timestamptz NULL,
+    confirmation_ip_address TEXT NULL,
+    confirmation_user_agent TEXT NULL
+);
+CREATE INDEX subscription_consents_subscriber_id_idx ON subscription_consents (subscriber_id);
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- 已被删除的订阅者只保留邮箱的哈希值(小写邮箱的SHA-256)，用于避免再次意外导入该邮箱
+CREATE TABLE erased_subscribers (
+    email_hash TEXT PRIMARY KEY,
+    erased_at timestamptz NOT NULL
+);
@@ -0,0 +1,32 @@
+-- Add migration script here
+-- 订阅者可以选择的邮件列表(主题)；发布时可以选择一个或多个列表
+-- is_default: 注册或发布时未选择列表则使用默认列表，最多只有一个
+CREATE TABLE mailing_lists (
+    list_id uuid PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL
+);
+CREATE UNIQUE INDEX mailing_lists_single_default_idx ON mailing_lists (is_default) WHERE is_default;
+
+CREATE TABLE list_memberships (
+    list_id uuid NOT NULL REFERENCES mailing_lists(list_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id) ON DELETE CASCADE,
+    joined_at timestamptz NOT NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+-- 此前所有订阅者都属于同一个隐式列表，将其作为默认列表
+INSERT INTO mailing_lists (list_id, slug, name, description, is_default, created_at)
+VALUES (
+    '5b0d5c1e-8f3a-4a57-9d55-2f4c3b1e7a10',
+    'newsletter',
+    'Newsletter',
+    'Our regular newsletter.',
+    true,
+    now()
+);
+INSERT INTO list_memberships (list_id, subscriber_id, joined_at)
+SELECT '5b0d5c1e-8f3a-4a57-9d55-2f4c3b1e7a10', id, subscribed_at FROM subscriptions;
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- 订阅者在偏好设置页面中可以修改的选项
+-- email_format: 'html'或'text'，'text'表示只接收纯文本邮件
+-- paused_until: 暂停投递到该时间为止，为NULL表示没有暂停
+ALTER TABLE subscriptions ADD COLUMN email_format TEXT NOT NULL DEFAULT 'html';
+ALTER TABLE subscriptions ADD COLUMN paused_until timestamptz NULL;
+
+-- 修改邮箱时，确认令牌发送到新邮箱，确认后才替换订阅者的邮箱
+ALTER TABLE subscription_tokens ADD COLUMN new_email TEXT NULL;
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- 订阅者的标签，发布时可以用分群表达式(如'beta AND NOT churned')选择收件人
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    tagged_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
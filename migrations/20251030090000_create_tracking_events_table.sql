@@ -0,0 +1,14 @@
+-- Add migration script here
+-- 打开和点击追踪，只有发布时开启追踪的简报才会记录
+ALTER TABLE newsletter_issues ADD COLUMN tracking_enabled BOOLEAN NOT NULL DEFAULT false;
+
+-- kind: 'open' 或 'click'；url只在点击时记录
+CREATE TABLE tracking_events (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(newsletter_issue_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    url TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX tracking_events_issue_idx ON tracking_events (newsletter_issue_id, kind);
+CREATE INDEX tracking_events_subscriber_idx ON tracking_events (subscriber_id);
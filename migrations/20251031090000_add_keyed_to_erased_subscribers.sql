@@ -0,0 +1,4 @@
+-- Add migration script here
+-- 墓碑改为保存用'hmac_secret'计算的HMAC，见'email_tombstone_hash'
+-- 已有的墓碑保存的是未加密钥的SHA-256，'keyed'为false，应用启动时会把它们换成HMAC
+ALTER TABLE erased_subscribers ADD COLUMN keyed BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,183 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::request_metadata::RequestMetadata;
+
+/// 审计日志页面一次最多展示的记录数
+pub const AUDIT_LOG_PAGE_SIZE: i64 = 200;
+
+/// 需要记入审计日志的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    Logout,
+    ChangePassword,
+    PublishNewsletter,
+    CreateMailingList,
+    DeleteMailingList,
+    TagSubscriber,
+    UntagSubscriber,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 8] = [
+        AuditAction::Login,
+        AuditAction::Logout,
+        AuditAction::ChangePassword,
+        AuditAction::PublishNewsletter,
+        AuditAction::CreateMailingList,
+        AuditAction::DeleteMailingList,
+        AuditAction::TagSubscriber,
+        AuditAction::UntagSubscriber,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::Logout => "logout",
+            AuditAction::ChangePassword => "change_password",
+            AuditAction::PublishNewsletter => "publish_newsletter",
+            AuditAction::CreateMailingList => "create_mailing_list",
+            AuditAction::DeleteMailingList => "delete_mailing_list",
+            AuditAction::TagSubscriber => "tag_subscriber",
+            AuditAction::UntagSubscriber => "untag_subscriber",
+        }
+    }
+}
+
+impl TryFrom<String> for AuditAction {
+    type Error = anyhow::Error;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        AuditAction::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a known audit action.", s))
+    }
+}
+
+/// 审计日志中的一条记录
+pub struct AuditEntry {
+    pub audit_log_id: Uuid,
+    pub actor_user_id: Option<Uuid>,
+    /// 用户已被删除时为'None'
+    pub actor_username: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// 查询审计日志的过滤条件，为'None'的条件不参与过滤
+#[derive(Debug, Default)]
+pub struct AuditLogFilter {
+    pub actor_user_id: Option<Uuid>,
+    pub actor_username: Option<String>,
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// 追加一条审计日志
+/// - 'target'是操作的对象，例如'newsletter_issue/{id}'
+#[tracing::instrument(
+    name = "Record an audit log entry",
+    skip(pool, metadata),
+)]
+pub async fn record_audit_event(
+    pool: &PgPool,
+    actor_user_id: Uuid,
+    action: AuditAction,
+    target: Option<&str>,
+    metadata: &RequestMetadata,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (
+            audit_log_id,
+            actor_user_id,
+            action,
+            target,
+            ip_address,
+            user_agent,
+            occurred_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        actor_user_id,
+        action.as_str(),
+        target,
+        metadata.ip_address,
+        metadata.user_agent,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record an audit log entry.")?;
+    Ok(())
+}
+
+/// 按过滤条件查询审计日志，按时间倒序，最多返回'AUDIT_LOG_PAGE_SIZE'条
+#[tracing::instrument(
+    name = "List audit log entries",
+    skip(pool),
+)]
+pub async fn list_audit_log(
+    pool: &PgPool,
+    filter: &AuditLogFilter,
+) -> Result<Vec<AuditEntry>, anyhow::Error> {
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"
+        SELECT
+            a.audit_log_id,
+            a.actor_user_id,
+            u.username AS "actor_username?",
+            a.action,
+            a.target,
+            a.ip_address,
+            a.user_agent,
+            a.occurred_at
+        FROM audit_log a
+        LEFT JOIN users u ON u.user_id = a.actor_user_id
+        WHERE ($1::uuid IS NULL OR a.actor_user_id = $1)
+            AND ($2::text IS NULL OR u.username = $2)
+            AND ($3::text IS NULL OR a.action = $3)
+            AND ($4::text IS NULL OR a.target = $4)
+            AND ($5::timestamptz IS NULL OR a.occurred_at >= $5)
+            AND ($6::timestamptz IS NULL OR a.occurred_at < $6)
+        ORDER BY a.occurred_at DESC
+        LIMIT $7
+        "#,
+        filter.actor_user_id,
+        filter.actor_username,
+        filter.action.map(|a| a.as_str()),
+        filter.target,
+        filter.since,
+        filter.until,
+        AUDIT_LOG_PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve audit log entries.")?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditAction;
+
+    #[test]
+    fn actions_round_trip_through_their_string_form() {
+        for action in AuditAction::ALL {
+            assert_eq!(AuditAction::try_from(action.as_str().to_string()).unwrap(), action);
+        }
+    }
+
+    #[test]
+    fn an_unknown_action_is_rejected() {
+        assert!(AuditAction::try_from("delete_everything".to_string()).is_err());
+    }
+}
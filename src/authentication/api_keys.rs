@@ -0,0 +1,291 @@
+use actix_web_lab::middleware::Next;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::{web, HttpMessage, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::utils::e500;
+
+/// API密钥的前缀，便于在日志或代码仓库中识别泄露的密钥
+const API_KEY_PREFIX: &str = "z2p";
+
+/// API密钥的权限范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    PublishNewsletters,
+    ReadSubscribers,
+    WriteSubscribers,
+    ReadDeliveries,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 4] = [
+        ApiScope::PublishNewsletters,
+        ApiScope::ReadSubscribers,
+        ApiScope::WriteSubscribers,
+        ApiScope::ReadDeliveries,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::PublishNewsletters => "newsletters:publish",
+            ApiScope::ReadSubscribers => "subscribers:read",
+            ApiScope::WriteSubscribers => "subscribers:write",
+            ApiScope::ReadDeliveries => "deliveries:read",
+        }
+    }
+}
+
+impl TryFrom<String> for ApiScope {
+    type Error = anyhow::Error;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        ApiScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a known API scope.", s))
+    }
+}
+
+/// 通过API密钥认证的调用方，由'reject_invalid_api_keys'中间件放入请求扩展中
+/// - 以创建该密钥的用户的身份执行操作
+#[derive(Debug, Clone)]
+pub struct ApiKeyIdentity {
+    pub api_key_id: Uuid,
+    pub user_id: Uuid,
+    pub scopes: Vec<ApiScope>,
+}
+
+impl ApiKeyIdentity {
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// 管理页面中展示的API密钥信息，不包含密钥本身
+pub struct ApiKeyRecord {
+    pub api_key_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub name: String,
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// 生成并保存一个新的API密钥，返回密钥ID和完整的密钥
+/// - 完整的密钥只在此时可见，数据库中只保存其哈希值
+#[tracing::instrument(
+    name = "Create an API key",
+    skip(pool, scopes),
+)]
+pub async fn insert_api_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+) -> Result<(Uuid, String), anyhow::Error> {
+    let api_key_id = Uuid::new_v4();
+    let key_prefix = random_string(8);
+    let api_key = format!("{}_{}_{}", API_KEY_PREFIX, key_prefix, random_string(32));
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys (
+            api_key_id,
+            user_id,
+            name,
+            key_prefix,
+            key_hash,
+            scopes,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        api_key_id,
+        user_id,
+        name,
+        key_prefix,
+        hash_api_key(&api_key),
+        &scopes[..],
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new API key.")?;
+    Ok((api_key_id, api_key))
+}
+
+/// 获取API密钥列表，按创建时间倒序
+/// - 'user_id'为'None'时返回所有用户的密钥
+#[tracing::instrument(
+    name = "List API keys",
+    skip(pool),
+)]
+pub async fn list_api_keys(
+    pool: &PgPool,
+    user_id: Option<Uuid>,
+) -> Result<Vec<ApiKeyRecord>, anyhow::Error> {
+    let keys = sqlx::query_as!(
+        ApiKeyRecord,
+        r#"
+        SELECT
+            k.api_key_id,
+            k.user_id,
+            u.username,
+            k.name,
+            k.key_prefix,
+            k.scopes,
+            k.created_at,
+            k.last_used_at,
+            k.revoked_at
+        FROM api_keys k
+        JOIN users u ON u.user_id = k.user_id
+        WHERE $1::uuid IS NULL OR k.user_id = $1
+        ORDER BY k.created_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the list of API keys.")?;
+    Ok(keys)
+}
+
+/// 撤销API密钥，返回是否找到了尚未撤销的密钥
+/// - 'user_id'不为'None'时只能撤销该用户自己的密钥
+#[tracing::instrument(
+    name = "Revoke an API key",
+    skip(pool),
+)]
+pub async fn mark_api_key_revoked(
+    pool: &PgPool,
+    api_key_id: Uuid,
+    user_id: Option<Uuid>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = now()
+        WHERE api_key_id = $1
+            AND revoked_at IS NULL
+            AND ($2::uuid IS NULL OR user_id = $2)
+        "#,
+        api_key_id,
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke an API key.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// 校验API密钥，密钥不存在或已被撤销时返回'None'
+#[tracing::instrument(
+    name = "Authenticate an API key",
+    skip(pool, api_key),
+)]
+pub async fn authenticate_api_key(
+    pool: &PgPool,
+    api_key: &str,
+) -> Result<Option<ApiKeyIdentity>, anyhow::Error> {
+    // 密钥本身是高熵的随机字符串，直接按哈希值查找即可，无需加盐或慢哈希
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET last_used_at = now()
+        WHERE key_hash = $1 AND revoked_at IS NULL
+        RETURNING api_key_id, user_id, scopes
+        "#,
+        hash_api_key(api_key),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to validate an API key.")?;
+
+    row.map(|r| {
+        let scopes = r
+            .scopes
+            .into_iter()
+            .map(ApiScope::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ApiKeyIdentity {
+            api_key_id: r.api_key_id,
+            user_id: r.user_id,
+            scopes,
+        })
+    })
+    .transpose()
+}
+
+/// 拒绝没有携带有效API密钥('Authorization: Bearer <key>')的请求(401)
+pub async fn reject_invalid_api_keys(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not registered."))?
+        .clone();
+
+    let api_key = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|k| k.trim().to_owned());
+    let identity = match api_key {
+        Some(api_key) => authenticate_api_key(&pool, &api_key).await.map_err(e500)?,
+        None => None,
+    };
+
+    match identity {
+        Some(identity) => {
+            req.extensions_mut().insert(identity);
+            next.call(req).await
+        }
+        None => {
+            let response = HttpResponse::Unauthorized()
+                .insert_header((WWW_AUTHENTICATE, r#"Bearer realm="api""#))
+                .json(serde_json::json!({ "error": "A valid API key is required." }));
+            let e = anyhow::anyhow!("The request did not carry a valid API key");
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+fn hash_api_key(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.as_bytes()))
+}
+
+fn random_string(length: usize) -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(length)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiScope;
+
+    #[test]
+    fn scopes_round_trip_through_their_string_form() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::try_from(scope.as_str().to_string()).unwrap(), scope);
+        }
+    }
+
+    #[test]
+    fn an_unknown_scope_is_rejected() {
+        assert!(ApiScope::try_from("subscribers:delete".to_string()).is_err());
+    }
+}
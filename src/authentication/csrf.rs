@@ -0,0 +1,135 @@
+use actix_web_lab::middleware::Next;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::Method;
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use crate::session_state::TypedSession;
+use crate::utils::{e400, e500};
+
+/// 表单中携带CSRF令牌的字段名
+const CSRF_FORM_FIELD: &str = "csrf_token";
+/// 非表单请求(例如脚本)可以通过该请求头携带CSRF令牌
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// 当前会话的CSRF令牌，由'csrf_protect'中间件放入请求扩展中，
+/// 供渲染HTML表单的处理函数通过'ReqData<CsrfToken>'获取
+#[derive(Debug, Clone)]
+pub struct CsrfToken(String);
+
+impl std::fmt::Display for CsrfToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl AsRef<str> for CsrfToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// 为每个会话签发CSRF令牌，并拒绝令牌缺失或不匹配的状态变更请求(403)
+/// - 令牌存储在会话中，会话内不变
+/// - GET/HEAD/OPTIONS等安全方法不做校验
+pub async fn csrf_protect(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+
+    let expected_token = match session.get_csrf_token().map_err(e500)? {
+        Some(token) => token,
+        None => {
+            let token = generate_csrf_token();
+            session.insert_csrf_token(&token).map_err(e500)?;
+            token
+        }
+    };
+
+    if !is_safe_method(req.method()) {
+        let is_valid = submitted_csrf_token(&mut req)
+            .await?
+            .map(|token| constant_time_eq(token.as_bytes(), expected_token.as_bytes()))
+            .unwrap_or(false);
+        if !is_valid {
+            let e = anyhow::anyhow!("The CSRF token is missing or does not match the session");
+            return Err(InternalError::from_response(e, HttpResponse::Forbidden().finish()).into());
+        }
+    }
+
+    req.extensions_mut().insert(CsrfToken(expected_token));
+    next.call(req).await
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE)
+}
+
+/// 从请求头或表单字段中取出提交的CSRF令牌
+/// - 读取表单需要消费请求体，读取后再放回，后续的'web::Form'提取器不受影响
+async fn submitted_csrf_token(
+    req: &mut ServiceRequest,
+) -> Result<Option<String>, actix_web::Error> {
+    if let Some(value) = req.headers().get(CSRF_HEADER) {
+        return Ok(value.to_str().ok().map(|v| v.to_owned()));
+    }
+
+    let is_form = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map(|ct| ct.starts_with("application/x-www-form-urlencoded"))
+        .unwrap_or(false);
+    if !is_form {
+        return Ok(None);
+    }
+
+    let body = req.extract::<web::Bytes>().await?;
+    let fields: Vec<(String, String)> = serde_urlencoded::from_bytes(&body).map_err(e400)?;
+    req.set_payload(body.into());
+    Ok(fields
+        .into_iter()
+        .find(|(name, _)| name == CSRF_FORM_FIELD)
+        .map(|(_, value)| value))
+}
+
+/// 生成随机的长度为32个字符且大小写敏感的CSRF令牌
+fn generate_csrf_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+/// 比较耗时与两个令牌在哪一位不同无关，避免时序攻击
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::constant_time_eq;
+
+    #[test]
+    fn identical_tokens_are_equal() {
+        assert!(constant_time_eq(b"abcdef", b"abcdef"));
+    }
+
+    #[test]
+    fn different_tokens_are_not_equal() {
+        assert!(!constant_time_eq(b"abcdef", b"abcdeg"));
+        assert!(!constant_time_eq(b"abcdef", b"abcde"));
+        assert!(!constant_time_eq(b"", b"a"));
+    }
+}
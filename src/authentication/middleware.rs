@@ -3,11 +3,20 @@ use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::FromRequest;
 use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderMap, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use secrecy::Secret;
+use actix_web_flash_messages::FlashMessage;
+use chrono::Utc;
+use sqlx::PgPool;
 use uuid::Uuid;
 use std::ops::Deref;
 use actix_web::HttpMessage;
 use crate::utils::{e500, see_other};
 use crate::session_state::TypedSession;
+use crate::configuration::{PasswordHashingSettings, SessionSettings};
+use super::{delete_session, touch_session, validate_credentials, AuthError, Credentials};
 
 #[derive(Debug, Copy, Clone)]
 pub struct UserId(Uuid);
@@ -25,6 +34,23 @@ impl Deref for UserId {
     }
 }
 
+/// 当前请求所属的登录会话在'user_sessions'表中的ID
+#[derive(Debug, Copy, Clone)]
+pub struct SessionId(Uuid);
+
+impl std::fmt::Display for SessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for SessionId {
+    type Target = Uuid;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -33,16 +59,182 @@ pub async fn reject_anonymous_users(
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
-    
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not registered."))?
+        .clone();
+    let session_settings = req
+        .app_data::<web::Data<SessionSettings>>()
+        .ok_or_else(|| e500("The session settings are not registered."))?
+        .clone();
+
+    let user_id = session.get_user_id().map_err(e500)?;
+    let session_id = session.get_session_id().map_err(e500)?;
+    match (user_id, session_id) {
+        (Some(user_id), Some(session_id)) => {
+            let now = Utc::now().timestamp();
+            let expired = match (
+                session.get_created_at().map_err(e500)?,
+                session.get_last_active_at().map_err(e500)?,
+            ) {
+                (Some(created_at), Some(last_active_at)) => {
+                    session_has_expired(&session_settings, created_at, last_active_at, now)
+                }
+                // 缺少时间戳的会话无法判断有效期，一律视为过期
+                _ => true,
+            };
+            if expired {
+                delete_session(&pool, session_id, user_id).await.map_err(e500)?;
+                session.log_out();
+                FlashMessage::info("Your session has expired. Please log in again.").send();
+                let e = anyhow::anyhow!("The session has expired");
+                return Err(InternalError::from_response(e, see_other("/login")).into());
+            }
+
+            // 会话记录不存在，说明该会话已被撤销
+            if !touch_session(&pool, session_id, user_id).await.map_err(e500)? {
+                session.log_out();
+                FlashMessage::info("Your session has been revoked. Please log in again.").send();
+                let e = anyhow::anyhow!("The session has been revoked");
+                return Err(InternalError::from_response(e, see_other("/login")).into());
+            }
+            // 滑动续期：刷新最近活动时间
+            session.insert_last_active_at(now).map_err(e500)?;
             req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(SessionId(session_id));
             next.call(req).await
         }
-        None => {
+        _ => {
             let response = see_other("/login");
             let e = anyhow::anyhow!("The user has not logged in");
             Err(InternalError::from_response(e, response).into())
         }
     }
-}
\ No newline at end of file
+}
+
+/// 使用HTTP Basic认证代替登录会话，供没有浏览器的脚本使用
+/// - 凭据无效时返回401，并通过'WWW-Authenticate'提示客户端使用Basic认证
+pub async fn reject_invalid_basic_credentials(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not registered."))?
+        .clone();
+    let hashing_settings = req
+        .app_data::<web::Data<PasswordHashingSettings>>()
+        .ok_or_else(|| e500("The password hashing settings are not registered."))?
+        .clone();
+
+    let credentials = basic_authentication(req.headers()).map_err(basic_auth_challenge)?;
+    match validate_credentials(credentials, &pool, &hashing_settings).await {
+        Ok(user_id) => {
+            req.extensions_mut().insert(UserId(user_id));
+            next.call(req).await
+        }
+        Err(AuthError::InvalidCredentials(e)) => Err(basic_auth_challenge(e)),
+        Err(AuthError::UnexpectedError(e)) => Err(e500(e)),
+    }
+}
+
+/// 从'Authorization: Basic <base64(username:password)>'请求头中解析凭据
+fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get(AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    // 用户名中不能包含':'，密码中可以
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?;
+    Ok(Credentials {
+        username: username.to_string(),
+        password: Secret::new(password.to_string()),
+    })
+}
+
+fn basic_auth_challenge(e: anyhow::Error) -> actix_web::Error {
+    let response = HttpResponse::Unauthorized()
+        .insert_header((WWW_AUTHENTICATE, r#"Basic realm="publish""#))
+        .finish();
+    InternalError::from_response(e, response).into()
+}
+
+/// 判断会话是否已过期：闲置超时或超过最长有效期
+fn session_has_expired(
+    settings: &SessionSettings,
+    created_at: i64,
+    last_active_at: i64,
+    now: i64,
+) -> bool {
+    let idle_for = now.saturating_sub(last_active_at);
+    let age = now.saturating_sub(created_at);
+    idle_for >= settings.idle_timeout_seconds as i64
+        || age >= settings.absolute_timeout_seconds as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{basic_authentication, session_has_expired};
+    use crate::configuration::SessionSettings;
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use secrecy::ExposeSecret;
+
+    fn authorization(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn settings() -> SessionSettings {
+        SessionSettings {
+            idle_timeout_seconds: 60,
+            absolute_timeout_seconds: 600,
+        }
+    }
+
+    #[test]
+    fn an_active_session_within_its_lifetime_is_valid() {
+        assert!(!session_has_expired(&settings(), 1_000, 1_500, 1_530));
+    }
+
+    #[test]
+    fn an_idle_session_expires() {
+        assert!(session_has_expired(&settings(), 1_000, 1_000, 1_060));
+    }
+
+    #[test]
+    fn an_active_session_expires_after_its_absolute_lifetime() {
+        assert!(session_has_expired(&settings(), 1_000, 1_590, 1_600));
+    }
+
+    #[test]
+    fn basic_credentials_are_parsed() {
+        let headers = authorization(&format!("Basic {}", base64::encode("admin:pass:word")));
+        let credentials = basic_authentication(&headers).unwrap();
+        assert_eq!(credentials.username, "admin");
+        assert_eq!(credentials.password.expose_secret(), "pass:word");
+    }
+
+    #[test]
+    fn other_authorization_schemes_are_rejected() {
+        assert!(basic_authentication(&authorization("Bearer token")).is_err());
+        assert!(basic_authentication(&HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn basic_credentials_without_a_password_are_rejected() {
+        let headers = authorization(&format!("Basic {}", base64::encode("admin")));
+        assert!(basic_authentication(&headers).is_err());
+    }
+}
@@ -1,6 +1,19 @@
+mod api_keys;
+pub use api_keys::*;
+mod csrf;
+pub use csrf::{csrf_protect, CsrfToken};
 mod middleware;
-pub use middleware::reject_anonymous_users;
-pub use middleware::UserId;
+pub use middleware::{reject_anonymous_users, reject_invalid_basic_credentials};
+pub use middleware::{SessionId, UserId};
+mod oidc;
+pub use oidc::*;
 mod password;
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
-
+pub use password::{
+    change_password, dummy_password_hash, hash_password, validate_credentials, AuthError, Credentials,
+};
+mod role;
+pub use role::{get_user_role, UserRole};
+mod sessions;
+pub use sessions::*;
+mod users;
+pub use users::{create_user, get_user_id};
@@ -0,0 +1,370 @@
+use anyhow::Context;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use reqwest::Url;
+use std::str::FromStr;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::configuration::{OidcSettings, OidcUserMapping, PasswordHashingSettings};
+use super::{get_user_id, hash_password};
+
+/// 跳转到身份提供方之前生成的一次性数据，保存在会话中，回调时用于校验
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct OidcLoginState {
+    state: String,
+    nonce: String,
+    code_verifier: String,
+}
+
+/// 通过校验的ID Token中与用户映射相关的声明
+#[derive(Debug)]
+pub struct OidcIdentity {
+    pub issuer: String,
+    pub subject: String,
+    /// 只有身份提供方声明已验证过的邮箱才会保留
+    pub verified_email: Option<String>,
+    pub preferred_username: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(default)]
+    id_token_signing_alg_values_supported: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(serde::Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+    preferred_username: Option<String>,
+}
+
+/// OIDC依赖方(Relying Party)，实现授权码流程 + PKCE
+pub struct OidcClient {
+    settings: OidcSettings,
+    redirect_uri: String,
+    http_client: reqwest::Client,
+}
+
+impl OidcClient {
+    pub fn new(settings: OidcSettings, application_base_url: &str) -> Self {
+        let redirect_uri = format!("{}/login/oidc/callback", application_base_url);
+        let http_client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap();
+        Self {
+            settings,
+            redirect_uri,
+            http_client,
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.settings.display_name
+    }
+
+    pub fn user_mapping(&self) -> OidcUserMapping {
+        self.settings.user_mapping
+    }
+
+    pub fn jit_provisioning(&self) -> bool {
+        self.settings.jit_provisioning
+    }
+
+    /// 生成跳转到身份提供方的授权地址，以及需要保存在会话中的登录状态
+    #[tracing::instrument(name = "Build the OIDC authorization URL", skip(self))]
+    pub async fn authorization_url(&self) -> Result<(Url, OidcLoginState), anyhow::Error> {
+        let metadata = self.provider_metadata().await?;
+        let login_state = OidcLoginState {
+            state: random_string(32),
+            nonce: random_string(32),
+            code_verifier: random_string(64),
+        };
+
+        let mut url = Url::parse(&metadata.authorization_endpoint)
+            .context("The authorization endpoint is not a valid URL.")?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.settings.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", &self.settings.scopes)
+            .append_pair("state", &login_state.state)
+            .append_pair("nonce", &login_state.nonce)
+            .append_pair("code_challenge", &pkce_challenge(&login_state.code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        Ok((url, login_state))
+    }
+
+    /// 校验回调中的state，用授权码换取ID Token并校验
+    #[tracing::instrument(name = "Complete the OIDC login", skip(self, code, state, login_state))]
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        state: &str,
+        login_state: &OidcLoginState,
+    ) -> Result<OidcIdentity, anyhow::Error> {
+        if state != login_state.state {
+            anyhow::bail!("The state returned by the identity provider does not match.");
+        }
+        let metadata = self.provider_metadata().await?;
+
+        let token_response: TokenResponse = self
+            .http_client
+            .post(&metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("client_id", self.settings.client_id.as_str()),
+                ("client_secret", self.settings.client_secret.expose_secret().as_str()),
+                ("code_verifier", login_state.code_verifier.as_str()),
+            ])
+            .send()
+            .await
+            .context("Failed to call the token endpoint.")?
+            .error_for_status()
+            .context("The token endpoint rejected the authorization code.")?
+            .json()
+            .await
+            .context("Failed to parse the token endpoint response.")?;
+
+        let claims = self
+            .verify_id_token(&token_response.id_token, &metadata)
+            .await?;
+        if claims.nonce.as_deref() != Some(login_state.nonce.as_str()) {
+            anyhow::bail!("The nonce in the ID token does not match.");
+        }
+
+        Ok(OidcIdentity {
+            issuer: metadata.issuer,
+            subject: claims.sub,
+            verified_email: claims.email.filter(|_| claims.email_verified == Some(true)),
+            preferred_username: claims.preferred_username,
+        })
+    }
+
+    async fn provider_metadata(&self) -> Result<ProviderMetadata, anyhow::Error> {
+        let issuer_url = self.settings.issuer_url.trim_end_matches('/');
+        let metadata: ProviderMetadata = self
+            .http_client
+            .get(format!("{}/.well-known/openid-configuration", issuer_url))
+            .send()
+            .await
+            .context("Failed to fetch the OIDC discovery document.")?
+            .error_for_status()
+            .context("The OIDC discovery document is not available.")?
+            .json()
+            .await
+            .context("Failed to parse the OIDC discovery document.")?;
+        if metadata.issuer.trim_end_matches('/') != issuer_url {
+            anyhow::bail!("The discovery document belongs to another issuer ({}).", metadata.issuer);
+        }
+        Ok(metadata)
+    }
+
+    /// ID Token允许使用的签名算法
+    /// - 优先使用配置的算法，其次是发现文档中公布的算法，都没有时只允许RS256
+    /// - HS*算法以client secret作为密钥，只有明确配置时才允许
+    fn allowed_algorithms(&self, metadata: &ProviderMetadata) -> Vec<Algorithm> {
+        if !self.settings.signing_algorithms.is_empty() {
+            return self.settings.signing_algorithms.clone();
+        }
+        let advertised: Vec<Algorithm> = metadata
+            .id_token_signing_alg_values_supported
+            .iter()
+            .filter_map(|alg| Algorithm::from_str(alg).ok())
+            .filter(|alg| !is_symmetric(*alg))
+            .collect();
+        if advertised.is_empty() {
+            vec![Algorithm::RS256]
+        } else {
+            advertised
+        }
+    }
+
+    /// 校验ID Token的签名算法、签名、签发者、受众和有效期
+    /// - HS*算法使用client secret校验，其余算法使用身份提供方公布的JWKS
+    async fn verify_id_token(
+        &self,
+        id_token: &str,
+        metadata: &ProviderMetadata,
+    ) -> Result<IdTokenClaims, anyhow::Error> {
+        let header = decode_header(id_token).context("The ID token is malformed.")?;
+        if !self.allowed_algorithms(metadata).contains(&header.alg) {
+            anyhow::bail!("The ID token is signed with a disallowed algorithm ({:?}).", header.alg);
+        }
+        let key = match header.alg {
+            alg if is_symmetric(alg) => {
+                DecodingKey::from_secret(self.settings.client_secret.expose_secret().as_bytes())
+            }
+            _ => {
+                let jwks: JwkSet = self
+                    .http_client
+                    .get(&metadata.jwks_uri)
+                    .send()
+                    .await
+                    .context("Failed to fetch the JWKS of the identity provider.")?
+                    .error_for_status()
+                    .context("The JWKS of the identity provider is not available.")?
+                    .json()
+                    .await
+                    .context("Failed to parse the JWKS of the identity provider.")?;
+                let jwk = match &header.kid {
+                    Some(kid) => jwks.find(kid),
+                    None => jwks.keys.first(),
+                }
+                .context("No matching key to verify the ID token.")?;
+                DecodingKey::from_jwk(jwk).context("The signing key is not supported.")?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.settings.client_id]);
+        validation.set_issuer(&[&metadata.issuer]);
+        let token = decode::<IdTokenClaims>(id_token, &key, &validation)
+            .context("The ID token failed validation.")?;
+        Ok(token.claims)
+    }
+}
+
+/// 根据配置的映射方式查找身份对应的用户，找不到时按配置自动创建
+/// - 返回'None'表示该身份没有对应的用户，且不允许或无法自动创建
+#[tracing::instrument(
+    name = "Find or provision the user of an OIDC identity",
+    skip(pool, client, hashing_settings),
+)]
+pub async fn find_or_provision_oidc_user(
+    pool: &PgPool,
+    client: &OidcClient,
+    identity: &OidcIdentity,
+    hashing_settings: &PasswordHashingSettings,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let existing = match client.user_mapping() {
+        OidcUserMapping::Subject => sqlx::query!(
+            r#"SELECT user_id FROM users WHERE oidc_issuer = $1 AND oidc_subject = $2"#,
+            identity.issuer,
+            identity.subject,
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up a user by OIDC subject.")?
+        .map(|r| r.user_id),
+        OidcUserMapping::Email => match &identity.verified_email {
+            Some(email) => sqlx::query!(
+                r#"SELECT user_id FROM users WHERE lower(email) = lower($1)"#,
+                email,
+            )
+            .fetch_optional(pool)
+            .await
+            .context("Failed to look up a user by email.")?
+            .map(|r| r.user_id),
+            None => None,
+        },
+    };
+    if existing.is_some() || !client.jit_provisioning() {
+        return Ok(existing);
+    }
+    // 按邮箱映射时，没有已验证的邮箱就无法在下次登录时找到该用户
+    if client.user_mapping() == OidcUserMapping::Email && identity.verified_email.is_none() {
+        return Ok(None);
+    }
+    provision_user(pool, identity, hashing_settings).await
+}
+
+/// 为OIDC身份创建新用户
+/// - 密码是随机生成且不公开的，该用户只能通过单点登录
+/// - 用户名已被占用时加上随机后缀重试；邮箱已属于其他用户时无法创建，返回'None'
+async fn provision_user(
+    pool: &PgPool,
+    identity: &OidcIdentity,
+    hashing_settings: &PasswordHashingSettings,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let base_username = identity
+        .verified_email
+        .clone()
+        .or_else(|| identity.preferred_username.clone())
+        .unwrap_or_else(|| identity.subject.clone());
+    let password_hash = hash_password(Secret::new(random_string(64)), hashing_settings).await?;
+
+    let mut username = base_username.clone();
+    for _ in 0..3 {
+        let user_id = sqlx::query!(
+            r#"
+            INSERT INTO users (user_id, username, password_hash, email, oidc_issuer, oidc_subject)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT DO NOTHING
+            RETURNING user_id
+            "#,
+            Uuid::new_v4(),
+            username,
+            password_hash.expose_secret(),
+            identity.verified_email,
+            identity.issuer,
+            identity.subject,
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to provision a user for an OIDC identity.")?
+        .map(|r| r.user_id);
+        if let Some(user_id) = user_id {
+            tracing::info!(%user_id, %username, "Provisioned a new user for an OIDC identity");
+            return Ok(Some(user_id));
+        }
+        if get_user_id(pool, &username).await?.is_none() {
+            // 冲突的不是用户名，而是邮箱或身份，换用户名也无济于事
+            break;
+        }
+        username = format!("{}-{}", base_username, random_string(6).to_lowercase());
+    }
+    tracing::warn!(
+        username = %base_username,
+        "Could not provision a user for an OIDC identity: the account already exists"
+    );
+    Ok(None)
+}
+
+fn is_symmetric(alg: Algorithm) -> bool {
+    matches!(alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)
+}
+
+/// PKCE S256: BASE64URL(SHA256(code_verifier))
+fn pkce_challenge(code_verifier: &str) -> String {
+    base64::encode_config(Sha256::digest(code_verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+fn random_string(length: usize) -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(length)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pkce_challenge;
+
+    #[test]
+    fn pkce_challenge_is_the_unpadded_url_safe_sha256_of_the_verifier() {
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mJ0kZgAImGDqsCLbIvzhiY3avJKnqw"),
+            "4-QOcGS4a-fTx_dxjTnNiEz4_7Yp5oyGhaHzV3d9Hy4"
+        );
+    }
+}
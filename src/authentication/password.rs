@@ -3,6 +3,7 @@ use secrecy::Secret;
 use secrecy::ExposeSecret;
 use sqlx::PgPool;
 use crate::telemetry::spawn_blocking_with_tracing;
+use crate::configuration::PasswordHashingSettings;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, 
     PasswordHasher, PasswordVerifier, Version
@@ -26,20 +27,18 @@ pub struct Credentials {
 /// - 1、先从数据库中查询存储的HPC格式的哈希值
 /// - 2、使用PHC格式的哈希值初始化PasswrodHash(PHC的实现)
 /// - 3、使用PHC实例验证password
+/// - 4、验证通过后，若存储的哈希值使用了过时的算法或参数，则透明地重新计算并保存
 #[tracing::instrument(
     name = "Validate credentials",
-    skip(credentials, pool),
+    skip(credentials, pool, hashing_settings),
 )]
 pub async fn validate_credentials(
     credentials: Credentials,
     pool: &PgPool,
+    hashing_settings: &PasswordHashingSettings,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$gZiv/M1gPc22ElAH/Jh1Hw$\
-        CwOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-        .to_string(),
-    );
+    let mut expected_password_hash = dummy_password_hash(hashing_settings)?;
 
     if let Some((stored_user_id, stored_password_hash)) = get_stored_credentials(
         &credentials.username,
@@ -51,6 +50,8 @@ pub async fn validate_credentials(
         expected_password_hash = stored_password_hash;
     }
 
+    let stored_password_hash = expected_password_hash.clone();
+    let password_candidate = credentials.password.clone();
     spawn_blocking_with_tracing(move || {
         verify_password_hash(
             expected_password_hash, 
@@ -63,10 +64,91 @@ pub async fn validate_credentials(
     // 只有在存储中找到凭据，才会将其设置为'Some'
     // 因此，即使默认密码与所提供的密码匹配(以某种方式)
     // 也永远不会对不存在的用户进行身份验证
-    user_id.ok_or_else(|| 
+    let user_id = user_id.ok_or_else(|| 
         anyhow::anyhow!("Unkonw username")
     )
-    .map_err(AuthError::InvalidCredentials)
+    .map_err(AuthError::InvalidCredentials)?;
+
+    // 升级失败不影响本次登录，下次登录时会再次尝试
+    if let Err(e) = upgrade_password_hash_if_outdated(
+        user_id,
+        stored_password_hash,
+        password_candidate,
+        hashing_settings,
+        pool,
+    )
+    .await
+    {
+        tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to upgrade an outdated password hash",
+        );
+    }
+
+    Ok(user_id)
+}
+
+/// 用户名不存在时用于校验的占位哈希值
+/// - 使用配置中的参数计算，校验耗时与真实用户相同，无法通过响应时间判断用户名是否存在
+/// - 每组配置只计算一次；启动时调用一次，避免第一次登录时多花一次哈希的时间
+pub fn dummy_password_hash(
+    hashing_settings: &PasswordHashingSettings,
+) -> Result<Secret<String>, anyhow::Error> {
+    let password_hash = hashing_settings
+        .dummy_password_hash
+        .get_or_try_init(|| {
+            let params = hashing_settings
+                .params()
+                .context("Invalid Argon2 parameters in configuration.")?;
+            let password = Secret::new(uuid::Uuid::new_v4().to_string());
+            compute_passowrd_hash(password, params)
+                .map(|password_hash| password_hash.expose_secret().to_owned())
+        })?;
+    Ok(Secret::new(password_hash.clone()))
+}
+
+/// 存储的哈希值与当前配置的算法、版本或参数不一致时，
+/// 使用刚刚验证通过的明文密码重新计算哈希值并保存
+#[tracing::instrument(
+    name = "Upgrade outdated password hash",
+    skip(stored_password_hash, password, hashing_settings, pool),
+)]
+async fn upgrade_password_hash_if_outdated(
+    user_id: uuid::Uuid,
+    stored_password_hash: Secret<String>,
+    password: Secret<String>,
+    hashing_settings: &PasswordHashingSettings,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let params = hashing_settings
+        .params()
+        .context("Invalid Argon2 parameters in configuration.")?;
+    let stored_password_hash = PasswordHash::new(stored_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format.")?;
+    if !needs_rehash(&stored_password_hash, &params) {
+        return Ok(());
+    }
+    tracing::info!("Stored password hash is outdated, rehashing it");
+    store_password_hash(user_id, password, params, pool).await
+}
+
+/// 判断PHC格式的哈希值是否使用了当前配置之外的算法、版本或参数
+fn needs_rehash(password_hash: &PasswordHash, params: &Params) -> bool {
+    if password_hash.algorithm != Algorithm::Argon2id.ident() {
+        return true;
+    }
+    if password_hash.version != Some(Version::V0x13.into()) {
+        return true;
+    }
+    match Params::try_from(password_hash) {
+        Ok(stored_params) => {
+            stored_params.m_cost() != params.m_cost()
+                || stored_params.t_cost() != params.t_cost()
+                || stored_params.p_cost() != params.p_cost()
+        }
+        Err(_) => true,
+    }
 }
 
 #[tracing::instrument(
@@ -116,15 +198,28 @@ async fn get_stored_credentials(
 
 #[tracing::instrument(
     name = "Change password",
-    skip(password, pool),
+    skip(password, pool, hashing_settings),
 )]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
     pool: &PgPool,
+    hashing_settings: &PasswordHashingSettings,
+) -> Result<(), anyhow::Error> {
+    let params = hashing_settings
+        .params()
+        .context("Invalid Argon2 parameters in configuration.")?;
+    store_password_hash(user_id, password, params, pool).await
+}
+
+async fn store_password_hash(
+    user_id: uuid::Uuid,
+    password: Secret<String>,
+    params: Params,
+    pool: &PgPool,
 ) -> Result<(), anyhow::Error> {
     let password_hash = spawn_blocking_with_tracing(
-            move || compute_passowrd_hash(password)
+            move || compute_passowrd_hash(password, params)
         )
         .await?
         .context("Failed to hash password")?;
@@ -145,17 +240,86 @@ pub async fn change_password(
     Ok(())
 }
 
+/// 使用配置中的参数计算新的密码哈希值，例如为新用户设置密码
+pub async fn hash_password(
+    password: Secret<String>,
+    hashing_settings: &PasswordHashingSettings,
+) -> Result<Secret<String>, anyhow::Error> {
+    let params = hashing_settings
+        .params()
+        .context("Invalid Argon2 parameters in configuration.")?;
+    spawn_blocking_with_tracing(move || compute_passowrd_hash(password, params))
+        .await?
+        .context("Failed to hash password")
+}
+
 fn compute_passowrd_hash(
     password: Secret<String>,
+    params: Params,
 ) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
     let password_hash = Argon2::new(
         Algorithm::Argon2id, 
         Version::V0x13, 
-        Params::new(1500, 2, 1, None).unwrap(),
+        params,
     )
     .hash_password(password.expose_secret().as_bytes(), &salt)?
     .to_string();
 
     Ok(Secret::new(password_hash))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dummy_password_hash, needs_rehash};
+    use crate::configuration::PasswordHashingSettings;
+    use argon2::password_hash::SaltString;
+    use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version};
+    use secrecy::ExposeSecret;
+
+    fn hash_with(algorithm: Algorithm, params: Params) -> String {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        Argon2::new(algorithm, Version::V0x13, params)
+            .hash_password(b"everythinghastostartsomewhere", &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn a_hash_with_the_configured_parameters_is_kept() {
+        let params = Params::new(16, 1, 1, None).unwrap();
+        let hash = hash_with(Algorithm::Argon2id, params.clone());
+        assert!(!needs_rehash(&PasswordHash::new(&hash).unwrap(), &params));
+    }
+
+    #[test]
+    fn a_hash_using_argon2d_is_rehashed() {
+        let params = Params::new(16, 1, 1, None).unwrap();
+        let hash = hash_with(Algorithm::Argon2d, params.clone());
+        assert!(needs_rehash(&PasswordHash::new(&hash).unwrap(), &params));
+    }
+
+    #[test]
+    fn a_hash_using_outdated_parameters_is_rehashed() {
+        let hash = hash_with(Algorithm::Argon2id, Params::new(16, 1, 1, None).unwrap());
+        let params = Params::new(32, 2, 1, None).unwrap();
+        assert!(needs_rehash(&PasswordHash::new(&hash).unwrap(), &params));
+    }
+
+    #[test]
+    fn the_dummy_hash_uses_the_configured_parameters() {
+        let settings = PasswordHashingSettings {
+            memory_size_kib: 32,
+            iterations: 2,
+            parallelism: 1,
+            dummy_password_hash: Default::default(),
+        };
+        let dummy_hash = dummy_password_hash(&settings).unwrap();
+        let params = settings.params().unwrap();
+        assert!(!needs_rehash(&PasswordHash::new(dummy_hash.expose_secret()).unwrap(), &params));
+        assert_eq!(
+            dummy_password_hash(&settings).unwrap().expose_secret(),
+            dummy_hash.expose_secret()
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// 用户角色
+/// - Owner: 可以管理所有用户，例如撤销其他用户的会话
+/// - Admin: 只能管理自己
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserRole {
+    Owner,
+    Admin,
+}
+
+impl UserRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Owner => "owner",
+            UserRole::Admin => "admin",
+        }
+    }
+}
+
+impl TryFrom<String> for UserRole {
+    type Error = anyhow::Error;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "owner" => Ok(Self::Owner),
+            "admin" => Ok(Self::Admin),
+            other => anyhow::bail!("{} is not a known user role.", other),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Get user role",
+    skip(pool),
+)]
+pub async fn get_user_role(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<UserRole, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT role FROM users WHERE user_id = $1"#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve the user's role.")?;
+    row.role.try_into()
+}
@@ -0,0 +1,198 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::request_metadata::RequestMetadata;
+use crate::session_state::TypedSession;
+
+/// 用户的一个登录会话
+/// - 会话状态本身存储在Redis中，这里只记录便于查看和撤销的元数据
+pub struct ActiveSession {
+    pub session_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+    pub last_active_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// 登录成功后登记一个新的会话，返回会话ID
+#[tracing::instrument(
+    name = "Register a new session",
+    skip(pool, metadata),
+)]
+pub async fn register_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    metadata: &RequestMetadata,
+) -> Result<Uuid, anyhow::Error> {
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (
+            session_id,
+            user_id,
+            created_at,
+            last_active_at,
+            ip_address,
+            user_agent
+        )
+        VALUES ($1, $2, now(), now(), $3, $4)
+        "#,
+        session_id,
+        user_id,
+        metadata.ip_address,
+        metadata.user_agent,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to register a new session.")?;
+    Ok(session_id)
+}
+
+/// 用户通过认证后建立登录会话
+/// - 轮换会话令牌，登记会话，并记录登录时间
+pub async fn start_session(
+    session: &TypedSession,
+    pool: &PgPool,
+    user_id: Uuid,
+    metadata: &RequestMetadata,
+) -> Result<(), anyhow::Error> {
+    // 用户登录时轮换会话令牌
+    session.renew();
+    let session_id = register_session(pool, user_id, metadata).await?;
+    session.insert_user_id(user_id)?;
+    session.insert_session_id(session_id)?;
+    let now = Utc::now().timestamp();
+    session.insert_created_at(now)?;
+    session.insert_last_active_at(now)?;
+    Ok(())
+}
+
+/// 更新会话的最近活动时间
+/// - 返回'false'表示会话已被撤销
+#[tracing::instrument(
+    name = "Touch session",
+    skip(pool),
+)]
+pub async fn touch_session(
+    pool: &PgPool,
+    session_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET last_active_at = now()
+        WHERE session_id = $1 AND user_id = $2
+        "#,
+        session_id,
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the session's last activity.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// 撤销用户自己的某个会话，返回是否找到了该会话
+#[tracing::instrument(
+    name = "Delete session",
+    skip(pool),
+)]
+pub async fn delete_session(
+    pool: &PgPool,
+    session_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE session_id = $1 AND user_id = $2
+        "#,
+        session_id,
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete a session.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// 撤销用户除当前会话以外的所有会话
+#[tracing::instrument(
+    name = "Delete other sessions",
+    skip(pool),
+)]
+pub async fn delete_other_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    current_session_id: Uuid,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND session_id <> $2
+        "#,
+        user_id,
+        current_session_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete the other sessions of a user.")?;
+    Ok(result.rows_affected())
+}
+
+/// 撤销某个用户的所有会话
+#[tracing::instrument(
+    name = "Delete user sessions",
+    skip(pool),
+)]
+pub async fn delete_user_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM user_sessions WHERE user_id = $1"#,
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete the sessions of a user.")?;
+    Ok(result.rows_affected())
+}
+
+/// 获取会话列表，按最近活动时间倒序
+/// - 'user_id'为'None'时返回所有用户的会话
+#[tracing::instrument(
+    name = "List sessions",
+    skip(pool),
+)]
+pub async fn list_sessions(
+    pool: &PgPool,
+    user_id: Option<Uuid>,
+) -> Result<Vec<ActiveSession>, anyhow::Error> {
+    let sessions = sqlx::query_as!(
+        ActiveSession,
+        r#"
+        SELECT
+            s.session_id,
+            s.user_id,
+            u.username,
+            s.created_at,
+            s.last_active_at,
+            s.ip_address,
+            s.user_agent
+        FROM user_sessions s
+        JOIN users u ON u.user_id = s.user_id
+        WHERE $1::uuid IS NULL OR s.user_id = $1
+        ORDER BY s.last_active_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the list of sessions.")?;
+    Ok(sessions)
+}
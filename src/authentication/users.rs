@@ -0,0 +1,58 @@
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::configuration::PasswordHashingSettings;
+use super::{hash_password, UserRole};
+
+/// 创建可以用密码登录的用户，用户名已存在时返回错误
+#[tracing::instrument(
+    name = "Create a user",
+    skip(pool, password, hashing_settings),
+)]
+pub async fn create_user(
+    pool: &PgPool,
+    username: &str,
+    password: Secret<String>,
+    role: UserRole,
+    hashing_settings: &PasswordHashingSettings,
+) -> Result<Uuid, anyhow::Error> {
+    if get_user_id(pool, username).await?.is_some() {
+        anyhow::bail!("A user named {} already exists.", username);
+    }
+    let user_id = Uuid::new_v4();
+    let password_hash = hash_password(password, hashing_settings).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, role)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        role.as_str(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create a user.")?;
+    Ok(user_id)
+}
+
+/// 按用户名查询用户ID，用户不存在时返回'None'
+#[tracing::instrument(
+    name = "Get user id by username",
+    skip(pool),
+)]
+pub async fn get_user_id(
+    pool: &PgPool,
+    username: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE username = $1"#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up a user by username.")?;
+    Ok(row.map(|r| r.user_id))
+}
@@ -0,0 +1,277 @@
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::io::BufRead;
+use uuid::Uuid;
+use crate::authentication::{change_password, create_user, delete_user_sessions, get_user_id, UserRole};
+use crate::configuration::{EmailClientSettings, Settings};
+use crate::consent::record_consent_confirmation;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::idempotency::prune_saved_responses;
+use crate::migrations::{apply_migrations, pending_migrations};
+use crate::request_metadata::RequestMetadata;
+use crate::routes::confirm_subscriber;
+use crate::startup::get_connection_pool;
+use crate::subscriber_data::find_subscriber_by_email;
+use crate::subscribers::list_subscribers;
+
+/// 命令行参数，不带子命令时启动服务器
+#[derive(Parser)]
+#[command(name = "zero2prod", about = "A newsletter delivery service")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (the default)
+    Serve,
+    /// Apply the database migrations embedded in this binary
+    Migrate {
+        /// Only list the pending migrations, failing if there are any
+        #[arg(long)]
+        check: bool,
+    },
+    /// Create a user who can log in to the admin area. The password is read from stdin
+    CreateAdmin {
+        #[arg(long)]
+        username: String,
+        /// 'owner' or 'admin'
+        #[arg(long, default_value = "admin", value_parser = parse_role)]
+        role: UserRole,
+    },
+    /// Set a new password for a user and log out all of their sessions. The password is read from stdin
+    ResetPassword {
+        #[arg(long)]
+        username: String,
+    },
+    /// Print the subscribers as tab-separated values
+    ListSubscribers {
+        /// Only list subscribers with this status, e.g. 'confirmed'
+        #[arg(long)]
+        status: Option<String>,
+    },
+    /// Confirm a pending subscriber without the confirmation link
+    ConfirmSubscriber {
+        #[arg(long)]
+        email: String,
+    },
+    /// Send a test email through the configured email provider
+    SendTestEmail {
+        #[arg(long)]
+        to: String,
+    },
+    /// Delete saved idempotent responses older than the given number of hours
+    PruneIdempotency {
+        #[arg(long, default_value_t = 48)]
+        older_than_hours: i64,
+    },
+    /// Validate the configuration without starting the server, listing every problem
+    CheckConfig,
+}
+
+fn parse_role(s: &str) -> Result<UserRole, String> {
+    UserRole::try_from(s.to_string()).map_err(|e| e.to_string())
+}
+
+/// 执行管理命令，结果输出到标准输出
+/// - 'serve'不是管理命令，由main.rs处理，在这里返回错误
+pub async fn run_command(command: Command, configuration: &Settings) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    match command {
+        Command::Serve => anyhow::bail!("`serve` starts the HTTP server and is not an admin command."),
+        Command::Migrate { check } => migrate(&pool, check).await,
+        Command::CreateAdmin { username, role } => {
+            let password = read_password()?;
+            let user_id =
+                create_user(&pool, &username, password, role, &configuration.password_hashing)
+                    .await?;
+            println!("Created {} {} ({}).", role.as_str(), username, user_id);
+            Ok(())
+        }
+        Command::ResetPassword { username } => {
+            let user_id = get_user_id(&pool, &username)
+                .await?
+                .with_context(|| format!("There is no user named {}.", username))?;
+            let password = read_password()?;
+            change_password(user_id, password, &pool, &configuration.password_hashing).await?;
+            let revoked = delete_user_sessions(&pool, user_id).await?;
+            println!("Changed the password of {} and revoked {} session(s).", username, revoked);
+            Ok(())
+        }
+        Command::ListSubscribers { status } => {
+            println!("id\temail\tname\tstatus\tsubscribed_at");
+            for s in list_subscribers(&pool, status.as_deref()).await? {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    s.id,
+                    s.email,
+                    s.name,
+                    s.status,
+                    s.subscribed_at.to_rfc3339()
+                );
+            }
+            Ok(())
+        }
+        Command::ConfirmSubscriber { email } => {
+            let subscriber_id = confirm_subscriber_by_email(&pool, &email).await?;
+            println!("Confirmed {} ({}).", email, subscriber_id);
+            Ok(())
+        }
+        Command::SendTestEmail { to } => {
+            send_test_email(&configuration.email_client, &to).await?;
+            println!("Sent a test email to {}.", to);
+            Ok(())
+        }
+        Command::PruneIdempotency { older_than_hours } => {
+            let deleted =
+                prune_saved_responses(&pool, chrono::Duration::hours(older_than_hours)).await?;
+            println!("Deleted {} saved response(s).", deleted);
+            Ok(())
+        }
+        Command::CheckConfig => {
+            configuration.validate()?;
+            println!("The {} configuration is valid.", configuration.environment.as_str());
+            Ok(())
+        }
+    }
+}
+
+/// 执行或检查嵌入的数据库迁移
+async fn migrate(pool: &PgPool, check: bool) -> Result<(), anyhow::Error> {
+    if check {
+        let pending = pending_migrations(pool).await?;
+        if !pending.is_empty() {
+            anyhow::bail!("The database has pending migrations: {:?}.", pending);
+        }
+        println!("The database is up to date.");
+        return Ok(());
+    }
+    let applied = apply_migrations(pool).await?;
+    if applied.is_empty() {
+        println!("The database is up to date.");
+    } else {
+        println!("Applied {} migration(s): {:?}.", applied.len(), applied);
+    }
+    Ok(())
+}
+
+/// 跳过确认链接，直接确认订阅者，同意记录的User-Agent标记为命令行
+/// - 已退订的订阅者不能被确认
+pub async fn confirm_subscriber_by_email(pool: &PgPool, email: &str) -> Result<Uuid, anyhow::Error> {
+    let subscriber = find_subscriber_by_email(pool, email)
+        .await?
+        .with_context(|| format!("There is no subscriber with the email {}.", email))?;
+    if subscriber.status == "unsubscribed" {
+        anyhow::bail!("{} has unsubscribed and cannot be confirmed.", email);
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    confirm_subscriber(&mut transaction, subscriber.id).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+    let metadata = RequestMetadata {
+        ip_address: None,
+        user_agent: Some("zero2prod confirm-subscriber".into()),
+    };
+    record_consent_confirmation(pool, subscriber.id, &metadata).await?;
+    Ok(subscriber.id)
+}
+
+/// 使用配置中的邮件服务发送一封测试邮件
+pub async fn send_test_email(settings: &EmailClientSettings, to: &str) -> Result<(), anyhow::Error> {
+    let recipient = SubscriberEmail::parse(to.to_string()).map_err(anyhow::Error::msg)?;
+    let sender = settings.sender().map_err(anyhow::Error::msg)?;
+    let email_client = EmailClient::new(
+        settings.base_url.clone(),
+        sender,
+        settings.authorization_token.clone(),
+        settings.timeout(),
+    );
+    email_client
+        .send_email(
+            &recipient,
+            "Test email from zero2prod",
+            "<p>The email provider is configured correctly.</p>",
+            "The email provider is configured correctly.",
+        )
+        .await
+        .context("Failed to send the test email.")
+}
+
+/// 从标准输入读取一行作为密码，避免密码出现在命令行参数和shell历史中
+fn read_password() -> Result<Secret<String>, anyhow::Error> {
+    eprintln!("Enter the password:");
+    let mut password = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut password)
+        .context("Failed to read the password from stdin.")?;
+    let password = password.trim_end_matches(&['\r', '\n'][..]).to_string();
+    if password.is_empty() {
+        anyhow::bail!("The password cannot be empty.");
+    }
+    Ok(Secret::new(password))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+    use crate::authentication::UserRole;
+    use super::{Cli, Command};
+
+    #[test]
+    fn the_cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn no_subcommand_runs_the_server() {
+        let cli = Cli::try_parse_from(["zero2prod"]).unwrap();
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn migrate_accepts_check() {
+        let cli = Cli::try_parse_from(["zero2prod", "migrate", "--check"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Migrate { check: true })));
+    }
+
+    #[test]
+    fn create_admin_defaults_to_the_admin_role() {
+        let cli = Cli::try_parse_from(["zero2prod", "create-admin", "--username", "ursula"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::CreateAdmin { role: UserRole::Admin, .. })
+        ));
+        let cli = Cli::try_parse_from([
+            "zero2prod", "create-admin", "--username", "ursula", "--role", "owner",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::CreateAdmin { role: UserRole::Owner, .. })
+        ));
+    }
+
+    #[test]
+    fn create_admin_rejects_unknown_roles() {
+        let outcome = Cli::try_parse_from([
+            "zero2prod", "create-admin", "--username", "ursula", "--role", "root",
+        ]);
+        assert!(outcome.is_err());
+    }
+
+    #[test]
+    fn check_config_takes_no_arguments() {
+        let cli = Cli::try_parse_from(["zero2prod", "check-config"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::CheckConfig)));
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::request_metadata::RequestMetadata;
+
+/// 注册表单未注明来源时记录的来源
+pub const DEFAULT_CONSENT_SOURCE: &str = "signup_form";
+
+/// 注册时随表单提交的同意信息
+pub struct ConsentEvidence {
+    /// 提交注册的表单或渠道，例如'homepage'
+    pub source: String,
+    /// 注册时展示的同意声明的版本
+    pub consent_text_version: String,
+}
+
+/// 一条同意记录；'confirmed_at'为'None'表示订阅者尚未确认
+#[derive(Debug, serde::Serialize)]
+pub struct ConsentRecord {
+    pub consent_id: Uuid,
+    pub subscriber_id: Uuid,
+    pub source: String,
+    pub consent_text_version: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub given_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub confirmation_ip_address: Option<String>,
+    pub confirmation_user_agent: Option<String>,
+}
+
+/// 校验来源或声明版本这类短标签：1到64个字母、数字或'-_.:/'
+pub fn parse_consent_label(value: String, field: &str) -> Result<String, String> {
+    let value = value.trim().to_string();
+    let is_valid = !value.is_empty()
+        && value.len() <= 64
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:/".contains(c));
+    if is_valid {
+        Ok(value)
+    } else {
+        Err(format!("{} is not a valid {}.", value, field))
+    }
+}
+
+/// 记录注册时的同意证据，与新订阅者在同一个事务中写入
+#[tracing::instrument(
+    name = "Record the consent of a new subscriber",
+    skip(transaction, evidence, metadata),
+)]
+pub async fn insert_consent_record(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    evidence: &ConsentEvidence,
+    metadata: &RequestMetadata,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_consents (
+            consent_id,
+            subscriber_id,
+            source,
+            consent_text_version,
+            ip_address,
+            user_agent,
+            given_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        evidence.source,
+        evidence.consent_text_version,
+        metadata.ip_address,
+        metadata.user_agent,
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to record the consent of a new subscriber.")?;
+    Ok(())
+}
+
+/// 订阅者点击确认链接后，补全所有尚未确认的同意记录
+/// - 重复点击确认链接不会覆盖第一次确认的时间
+#[tracing::instrument(
+    name = "Record the confirmation of a subscriber's consent",
+    skip(pool, metadata),
+)]
+pub async fn record_consent_confirmation(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    metadata: &RequestMetadata,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscription_consents
+        SET
+            confirmed_at = now(),
+            confirmation_ip_address = $2,
+            confirmation_user_agent = $3
+        WHERE subscriber_id = $1 AND confirmed_at IS NULL
+        "#,
+        subscriber_id,
+        metadata.ip_address,
+        metadata.user_agent,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the confirmation of a subscriber's consent.")?;
+    Ok(())
+}
+
+/// 获取同意记录，按注册时间排序
+/// - 'subscriber_id'为'None'时返回所有订阅者的记录，用于导出
+#[tracing::instrument(
+    name = "Get consent records",
+    skip(pool),
+)]
+pub async fn get_consent_records(
+    pool: &PgPool,
+    subscriber_id: Option<Uuid>,
+) -> Result<Vec<ConsentRecord>, anyhow::Error> {
+    let records = sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT
+            consent_id,
+            subscriber_id,
+            source,
+            consent_text_version,
+            ip_address,
+            user_agent,
+            given_at,
+            confirmed_at,
+            confirmation_ip_address,
+            confirmation_user_agent
+        FROM subscription_consents
+        WHERE $1::uuid IS NULL OR subscriber_id = $1
+        ORDER BY given_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve consent records.")?;
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_consent_label;
+
+    #[test]
+    fn short_labels_are_accepted() {
+        for label in ["homepage", "blog/footer", "2025-10-01", "v1.2", "campaign:spring_sale"] {
+            assert_eq!(parse_consent_label(label.into(), "source"), Ok(label.to_string()));
+        }
+    }
+
+    #[test]
+    fn empty_long_or_unusual_labels_are_rejected() {
+        let too_long = "a".repeat(65);
+        for label in ["", "  ", too_long.as_str(), "home page", "<script>", "source;drop"] {
+            assert!(parse_consent_label(label.into(), "source").is_err());
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use sqlx::PgPool;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 只读副本不可用后，在这段时间内读取都直接使用主库，之后再重试副本
+const REPLICA_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 主库和可选的只读副本
+/// - 写入和需要读到刚写入数据的查询使用'primary'
+/// - 可以容忍复制延迟的只读查询(订阅者列表、仪表盘、往期简报)使用'read'
+pub struct DatabasePools {
+    primary: PgPool,
+    replica: Option<Replica>,
+}
+
+struct Replica {
+    pool: PgPool,
+    /// 最近一次连接失败后，在此之前不再尝试副本
+    unavailable_until: Mutex<Option<Instant>>,
+}
+
+impl DatabasePools {
+    pub fn new(primary: PgPool, replica: Option<PgPool>) -> Self {
+        Self {
+            primary,
+            replica: replica.map(|pool| Replica {
+                pool,
+                unavailable_until: Mutex::new(None),
+            }),
+        }
+    }
+
+    pub fn primary(&self) -> &PgPool {
+        &self.primary
+    }
+
+    /// 只读查询使用的连接池
+    /// - 未配置副本，或者副本无法连接时返回主库
+    /// - 先从副本获取一个连接确认其可用，连接随即放回连接池
+    pub async fn read(&self) -> &PgPool {
+        let replica = match &self.replica {
+            Some(replica) => replica,
+            None => return &self.primary,
+        };
+        if let Some(until) = *replica.unavailable_until.lock().unwrap() {
+            if Instant::now() < until {
+                return &self.primary;
+            }
+        }
+        match replica.pool.acquire().await {
+            Ok(_) => {
+                *replica.unavailable_until.lock().unwrap() = None;
+                &replica.pool
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "The read replica is unavailable. Reading from the primary"
+                );
+                *replica.unavailable_until.lock().unwrap() = Some(Instant::now() + REPLICA_RETRY_INTERVAL);
+                &self.primary
+            }
+        }
+    }
+}
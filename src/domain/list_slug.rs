@@ -0,0 +1,53 @@
+/// 邮件列表的标识，用于注册表单、发布表单和API
+/// - 由1~64个小写字母、数字和'-'组成
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_valid_length = (1..=64).contains(&s.len());
+        let has_valid_characters = s
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if is_valid_length && has_valid_characters {
+            Ok(Self(s))
+        } else {
+            Err(format!("{} is not a valid mailing list.", s))
+        }
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListSlug;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_letters_digits_and_hyphens_are_accepted() {
+        assert_ok!(ListSlug::parse("product-updates-2025".to_string()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+
+    #[test]
+    fn a_slug_longer_than_64_characters_is_rejected() {
+        assert_ok!(ListSlug::parse("a".repeat(64)));
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn uppercase_and_other_characters_are_rejected() {
+        for slug in ["Newsletter", "product updates", "news_letter", "é"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+}
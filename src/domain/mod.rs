@@ -1,7 +1,11 @@
 mod subscriber_name;
 mod subscriber_email;
 mod new_subscriber;
+mod list_slug;
+mod subscriber_tag;
 
 pub use subscriber_name::SubscriberName;
 pub use subscriber_email::SubscriberEmail;
-pub use new_subscriber::NewSubscriber;
\ No newline at end of file
+pub use new_subscriber::NewSubscriber;
+pub use list_slug::ListSlug;
+pub use subscriber_tag::SubscriberTag;
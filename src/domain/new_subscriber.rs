@@ -1,8 +1,9 @@
-use crate::domain::SubscriberName;
-use crate::domain::SubscriberEmail;
+use crate::domain::{ListSlug, SubscriberEmail, SubscriberName};
 
 
+/// - topics: 订阅的邮件列表，为空时订阅默认列表
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
-}
\ No newline at end of file
+    pub topics: Vec<ListSlug>,
+}
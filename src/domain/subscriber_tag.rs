@@ -0,0 +1,58 @@
+/// 订阅者的标签，用于按分群表达式选择收件人
+/// - 由1~64个小写字母、数字、'-'和'_'组成
+/// - 'and'、'or'、'not'是分群表达式的关键字，不能作为标签
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let is_valid_length = (1..=64).contains(&s.len());
+        let has_valid_characters = s
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        let is_keyword = ["and", "or", "not"].contains(&s.as_str());
+        if is_valid_length && has_valid_characters && !is_keyword {
+            Ok(Self(s))
+        } else {
+            Err(format!("{} is not a valid tag.", s))
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_letters_digits_hyphens_and_underscores_are_accepted() {
+        assert_ok!(SubscriberTag::parse("beta_tester-2025".to_string()));
+    }
+
+    #[test]
+    fn empty_and_overlong_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse("".to_string()));
+        assert_ok!(SubscriberTag::parse("a".repeat(64)));
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn uppercase_and_other_characters_are_rejected() {
+        for tag in ["Beta", "early adopter", "beta(1)", "é"] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+
+    #[test]
+    fn segment_keywords_are_rejected() {
+        for tag in ["and", "or", "not"] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+}
@@ -1,24 +1,36 @@
 use secrecy::{ExposeSecret, Secret};
 use reqwest::Client;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use crate::domain::SubscriberEmail;
+use crate::metrics::METRICS;
+use crate::telemetry::trace_context_headers;
 
 #[derive(Clone)]
 pub struct EmailClient {
     http_client: Client,
     base_url: String,
-    sender: SubscriberEmail,
+    /// 可以在运行时修改，所有克隆共享
+    reloadable: Arc<RwLock<ReloadableSettings>>,
     authorization_token: Secret<String>,
 
 }
 
+struct ReloadableSettings {
+    sender: SubscriberEmail,
+    timeout: Duration,
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
     from: &'a str,
     to: &'a str,
     subject: &'a str,
-    html_body: &'a str,
+    /// 为'None'时只发送纯文本邮件
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html_body: Option<&'a str>,
     text_body: &'a str,
 }
 
@@ -30,45 +42,85 @@ impl EmailClient {
         authorization_token: Secret<String>,
         timeout: std::time::Duration,
     ) -> Self {
+        // 超时时间在每个请求上设置，以便重新加载配置后生效
         let http_client = Client::builder()
-            .timeout(timeout)
             .build()
             .unwrap();
 
         Self { 
             http_client: http_client,
             base_url, 
-            sender,
+            reloadable: Arc::new(RwLock::new(ReloadableSettings { sender, timeout })),
             authorization_token,
         }
     }
 
+    /// 修改发件人和超时时间，对所有克隆生效，正在发送的邮件不受影响
+    pub fn reload(&self, sender: SubscriberEmail, timeout: Duration) {
+        *self.reloadable.write().unwrap() = ReloadableSettings { sender, timeout };
+    }
+
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
+    ) -> Result<(), reqwest::Error> {
+        self.send(recipient, subject, Some(html_content), text_content).await
+    }
+
+    /// 发送只包含纯文本的邮件
+    pub async fn send_text_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        text_content: &str,
+    ) -> Result<(), reqwest::Error> {
+        self.send(recipient, subject, None, text_content).await
+    }
+
+    /// 请求头中带上当前的trace context，邮件服务的链路可以关联到本次请求
+    #[tracing::instrument(name = "Send an email", skip_all)]
+    async fn send(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: Option<&str>,
+        text_content: &str,
     ) -> Result<(), reqwest::Error> {
         let url = format!("{}/email",self.base_url);
+        // 读锁不能跨越'.await'持有
+        let (sender, timeout) = {
+            let reloadable = self.reloadable.read().unwrap();
+            (reloadable.sender.clone(), reloadable.timeout)
+        };
         let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
+            from: sender.as_ref(),
             to: recipient.as_ref(),
             subject: subject,
             html_body: html_content,
             text_body: text_content,
         };
-        let _builder = self.http_client
+        let mut request = self.http_client
             .post(&url)
+            .timeout(timeout)
             .header(
                 "X-Postmark-Server-Token",
                 self.authorization_token.expose_secret()
-            )
+            );
+        for (name, value) in trace_context_headers() {
+            request = request.header(name, value);
+        }
+        let outcome = request
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
-        
+            .await
+            .and_then(|response| response.error_for_status());
+        let label = if outcome.is_ok() { "sent" } else { "failed" };
+        METRICS.emails.with_label_values(&[label]).inc();
+        outcome?;
+
         Ok(())
     }
 }
@@ -79,11 +131,16 @@ mod tests {
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
+    use opentelemetry::sdk::propagation::TraceContextPropagator;
+    use opentelemetry::sdk::trace::TracerProvider;
+    use opentelemetry::trace::TracerProvider as _;
     use secrecy::Secret;
+    use tracing::Instrument;
     use wiremock::matchers::{any, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
     use crate::domain::SubscriberEmail;
     use crate::email_client::EmailClient;
+    use crate::telemetry::{get_subscriber, GlobalTelemetryGuard};
 
     fn subject() -> String {
         Sentence(1..2).fake()
@@ -149,6 +206,57 @@ mod tests {
             .await;
     }
 
+    #[tokio::test]
+    async fn send_text_email_omits_the_html_body() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_text_email(&email(), &subject(), &content())
+            .await;
+
+        claim::assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert!(body.get("HtmlBody").is_none());
+        assert!(body.get("TextBody").is_some());
+    }
+
+    #[tokio::test]
+    async fn send_email_propagates_the_trace_context() {
+        let _global = GlobalTelemetryGuard::acquire().await;
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = TracerProvider::builder().build();
+        let (subscriber, _) = get_subscriber(
+            "test".into(),
+            "info".into(),
+            std::io::sink,
+            Some(provider.tracer("test")),
+        );
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("traceparent"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .instrument(tracing::info_span!("Publish a newsletter issue"))
+            .await;
+
+        claim::assert_ok!(outcome);
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         let mock_server = MockServer::start().await;
@@ -216,4 +324,26 @@ mod tests {
 
         claim::assert_err!(outcome);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn reloaded_settings_apply_to_every_clone() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let clone = email_client.clone();
+        let sender = email();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(500)))
+            .mount(&mock_server)
+            .await;
+
+        email_client.reload(sender.clone(), std::time::Duration::from_secs(5));
+        let outcome = clone.send_email(&email(), &subject(), &content(), &content()).await;
+
+        // 原来的超时时间是200毫秒
+        claim::assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["From"], sender.as_ref());
+    }
+}
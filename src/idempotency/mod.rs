@@ -2,4 +2,6 @@ mod key;
 pub use key::IdempotencyKey;
 mod persistence;
 pub use persistence::get_saved_response;
-pub use persistence::save_response;
\ No newline at end of file
+pub use persistence::save_response;
+pub use persistence::{save_response_snapshot, ResponseSnapshot};
+pub use persistence::prune_saved_responses;
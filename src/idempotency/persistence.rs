@@ -1,10 +1,11 @@
 use super::IdempotencyKey;
 use actix_web::HttpResponse;
 use actix_web::http::StatusCode;
-use actix_web::body::to_bytes;
+use actix_web::body::{to_bytes, MessageBody};
 use sqlx:: PgPool;
 use sqlx::postgres::PgHasArrayType;
 use uuid::Uuid;
+use crate::metrics::METRICS;
 
 #[derive(Debug, sqlx::Type)]
 #[sqlx(type_name = "header_pair")]
@@ -19,6 +20,38 @@ impl PgHasArrayType for HeaderPairRecord {
     }
 }
 
+/// 可以在后台任务中保存的响应
+/// - 'HttpResponse'不是'Send'，因此先取出状态码、响应头和完整的响应体
+pub struct ResponseSnapshot {
+    status_code: i16,
+    headers: Vec<HeaderPairRecord>,
+    body: Vec<u8>,
+}
+
+impl ResponseSnapshot {
+    /// 只支持响应体已经完整地在内存中的响应，例如空响应体或'json'
+    pub fn capture(http_response: HttpResponse) -> Result<Self, anyhow::Error> {
+        let (response_head, body) = http_response.into_parts();
+        let body = body
+            .try_into_bytes()
+            .map_err(|_| anyhow::anyhow!("A streaming response body cannot be saved."))?;
+        Ok(Self {
+            status_code: response_head.status().as_u16() as i16,
+            headers: header_records(&response_head),
+            body: body.to_vec(),
+        })
+    }
+
+    pub fn to_response(&self) -> Result<HttpResponse, anyhow::Error> {
+        let status_code = StatusCode::from_u16(self.status_code.try_into()?)?;
+        let mut response = HttpResponse::build(status_code);
+        for HeaderPairRecord { name, value } in &self.headers {
+            response.append_header((name.as_str(), value.as_slice()));
+        }
+        Ok(response.body(self.body.clone()))
+    }
+}
+
 pub async fn save_response(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
@@ -28,17 +61,24 @@ pub async fn save_response(
     let (response_head, body) = http_response.into_parts();
     // MessageBody::Error 不是'Send'+'Sync'，因此，它与'anyhow'不能很好的兼容
     let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
-    let status_code = response_head.status().as_u16() as i16;
-    let headers = {
-        let mut h = Vec::with_capacity(response_head.headers().len());
-        for (name, value) in response_head.headers().iter() {
-            let name = name.as_str().to_owned();
-            let value = value.as_bytes().to_owned();
-            h.push(HeaderPairRecord {name, value});
-        }
-        h
+    let snapshot = ResponseSnapshot {
+        status_code: response_head.status().as_u16() as i16,
+        headers: header_records(&response_head),
+        body: body.to_vec(),
     };
+    save_response_snapshot(pool, idempotency_key, user_id, &snapshot).await?;
+
+    // 使用.map_into_boxed_body方法将HttpResponse<Bytes>转换为HttpResponse<BoxBody>
+    let http_response = response_head.set_body(body).map_into_boxed_body();
+    Ok(http_response)
+}
 
+pub async fn save_response_snapshot(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    snapshot: &ResponseSnapshot,
+) -> Result<(), anyhow::Error> {
     sqlx::query_unchecked!(
         r#"
         INSERT INTO idempotency (
@@ -53,16 +93,23 @@ pub async fn save_response(
         "#,
         user_id,
         idempotency_key.as_ref(),
-        status_code,
-        headers,
-        body.as_ref()
+        snapshot.status_code,
+        &snapshot.headers,
+        &snapshot.body,
     )
     .execute(pool)
     .await?;
+    Ok(())
+}
 
-    // 使用.map_into_boxed_body方法将HttpResponse<Bytes>转换为HttpResponse<BoxBody>
-    let http_response = response_head.set_body(body).map_into_boxed_body();
-    Ok(http_response)
+fn header_records(response_head: &HttpResponse<()>) -> Vec<HeaderPairRecord> {
+    let mut h = Vec::with_capacity(response_head.headers().len());
+    for (name, value) in response_head.headers().iter() {
+        let name = name.as_str().to_owned();
+        let value = value.as_bytes().to_owned();
+        h.push(HeaderPairRecord {name, value});
+    }
+    h
 }
 
 pub async fn get_saved_response(
@@ -89,6 +136,7 @@ pub async fn get_saved_response(
     .await?;
 
     if let Some(r) = saved_response {
+        METRICS.idempotency_hits.inc();
         let status_code = StatusCode::from_u16(
             r.response_status_code.try_into()?
         )?;
@@ -100,4 +148,21 @@ pub async fn get_saved_response(
     } else {
         Ok(None)
     }
-}
\ No newline at end of file
+}
+
+/// 删除早于'older_than'保存的响应，返回删除的数量
+/// - 超过该时长后，用相同的幂等键重试会被当作新的请求
+#[tracing::instrument(name = "Prune saved idempotent responses", skip(pool))]
+pub async fn prune_saved_responses(
+    pool: &PgPool,
+    older_than: chrono::Duration,
+) -> Result<u64, anyhow::Error> {
+    let cutoff = chrono::Utc::now() - older_than;
+    let result = sqlx::query!(
+        r#"DELETE FROM idempotency WHERE created_at < $1"#,
+        cutoff,
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,362 @@
+use actix_web::dev::Payload;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{web, FromRequest, HttpRequest};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::future::{ready, Ready};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use crate::audit::{record_audit_event, AuditAction};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::preferences::{preferences_link, EmailFormat};
+use crate::request_metadata::RequestMetadata;
+use crate::segments::Segment;
+use crate::shutdown::BackgroundTasks;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::tracking::add_tracking;
+
+/// 投递邮件简报所需的依赖，作为提取器从应用状态中取出
+/// - 所有字段都是共享的句柄，可以移动到后台任务中
+#[derive(Clone)]
+pub struct DeliveryContext {
+    pub pool: PgPool,
+    pub email_client: EmailClient,
+    pub base_url: String,
+    pub hmac_secret: HmacSecret,
+    /// 关闭的宽限期结束时被触发
+    pub interrupted: CancellationToken,
+}
+
+impl FromRequest for DeliveryContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<DeliveryContext, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let context = (|| {
+            Some(DeliveryContext {
+                pool: req.app_data::<web::Data<PgPool>>()?.get_ref().clone(),
+                email_client: req.app_data::<web::Data<EmailClient>>()?.get_ref().clone(),
+                base_url: req.app_data::<web::Data<ApplicationBaseUrl>>()?.0.clone(),
+                hmac_secret: req.app_data::<web::Data<HmacSecret>>()?.get_ref().clone(),
+                interrupted: req
+                    .app_data::<web::Data<BackgroundTasks>>()?
+                    .interrupted()
+                    .clone(),
+            })
+        })();
+        ready(context.ok_or_else(|| {
+            ErrorInternalServerError("The newsletter delivery is not configured.")
+        }))
+    }
+}
+
+/// 一期邮件简报的内容
+pub struct NewsletterContent {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    /// 是否追踪这期简报的打开和点击
+    pub tracking_enabled: bool,
+}
+
+/// 一期邮件简报的收件人范围
+/// - 属于任意一个所选邮件列表，并且满足分群表达式（如果有）的已确认订阅者
+pub struct Audience {
+    pub list_ids: Vec<Uuid>,
+    pub segment: Option<Segment>,
+}
+
+/// 发布一期邮件简报的结果
+#[derive(Debug, serde::Serialize)]
+pub struct DeliveryReport {
+    pub newsletter_issue_id: Uuid,
+    pub sent: i64,
+    pub failed: i64,
+}
+
+/// 一期邮件简报的投递状态
+#[derive(Debug, serde::Serialize)]
+pub struct DeliveryStatus {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub sent: i64,
+    pub failed: i64,
+}
+
+/// 保存一期邮件简报，并发送给收件人范围内所有已确认的订阅者
+/// - 同时属于多个所选列表的订阅者只会收到一次；暂停投递的订阅者不会收到
+/// - 每封邮件末尾附上该订阅者的偏好设置链接，选择纯文本的订阅者只收到纯文本内容
+/// - 开启追踪时HTML内容中的链接经过点击跳转，并插入追踪像素；偏好设置链接不追踪
+/// - 每个订阅者的投递结果都会被记录；某个订阅者投递失败不影响其他订阅者
+/// - 发送邮件之前写入审计日志，因此不会出现已经发出却没有审计记录的一期简报
+/// - 'context.interrupted'被触发(关闭的宽限期结束)后不再发送，剩余的订阅者记为投递失败，
+///   因此投递记录总是与实际发出的邮件一致
+#[tracing::instrument(
+    name = "Publish a newsletter issue to confirmed subscribers",
+    skip(context, content, audience, metadata),
+)]
+pub async fn publish_issue(
+    context: &DeliveryContext,
+    content: &NewsletterContent,
+    published_by: Uuid,
+    audience: &Audience,
+    metadata: &RequestMetadata,
+) -> Result<DeliveryReport, anyhow::Error> {
+    let DeliveryContext {
+        pool,
+        email_client,
+        base_url,
+        hmac_secret,
+        interrupted,
+    } = context;
+    let newsletter_issue_id = insert_newsletter_issue(pool, content, published_by).await?;
+    record_audit_event(
+        pool,
+        published_by,
+        AuditAction::PublishNewsletter,
+        Some(&format!("newsletter_issue/{}", newsletter_issue_id)),
+        metadata,
+    )
+    .await?;
+    let mut report = DeliveryReport {
+        newsletter_issue_id,
+        sent: 0,
+        failed: 0,
+    };
+
+    let subscribers = get_confirmed_subscribers(pool, audience).await?;
+    for subscriber in subscribers {
+        match subscriber {
+            Ok(subscriber) if interrupted.is_cancelled() => {
+                report.failed += 1;
+                let error_message = "The delivery was interrupted by a shutdown.".to_string();
+                record_delivery(pool, newsletter_issue_id, subscriber.id, Some(error_message))
+                    .await?;
+            }
+            Ok(subscriber) => {
+                let link = preferences_link(base_url, hmac_secret, subscriber.id);
+                let text_content = format!(
+                    "{}\n\n--\nManage your subscription: {}",
+                    content.text_content, link
+                );
+                let outcome = match subscriber.email_format {
+                    EmailFormat::Html => {
+                        let html_content = if content.tracking_enabled {
+                            add_tracking(
+                                &content.html_content,
+                                base_url,
+                                hmac_secret,
+                                newsletter_issue_id,
+                                subscriber.id,
+                            )
+                        } else {
+                            content.html_content.clone()
+                        };
+                        let html_content = format!(
+                            "{}<hr /><p><a href=\"{}\">Manage your subscription</a></p>",
+                            html_content, link
+                        );
+                        email_client
+                            .send_email(&subscriber.email, &content.title, &html_content, &text_content)
+                            .await
+                    }
+                    EmailFormat::Text => {
+                        email_client
+                            .send_text_email(&subscriber.email, &content.title, &text_content)
+                            .await
+                    }
+                }
+                .with_context(|| {
+                    format!("Failed to send newsletter issue to {}", subscriber.email)
+                });
+                let error_message = match &outcome {
+                    Ok(()) => {
+                        report.sent += 1;
+                        None
+                    }
+                    Err(error) => {
+                        tracing::error!(
+                            error.cause_chain = ?error,
+                            error.message = %error,
+                            "Failed to deliver a newsletter issue to a confirmed subscriber",
+                        );
+                        report.failed += 1;
+                        Some(format!("{:#}", error))
+                    }
+                };
+                record_delivery(pool, newsletter_issue_id, subscriber.id, error_message).await?;
+            }
+            Err(error) => {
+                tracing::warn!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid",
+                );
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_newsletter_issue(
+    pool: &PgPool,
+    content: &NewsletterContent,
+    published_by: Uuid,
+) -> Result<Uuid, anyhow::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_by,
+            published_at,
+            tracking_enabled
+        )
+        VALUES ($1, $2, $3, $4, $5, now(), $6)
+        "#,
+        newsletter_issue_id,
+        content.title,
+        content.text_content,
+        content.html_content,
+        published_by,
+        content.tracking_enabled,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a newsletter issue.")?;
+    Ok(newsletter_issue_id)
+}
+
+/// 记录对某个订阅者的投递结果，'error_message'为'None'表示投递成功
+#[tracing::instrument(skip(pool, error_message))]
+async fn record_delivery(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    error_message: Option<String>,
+) -> Result<(), anyhow::Error> {
+    let status = if error_message.is_none() { "sent" } else { "failed" };
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id,
+            subscriber_id,
+            status,
+            error_message,
+            attempted_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        newsletter_issue_id,
+        subscriber_id,
+        status,
+        error_message,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the delivery of a newsletter issue.")?;
+    Ok(())
+}
+
+/// 查询一期邮件简报的投递状态，不存在时返回'None'
+#[tracing::instrument(name = "Get the delivery status of a newsletter issue", skip(pool))]
+pub async fn get_delivery_status(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<DeliveryStatus>, anyhow::Error> {
+    let status = sqlx::query_as!(
+        DeliveryStatus,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            COUNT(d.subscriber_id) FILTER (WHERE d.status = 'sent') AS "sent!",
+            COUNT(d.subscriber_id) FILTER (WHERE d.status = 'failed') AS "failed!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_deliveries d ON d.newsletter_issue_id = i.newsletter_issue_id
+        WHERE i.newsletter_issue_id = $1
+        GROUP BY i.newsletter_issue_id
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the delivery status of a newsletter issue.")?;
+    Ok(status)
+}
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    email: SubscriberEmail,
+    email_format: EmailFormat,
+}
+
+#[derive(sqlx::FromRow)]
+struct ConfirmedSubscriberRow {
+    id: Uuid,
+    email: String,
+    email_format: String,
+}
+
+/// 追加收件人的筛选条件，'subscriptions'表的别名为's'
+/// - 用'EXISTS'判断列表成员关系，因此每个订阅者只出现一次
+fn push_audience_filter(builder: &mut QueryBuilder<'_, Postgres>, audience: &Audience) {
+    builder
+        .push(
+            " FROM subscriptions s \
+            WHERE s.status = 'confirmed' \
+            AND (s.paused_until IS NULL OR s.paused_until <= now()) \
+            AND EXISTS (SELECT 1 FROM list_memberships m WHERE m.subscriber_id = s.id AND m.list_id = ANY(",
+        )
+        .push_bind(audience.list_ids.clone())
+        .push("))");
+    if let Some(segment) = &audience.segment {
+        builder.push(" AND ");
+        segment.push_sql(builder);
+    }
+}
+
+/// 统计收件人范围内的订阅者数量，用于发布前预览
+#[tracing::instrument(name = "Count the recipients of a newsletter issue", skip(pool, audience))]
+pub async fn count_recipients(pool: &PgPool, audience: &Audience) -> Result<i64, anyhow::Error> {
+    let mut builder = QueryBuilder::new("SELECT COUNT(*)");
+    push_audience_filter(&mut builder, audience);
+    let (count,): (i64,) = builder
+        .build_query_as()
+        .fetch_one(pool)
+        .await
+        .context("Failed to count the recipients of a newsletter issue.")?;
+    Ok(count)
+}
+
+/// 从Postgres数据库中获取收件人范围内已确认且没有暂停投递的订阅者，每个订阅者只出现一次
+/// - 查询根据邮件列表和分群表达式动态生成，所有值都通过绑定参数传入
+#[tracing::instrument(name = "Get confirmed subscribers", skip(pool, audience))]
+async fn get_confirmed_subscribers(
+    pool: &PgPool,
+    audience: &Audience,
+) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
+    let mut builder = QueryBuilder::new("SELECT s.id, s.email, s.email_format");
+    push_audience_filter(&mut builder, audience);
+    let confirmed_subscribers = builder
+        .build_query_as::<ConfirmedSubscriberRow>()
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|r| -> Result<ConfirmedSubscriber, anyhow::Error> {
+            let email = SubscriberEmail::parse(r.email).map_err(|e| anyhow::anyhow!(e))?;
+            let email_format =
+                EmailFormat::try_from(r.email_format).map_err(|e| anyhow::anyhow!(e))?;
+            Ok(ConfirmedSubscriber { id: r.id, email, email_format })
+        })
+        .collect();
+    Ok(confirmed_subscribers)
+}
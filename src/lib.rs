@@ -9,3 +9,21 @@ pub mod authentication;
 pub mod session_state;
 pub mod utils;
 pub mod idempotency;
+pub mod request_metadata;
+pub mod security_headers;
+pub mod issue_delivery;
+pub mod audit;
+pub mod consent;
+pub mod subscribers;
+pub mod signed_token;
+pub mod subscriber_data;
+pub mod mailing_lists;
+pub mod preferences;
+pub mod segments;
+pub mod tracking;
+pub mod metrics;
+pub mod migrations;
+pub mod cli;
+pub mod shutdown;
+pub mod reload;
+pub mod database;
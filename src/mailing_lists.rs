@@ -0,0 +1,228 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::domain::ListSlug;
+
+/// 一个邮件列表(主题)及其已确认的订阅者数量
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub description: String,
+    pub is_default: bool,
+    pub created_at: DateTime<Utc>,
+    pub confirmed_subscribers: i64,
+}
+
+/// 解析注册表单、发布表单或API中选择的列表时的错误
+#[derive(thiserror::Error)]
+pub enum ListSelectionError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ListSelectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::routes::error_chain_fmt(self, f)
+    }
+}
+
+/// 解析表单或API提交的列表标识，重复的标识只保留一个
+pub fn parse_list_slugs(slugs: Vec<String>) -> Result<Vec<ListSlug>, String> {
+    let mut parsed: Vec<ListSlug> = Vec::with_capacity(slugs.len());
+    for slug in slugs {
+        let slug = ListSlug::parse(slug.trim().to_string())?;
+        if !parsed.contains(&slug) {
+            parsed.push(slug);
+        }
+    }
+    Ok(parsed)
+}
+
+/// 列出所有邮件列表，默认列表在前
+#[tracing::instrument(name = "List mailing lists", skip(pool))]
+pub async fn list_mailing_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT
+            l.list_id,
+            l.slug,
+            l.name,
+            l.description,
+            l.is_default,
+            l.created_at,
+            COUNT(s.id) AS "confirmed_subscribers!"
+        FROM mailing_lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id
+        LEFT JOIN subscriptions s ON s.id = m.subscriber_id AND s.status = 'confirmed'
+        GROUP BY l.list_id
+        ORDER BY l.is_default DESC, l.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the mailing lists.")?;
+    Ok(lists)
+}
+
+/// 创建一个邮件列表，标识已被占用时返回'None'
+#[tracing::instrument(name = "Create a mailing list", skip(pool, name, description))]
+pub async fn create_mailing_list(
+    pool: &PgPool,
+    slug: &ListSlug,
+    name: &str,
+    description: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let list_id = sqlx::query!(
+        r#"
+        INSERT INTO mailing_lists (list_id, slug, name, description, is_default, created_at)
+        VALUES ($1, $2, $3, $4, false, now())
+        ON CONFLICT (slug) DO NOTHING
+        RETURNING list_id
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        name,
+        description,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to create a mailing list.")?
+    .map(|r| r.list_id);
+    Ok(list_id)
+}
+
+/// 删除一个邮件列表及其成员关系，返回被删除列表的标识
+/// - 默认列表不能删除，此时与列表不存在一样返回'None'
+#[tracing::instrument(name = "Delete a mailing list", skip(pool))]
+pub async fn delete_mailing_list(
+    pool: &PgPool,
+    list_id: Uuid,
+) -> Result<Option<String>, anyhow::Error> {
+    let slug = sqlx::query!(
+        r#"
+        DELETE FROM mailing_lists
+        WHERE list_id = $1 AND NOT is_default
+        RETURNING slug
+        "#,
+        list_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to delete a mailing list.")?
+    .map(|r| r.slug);
+    Ok(slug)
+}
+
+/// 将列表标识转换为列表ID
+/// - 没有选择任何列表时使用默认列表
+/// - 任何一个标识不存在都会返回'ListSelectionError::Invalid'
+#[tracing::instrument(name = "Resolve mailing lists", skip(pool, slugs))]
+pub async fn resolve_mailing_lists(
+    pool: &PgPool,
+    slugs: &[ListSlug],
+) -> Result<Vec<Uuid>, ListSelectionError> {
+    if slugs.is_empty() {
+        let default_list = sqlx::query!(
+            r#"SELECT list_id FROM mailing_lists WHERE is_default"#,
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the default mailing list.")?
+        .context("There is no default mailing list.")?;
+        return Ok(vec![default_list.list_id]);
+    }
+
+    let slugs: Vec<String> = slugs.iter().map(|s| s.as_ref().to_owned()).collect();
+    let rows = sqlx::query!(
+        r#"SELECT list_id, slug FROM mailing_lists WHERE slug = ANY($1)"#,
+        &slugs[..],
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the selected mailing lists.")?;
+    if let Some(unknown) = slugs.iter().find(|s| !rows.iter().any(|r| &r.slug == *s)) {
+        return Err(ListSelectionError::Invalid(format!(
+            "{} is not a known mailing list.",
+            unknown
+        )));
+    }
+    Ok(rows.into_iter().map(|r| r.list_id).collect())
+}
+
+/// 将订阅者加入邮件列表，已经是成员的列表保持不变
+#[tracing::instrument(name = "Add a subscriber to mailing lists", skip(transaction))]
+pub async fn add_list_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, joined_at)
+        SELECT t.list_id, $2, now() FROM UNNEST($1::uuid[]) AS t(list_id)
+        ON CONFLICT (list_id, subscriber_id) DO NOTHING
+        "#,
+        list_ids,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to add a subscriber to mailing lists.")?;
+    Ok(())
+}
+
+/// 查询订阅者所在列表的标识，按标识排序
+#[tracing::instrument(name = "Get the mailing lists of a subscriber", skip(pool))]
+pub async fn get_subscriber_lists(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    let slugs = sqlx::query!(
+        r#"
+        SELECT l.slug
+        FROM list_memberships m
+        JOIN mailing_lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the mailing lists of a subscriber.")?
+    .into_iter()
+    .map(|r| r.slug)
+    .collect();
+    Ok(slugs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_list_slugs;
+    use claim::assert_err;
+
+    #[test]
+    fn duplicate_slugs_are_collapsed() {
+        let slugs = parse_list_slugs(vec![
+            "newsletter".to_string(),
+            " product-updates ".to_string(),
+            "newsletter".to_string(),
+        ])
+        .unwrap();
+        let slugs: Vec<&str> = slugs.iter().map(|s| s.as_ref()).collect();
+        assert_eq!(slugs, ["newsletter", "product-updates"]);
+    }
+
+    #[test]
+    fn an_invalid_slug_rejects_the_whole_selection() {
+        assert_err!(parse_list_slugs(vec![
+            "newsletter".to_string(),
+            "Not A Slug".to_string(),
+        ]));
+    }
+}
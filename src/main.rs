@@ -1,7 +1,12 @@
 //! src/lib.rs
-use zero2prod::configuration::get_configuration;
+use zero2prod::configuration::{load_configuration, InvalidConfiguration, Settings};
 use zero2prod::startup::Application;
-use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use anyhow::Context;
+use clap::Parser;
+use zero2prod::cli::{run_command, Cli, Command};
+use zero2prod::reload::{reload_on_sighup, ConfigReloader};
+use zero2prod::shutdown::shutdown_signal;
+use zero2prod::telemetry::{get_subscriber, init_otlp_tracer, init_subscriber};
 
 /// tracing crate 提供了核心APi与抽象，其中提供了 Subsciber trait；
 ///     - tracing中的Subscriber trait 与log的Log trait 类似；
@@ -15,12 +20,67 @@ use zero2prod::telemetry::{get_subscriber, init_subscriber};
 // 现在是anyhow::Result而不是std::io::Error
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
-    init_subscriber(subscriber);
+    let cli = Cli::parse();
+    // 'check-config'在初始化日志和链路追踪之前执行，不会启动导出器；
+    // 无法读取或解析的配置同样作为问题报告
+    if let Some(Command::CheckConfig) = cli.command {
+        let configuration: Settings = load_configuration()
+            .and_then(|c| c.try_deserialize())
+            .map_err(InvalidConfiguration::from)?;
+        return run_command(Command::CheckConfig, &configuration).await;
+    }
+    let raw_configuration = load_configuration().context("Failed to read configuration.")?;
+    let configuration: Settings = raw_configuration
+        .clone()
+        .try_deserialize()
+        .context("Failed to read configuration.")?;
 
-    let configuration = get_configuration().expect("Failed to read configuration.");
+    // 配置了OTLP时，跨度同时导出到链路追踪收集器
+    let otlp_tracer = configuration
+        .otlp
+        .as_ref()
+        .map(|otlp| init_otlp_tracer("zero2prod", otlp))
+        .transpose()
+        .context("Failed to set up the OTLP trace exporter.")?;
 
-    let application = Application::build(configuration).await.unwrap();
-    application.run_until_stopped().await?;
+    match cli.command {
+        None | Some(Command::Serve) => {
+            let (subscriber, log_filter) = get_subscriber(
+                "zero2prod".into(),
+                configuration.log_filter.clone(),
+                std::io::stdout,
+                otlp_tracer,
+            );
+            init_subscriber(subscriber);
+            let application = Application::build(configuration).await?;
+            // 收到SIGHUP时重新加载日志过滤规则和邮件客户端的设置
+            let reloader = ConfigReloader::new(
+                &raw_configuration,
+                application.email_client(),
+                Some(log_filter),
+            );
+            tokio::spawn(reload_on_sighup(reloader));
+            let shutdown = application.shutdown_trigger();
+            tokio::spawn(async move {
+                shutdown_signal().await;
+                tracing::info!("Received a shutdown signal");
+                shutdown.cancel();
+            });
+            application.run_until_stopped().await?;
+        }
+        // 管理命令的结果输出到标准输出，日志输出到标准错误
+        Some(command) => {
+            let (subscriber, _) = get_subscriber(
+                "zero2prod".into(),
+                configuration.log_filter.clone(),
+                std::io::stderr,
+                otlp_tracer,
+            );
+            init_subscriber(subscriber);
+            run_command(command, &configuration).await?;
+        }
+    }
+    // 导出尚未发送的跨度，该调用会阻塞当前线程
+    tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider).await?;
     Ok(())
 }
\ No newline at end of file
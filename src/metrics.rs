@@ -0,0 +1,195 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{ContentType, AUTHORIZATION};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use crate::utils::e500;
+
+/// 应用程序的Prometheus指标
+/// - 所有指标注册在同一个'Registry'中，由'/metrics'以文本格式导出
+/// - 带标签的计数器在创建时先初始化常用的标签值，尚未发生的事件也会导出为0
+pub struct Metrics {
+    registry: Registry,
+    /// 按方法、路由模板和状态码统计的请求数
+    pub http_requests: IntCounterVec,
+    /// 按方法和路由模板统计的请求耗时
+    pub http_request_duration: HistogramVec,
+    /// 连接池中空闲('idle')和使用中('in_use')的连接数，在抓取时更新
+    pub db_pool_connections: IntGaugeVec,
+    /// 发送邮件的结果：'sent'或'failed'
+    pub emails: IntCounterVec,
+    /// 新的订阅
+    pub subscriptions: IntCounter,
+    /// 确认的订阅
+    pub confirmations: IntCounter,
+    /// 密码登录的结果：'success'或'failure'
+    pub logins: IntCounterVec,
+    /// 直接返回已保存响应的幂等请求
+    pub idempotency_hits: IntCounter,
+}
+
+pub static METRICS: Lazy<Metrics> =
+    Lazy::new(|| Metrics::new().expect("Failed to register the Prometheus metrics."));
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("zero2prod".into()), None)?;
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by method, route and status."),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latencies by method and route.",
+            ),
+            &["method", "route"],
+        )?;
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Postgres pool connections by state."),
+            &["state"],
+        )?;
+        let emails = IntCounterVec::new(
+            Opts::new("emails_total", "Emails handed to the email provider by outcome."),
+            &["outcome"],
+        )?;
+        let subscriptions = IntCounter::new("subscriptions_total", "New subscriptions.")?;
+        let confirmations =
+            IntCounter::new("confirmations_total", "Confirmed subscriptions.")?;
+        let logins = IntCounterVec::new(
+            Opts::new("logins_total", "Password logins by outcome."),
+            &["outcome"],
+        )?;
+        let idempotency_hits = IntCounter::new(
+            "idempotency_hits_total",
+            "Requests answered with a saved idempotent response.",
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(emails.clone()))?;
+        registry.register(Box::new(subscriptions.clone()))?;
+        registry.register(Box::new(confirmations.clone()))?;
+        registry.register(Box::new(logins.clone()))?;
+        registry.register(Box::new(idempotency_hits.clone()))?;
+
+        for outcome in ["sent", "failed"] {
+            emails.with_label_values(&[outcome]);
+        }
+        for outcome in ["success", "failure"] {
+            logins.with_label_values(&[outcome]);
+        }
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_pool_connections,
+            emails,
+            subscriptions,
+            confirmations,
+            logins,
+            idempotency_hits,
+        })
+    }
+
+    /// 更新连接池的指标，并以Prometheus文本格式导出所有指标
+    pub fn render(&self, pool: &PgPool) -> Result<String, anyhow::Error> {
+        let size = pool.size() as i64;
+        let idle = pool.num_idle() as i64;
+        self.db_pool_connections.with_label_values(&["idle"]).set(idle);
+        self.db_pool_connections.with_label_values(&["in_use"]).set(size - idle);
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .context("Failed to encode the Prometheus metrics.")
+    }
+}
+
+/// 记录每个请求的数量和耗时
+/// - 路由标签使用路由模板(例如'/admin/subscribers/{subscriber_id}')而不是实际路径，避免标签数量无限增长
+pub async fn record_http_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let method = req.method().to_string();
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".into());
+    let timer = METRICS
+        .http_request_duration
+        .with_label_values(&[&method, &route])
+        .start_timer();
+    let response = next.call(req).await;
+    timer.observe_duration();
+    let status = match &response {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    METRICS
+        .http_requests
+        .with_label_values(&[&method, &route, status.as_str()])
+        .inc();
+    response
+}
+
+/// 应用端口上的'/metrics'所需的令牌
+pub struct MetricsToken(pub Secret<String>);
+
+/// 以Prometheus文本格式导出指标
+/// - 注册了'MetricsToken'时(即在应用端口上提供)，需要'Authorization: Bearer <token>'
+pub async fn metrics_endpoint(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    token: Option<web::Data<MetricsToken>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(token) = token {
+        if !is_authorized(&req, &token) {
+            return Ok(HttpResponse::Unauthorized().finish());
+        }
+    }
+    let body = METRICS.render(&pool).map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(body))
+}
+
+/// 比较令牌的SHA-256哈希值，比较耗时与令牌的公共前缀长度无关
+fn is_authorized(req: &HttpRequest, token: &MetricsToken) -> bool {
+    let provided = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    match provided {
+        Some(provided) => {
+            Sha256::digest(provided.as_bytes())
+                == Sha256::digest(token.0.expose_secret().as_bytes())
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+
+    #[test]
+    fn all_metrics_can_be_registered_together() {
+        let metrics = Metrics::new().unwrap();
+        let names: Vec<String> = metrics
+            .registry
+            .gather()
+            .iter()
+            .map(|family| family.get_name().to_string())
+            .collect();
+        assert!(names.contains(&"zero2prod_emails_total".to_string()));
+        assert!(names.contains(&"zero2prod_logins_total".to_string()));
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::Context;
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+
+/// 编译时嵌入二进制文件的数据库迁移
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// 启动时如何处理尚未执行的迁移
+/// - apply: 自动执行
+/// - verify: 存在未执行的迁移时拒绝启动，需要先运行'zero2prod migrate'
+/// - ignore: 不检查
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum MigrationMode {
+    Apply,
+    Verify,
+    Ignore,
+}
+
+impl MigrationMode {
+    pub const ALL: [MigrationMode; 3] = [Self::Apply, Self::Verify, Self::Ignore];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationMode::Apply => "apply",
+            MigrationMode::Verify => "verify",
+            MigrationMode::Ignore => "ignore",
+        }
+    }
+}
+
+impl TryFrom<String> for MigrationMode {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.as_str() == s.to_lowercase())
+            .ok_or_else(|| {
+                format!(
+                    "{} is not a supported migration mode. Use 'apply', 'verify' or 'ignore'.",
+                    s
+                )
+            })
+    }
+}
+
+/// 按配置的模式处理尚未执行的迁移
+#[tracing::instrument(name = "Check database migrations", skip(pool))]
+pub async fn run_startup_migrations(pool: &PgPool, mode: MigrationMode) -> Result<(), anyhow::Error> {
+    match mode {
+        MigrationMode::Apply => {
+            let applied = apply_migrations(pool).await?;
+            if !applied.is_empty() {
+                tracing::info!(?applied, "Applied pending migrations");
+            }
+        }
+        MigrationMode::Verify => {
+            let pending = pending_migrations(pool).await?;
+            if !pending.is_empty() {
+                anyhow::bail!(
+                    "The database has pending migrations: {:?}. Run `zero2prod migrate` first.",
+                    pending
+                );
+            }
+        }
+        MigrationMode::Ignore => {}
+    }
+    Ok(())
+}
+
+/// 执行所有尚未执行的迁移，返回本次执行的迁移版本
+/// - 已执行的迁移在嵌入后被修改过时返回错误
+pub async fn apply_migrations(pool: &PgPool) -> Result<Vec<i64>, anyhow::Error> {
+    let pending = pending_migrations(pool).await?;
+    MIGRATOR
+        .run(pool)
+        .await
+        .context("Failed to apply the database migrations.")?;
+    Ok(pending)
+}
+
+/// 返回尚未在数据库中成功执行的迁移版本，按版本号排序
+/// - 只读取'_sqlx_migrations'，不会创建该表；该表不存在时所有迁移都未执行
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<i64>, anyhow::Error> {
+    let table_exists: bool =
+        sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(pool)
+            .await
+            .context("Failed to check for the migrations table.")?;
+    let applied: Vec<i64> = if table_exists {
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+            .context("Failed to read the applied migrations.")?
+    } else {
+        Vec::new()
+    };
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| m.version)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MigrationMode;
+
+    #[test]
+    fn migration_modes_roundtrip() {
+        for mode in MigrationMode::ALL {
+            assert_eq!(MigrationMode::try_from(mode.as_str().to_string()), Ok(mode));
+        }
+        assert_eq!(
+            MigrationMode::try_from("APPLY".to_string()),
+            Ok(MigrationMode::Apply)
+        );
+        assert!(MigrationMode::try_from("skip".to_string()).is_err());
+    }
+
+    #[test]
+    fn every_migration_is_embedded() {
+        let files = std::fs::read_dir("./migrations")
+            .unwrap()
+            .filter(|entry| {
+                entry.as_ref().unwrap().path().extension() == Some("sql".as_ref())
+            })
+            .count();
+        assert_eq!(super::MIGRATOR.iter().count(), files);
+    }
+}
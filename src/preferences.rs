@@ -0,0 +1,224 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domain::SubscriberName;
+use crate::mailing_lists::add_list_memberships;
+use crate::signed_token::{sign_token, verify_token};
+use crate::startup::HmacSecret;
+
+/// 偏好设置链接的令牌用途
+const PREFERENCES_TOKEN_PURPOSE: &str = "subscriber_preferences";
+
+/// 暂停投递时可以选择的天数
+pub const PAUSE_OPTIONS_IN_DAYS: [i64; 3] = [7, 30, 90];
+
+/// 订阅者接收的邮件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailFormat {
+    /// 同时包含HTML和纯文本，由邮件客户端选择
+    Html,
+    /// 只包含纯文本
+    Text,
+}
+
+impl EmailFormat {
+    pub const ALL: [EmailFormat; 2] = [EmailFormat::Html, EmailFormat::Text];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailFormat::Html => "html",
+            EmailFormat::Text => "text",
+        }
+    }
+}
+
+impl TryFrom<String> for EmailFormat {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        EmailFormat::ALL
+            .into_iter()
+            .find(|format| format.as_str() == s)
+            .ok_or_else(|| format!("{} is not a known email format.", s))
+    }
+}
+
+/// 生成偏好设置链接中的令牌
+/// - 链接随每期简报发出，需要长期有效，因此令牌不过期；订阅者退订后链接失效
+pub fn preferences_token(secret: &HmacSecret, subscriber_id: Uuid) -> String {
+    sign_token(secret, PREFERENCES_TOKEN_PURPOSE, &subscriber_id.to_string())
+}
+
+/// 校验偏好设置链接中的令牌，返回订阅者ID
+pub fn verify_preferences_token(secret: &HmacSecret, token: &str) -> Result<Uuid, anyhow::Error> {
+    let payload = verify_token(secret, PREFERENCES_TOKEN_PURPOSE, token)?;
+    Uuid::parse_str(&payload).context("The token payload is not a subscriber id.")
+}
+
+/// 某个订阅者的偏好设置链接
+pub fn preferences_link(base_url: &str, secret: &HmacSecret, subscriber_id: Uuid) -> String {
+    format!(
+        "{}/subscriptions/preferences?token={}",
+        base_url,
+        urlencoding::encode(&preferences_token(secret, subscriber_id)),
+    )
+}
+
+/// 偏好设置中对暂停投递的修改
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseChange {
+    /// 保持当前的暂停状态
+    Keep,
+    /// 立即恢复投递
+    Resume,
+    /// 从现在起暂停投递若干天
+    PauseForDays(i64),
+}
+
+/// 订阅者提交的偏好设置
+pub struct PreferencesUpdate {
+    pub name: SubscriberName,
+    pub email_format: EmailFormat,
+    pub list_ids: Vec<Uuid>,
+    pub pause: PauseChange,
+}
+
+/// 保存订阅者的偏好设置，并将其邮件列表替换为所选的列表
+#[tracing::instrument(name = "Update subscriber preferences", skip(pool, update))]
+pub async fn update_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    update: &PreferencesUpdate,
+) -> Result<(), anyhow::Error> {
+    // 第一个值表示是否修改暂停状态
+    let (change_pause, paused_until): (bool, Option<DateTime<Utc>>) = match update.pause {
+        PauseChange::Keep => (false, None),
+        PauseChange::Resume => (true, None),
+        PauseChange::PauseForDays(days) => (true, Some(Utc::now() + chrono::Duration::days(days))),
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            name = $2,
+            email_format = $3,
+            paused_until = CASE WHEN $4 THEN $5 ELSE paused_until END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        update.name.as_ref(),
+        update.email_format.as_str(),
+        change_pause,
+        paused_until,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the preferences of a subscriber.")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM list_memberships
+        WHERE subscriber_id = $1 AND NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        &update.list_ids[..],
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove a subscriber from mailing lists.")?;
+    add_list_memberships(&mut transaction, subscriber_id, &update.list_ids).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscriber preferences.")?;
+    Ok(())
+}
+
+/// 退订：订阅者不再收到任何简报，未使用的确认令牌作废
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(pool))]
+pub async fn unsubscribe(pool: &PgPool, subscriber_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to unsubscribe a subscriber.")?;
+    // 否则点击旧的确认链接会重新订阅
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the subscription tokens of a subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+    Ok(())
+}
+
+/// 保存修改邮箱的确认令牌，确认之前订阅者的邮箱保持不变
+#[tracing::instrument(name = "Store an email change token", skip(pool, subscription_token, new_email))]
+pub async fn store_email_change_token(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+    new_email: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, new_email)
+        VALUES ($1, $2, $3)
+        "#,
+        subscription_token,
+        subscriber_id,
+        new_email,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store an email change token.")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preferences_token, verify_preferences_token, EmailFormat};
+    use crate::signed_token::sign_token;
+    use crate::startup::HmacSecret;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("key".to_string()))
+    }
+
+    #[test]
+    fn email_formats_round_trip() {
+        for format in EmailFormat::ALL {
+            assert_eq!(EmailFormat::try_from(format.as_str().to_string()), Ok(format));
+        }
+        assert!(EmailFormat::try_from("pdf".to_string()).is_err());
+    }
+
+    #[test]
+    fn a_preferences_token_carries_the_subscriber_id() {
+        let subscriber_id = Uuid::new_v4();
+        let token = preferences_token(&secret(), subscriber_id);
+        assert_eq!(verify_preferences_token(&secret(), &token).unwrap(), subscriber_id);
+    }
+
+    #[test]
+    fn a_token_for_another_purpose_is_rejected() {
+        let token = sign_token(&secret(), "subscriber_data_export", &Uuid::new_v4().to_string());
+        assert!(verify_preferences_token(&secret(), &token).is_err());
+    }
+}
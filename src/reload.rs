@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+use config::{Config, Value, ValueKind};
+use crate::configuration::{load_configuration, Settings};
+use crate::email_client::EmailClient;
+use crate::telemetry::LogFilter;
+
+/// 可以在运行时重新加载的配置项，其他配置项的修改需要重启才能生效
+pub const RELOADABLE_KEYS: [&str; 3] = [
+    "log_filter",
+    "email_client.sender_email",
+    "email_client.timeout_milliseconds",
+];
+
+/// 一次重新加载的结果，只包含配置项的名称，不包含值
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReloadOutcome {
+    /// 已经生效的修改
+    pub applied: Vec<String>,
+    /// 需要重启才能生效而被忽略的修改
+    pub rejected: Vec<String>,
+}
+
+/// 重新读取配置文件和环境变量，应用可以重新加载的修改
+/// - 修改后的配置必须能通过校验，否则整个修改被拒绝
+/// - 被拒绝的修改不会被记住，之后每次重新加载都会再次报告，直到重启或改回原值
+pub struct ConfigReloader {
+    /// 当前生效的配置值，键为'email_client.sender_email'这样的路径
+    current: BTreeMap<String, ValueKind>,
+    email_client: EmailClient,
+    log_filter: Option<LogFilter>,
+}
+
+impl ConfigReloader {
+    /// 'configuration'是启动时使用的配置；'log_filter'为'None'时不修改日志过滤规则
+    pub fn new(configuration: &Config, email_client: EmailClient, log_filter: Option<LogFilter>) -> Self {
+        Self {
+            current: flatten(configuration),
+            email_client,
+            log_filter,
+        }
+    }
+
+    #[tracing::instrument(name = "Reload the configuration", skip(self))]
+    pub fn reload(&mut self) -> Result<ReloadOutcome, anyhow::Error> {
+        self.apply(load_configuration()?)
+    }
+
+    pub fn apply(&mut self, configuration: Config) -> Result<ReloadOutcome, anyhow::Error> {
+        let values = flatten(&configuration);
+        let settings: Settings = configuration.try_deserialize()?;
+        settings.validate()?;
+
+        let mut outcome = ReloadOutcome::default();
+        for key in self.current.keys().chain(values.keys()) {
+            if self.current.get(key) == values.get(key)
+                || outcome.applied.contains(key)
+                || outcome.rejected.contains(key)
+            {
+                continue;
+            }
+            if RELOADABLE_KEYS.contains(&key.as_str()) {
+                outcome.applied.push(key.clone());
+            } else {
+                outcome.rejected.push(key.clone());
+            }
+        }
+
+        if outcome.applied.iter().any(|key| key == "log_filter") {
+            if let Some(log_filter) = &self.log_filter {
+                log_filter.reload(&settings.log_filter)?;
+            }
+        }
+        if outcome.applied.iter().any(|key| key.starts_with("email_client.")) {
+            // 已经通过校验，发件人一定有效
+            let sender = settings.email_client.sender().map_err(anyhow::Error::msg)?;
+            self.email_client.reload(sender, settings.email_client.timeout());
+        }
+        for key in &outcome.applied {
+            match values.get(key) {
+                Some(value) => self.current.insert(key.clone(), value.clone()),
+                None => self.current.remove(key),
+            };
+        }
+
+        for key in &outcome.rejected {
+            tracing::warn!(key = %key, "Ignoring a configuration change that requires a restart");
+        }
+        if !outcome.applied.is_empty() {
+            tracing::info!(keys = ?outcome.applied, "Applied configuration changes");
+        }
+        Ok(outcome)
+    }
+}
+
+/// 收到SIGHUP时重新加载配置；失败时保留当前配置
+/// - 只支持unix，其他平台上不会重新加载
+pub async fn reload_on_sighup(reloader: ConfigReloader) -> Result<(), std::io::Error> {
+    #[cfg(unix)]
+    {
+        let mut reloader = reloader;
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        while hangup.recv().await.is_some() {
+            if let Err(e) = reloader.reload() {
+                tracing::error!(error = format!("{:#}", e), "Failed to reload the configuration");
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    drop(reloader);
+    Ok(())
+}
+
+/// 把嵌套的配置展开为'a.b.c' => 值
+fn flatten(configuration: &Config) -> BTreeMap<String, ValueKind> {
+    fn visit(prefix: &str, value: &Value, values: &mut BTreeMap<String, ValueKind>) {
+        match &value.kind {
+            ValueKind::Table(table) => {
+                for (key, value) in table {
+                    let key = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    visit(&key, value, values);
+                }
+            }
+            kind => {
+                values.insert(prefix.to_string(), kind.clone());
+            }
+        }
+    }
+    let mut values = BTreeMap::new();
+    visit("", &configuration.cache, &mut values);
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{Config, File};
+    use secrecy::Secret;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailClient;
+    use super::{ConfigReloader, ReloadOutcome};
+
+    /// 本地环境的配置，加上'overrides'
+    fn configuration(overrides: &[(&str, &str)]) -> Config {
+        let mut builder = Config::builder()
+            .set_override("environment", "local")
+            .unwrap()
+            .add_source(File::with_name("configuration/base"))
+            .add_source(File::with_name("configuration/local"));
+        for (key, value) in overrides {
+            builder = builder.set_override(*key, *value).unwrap();
+        }
+        builder.build().unwrap()
+    }
+
+    fn reloader() -> ConfigReloader {
+        let email_client = EmailClient::new(
+            "http://localhost".into(),
+            SubscriberEmail::parse("sender@example.com".into()).unwrap(),
+            Secret::new("token".into()),
+            std::time::Duration::from_secs(1),
+        );
+        ConfigReloader::new(&configuration(&[]), email_client, None)
+    }
+
+    #[test]
+    fn an_unchanged_configuration_changes_nothing() {
+        let mut reloader = reloader();
+
+        let outcome = reloader.apply(configuration(&[])).unwrap();
+
+        assert_eq!(outcome, ReloadOutcome::default());
+    }
+
+    #[test]
+    fn reloadable_changes_are_applied() {
+        let mut reloader = reloader();
+
+        let outcome = reloader
+            .apply(configuration(&[
+                ("email_client.sender_email", "newsletter@example.com"),
+                ("email_client.timeout_milliseconds", "5000"),
+                ("log_filter", "debug"),
+            ]))
+            .unwrap();
+
+        assert_eq!(
+            outcome.applied,
+            vec![
+                "email_client.sender_email",
+                "email_client.timeout_milliseconds",
+                "log_filter",
+            ]
+        );
+        assert!(outcome.rejected.is_empty());
+    }
+
+    #[test]
+    fn changes_that_require_a_restart_are_rejected_until_reverted() {
+        let mut reloader = reloader();
+        let changed = || configuration(&[("application.port", "9000"), ("log_filter", "warn")]);
+
+        let outcome = reloader.apply(changed()).unwrap();
+        assert_eq!(outcome.applied, vec!["log_filter"]);
+        assert_eq!(outcome.rejected, vec!["application.port"]);
+
+        // 被拒绝的修改没有生效，再次重新加载时仍会报告
+        let outcome = reloader.apply(changed()).unwrap();
+        assert!(outcome.applied.is_empty());
+        assert_eq!(outcome.rejected, vec!["application.port"]);
+
+        let outcome = reloader.apply(configuration(&[("log_filter", "warn")])).unwrap();
+        assert_eq!(outcome, ReloadOutcome::default());
+    }
+
+    #[test]
+    fn invalid_configurations_are_rejected_entirely() {
+        let mut reloader = reloader();
+
+        let outcome = reloader.apply(configuration(&[
+            ("email_client.sender_email", "not-an-email"),
+            ("log_filter", "debug"),
+        ]));
+        assert!(outcome.is_err());
+
+        // 之前的修改没有生效
+        let outcome = reloader.apply(configuration(&[("log_filter", "debug")])).unwrap();
+        assert_eq!(outcome.applied, vec!["log_filter"]);
+    }
+}
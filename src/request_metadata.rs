@@ -0,0 +1,103 @@
+use actix_web::dev::Payload;
+use actix_web::http::header::USER_AGENT;
+use actix_web::{web, FromRequest, HttpRequest};
+use std::convert::Infallible;
+use std::future::{Ready, ready};
+use std::net::IpAddr;
+
+/// 反向代理的地址，来自'application.trusted_proxies'
+/// - 只有直接连接的对端是这些地址之一时，才采用X-Forwarded-For中的客户端地址
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+/// 发起请求的客户端信息：IP地址与User-Agent
+/// - IP地址默认是连接的对端地址，X-Forwarded-For等请求头可以由客户端任意伪造
+/// - 对端是可信代理时，从右往左取X-Forwarded-For中第一个不是可信代理的地址，
+///   更靠左的地址由客户端提供，不予采用
+pub struct RequestMetadata {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequest for RequestMetadata {
+    type Error = Infallible;
+    type Future = Ready<Result<RequestMetadata, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let trusted_proxies = req
+            .app_data::<web::Data<TrustedProxies>>()
+            .map(|proxies| proxies.0.as_slice())
+            .unwrap_or_default();
+        let ip_address = client_ip(req, trusted_proxies).map(|ip| ip.to_string());
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.to_owned());
+        ready(Ok(RequestMetadata { ip_address, user_agent }))
+    }
+}
+
+fn client_ip(req: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let mut ip = req.peer_addr()?.ip();
+    if !trusted_proxies.contains(&ip) {
+        return Some(ip);
+    }
+    let forwarded_for: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(str::trim)
+        .collect();
+    for address in forwarded_for.into_iter().rev() {
+        match address.parse::<IpAddr>() {
+            Ok(address) => ip = address,
+            Err(_) => break,
+        }
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+    }
+    Some(ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::client_ip;
+    use actix_web::test::TestRequest;
+    use std::net::{IpAddr, SocketAddr};
+
+    const PROXY: &str = "10.0.0.1";
+
+    fn client_ip_of(forwarded_for: &str, trusted_proxies: &[&str]) -> String {
+        let peer_addr = SocketAddr::new(PROXY.parse().unwrap(), 40000);
+        let request = TestRequest::default()
+            .peer_addr(peer_addr)
+            .insert_header(("X-Forwarded-For", forwarded_for))
+            .to_http_request();
+        let trusted_proxies: Vec<IpAddr> = trusted_proxies.iter().map(|p| p.parse().unwrap()).collect();
+        client_ip(&request, &trusted_proxies).unwrap().to_string()
+    }
+
+    #[test]
+    fn forwarding_headers_are_ignored_without_a_trusted_proxy() {
+        assert_eq!(client_ip_of("203.0.113.7", &[]), PROXY);
+    }
+
+    #[test]
+    fn the_address_added_by_the_trusted_proxy_is_used() {
+        assert_eq!(client_ip_of("203.0.113.7", &[PROXY]), "203.0.113.7");
+        // 最左边的地址由客户端伪造
+        assert_eq!(client_ip_of("198.51.100.1, 203.0.113.7", &[PROXY]), "203.0.113.7");
+        assert_eq!(
+            client_ip_of("203.0.113.7, 10.0.0.2", &[PROXY, "10.0.0.2"]),
+            "203.0.113.7"
+        );
+    }
+
+    #[test]
+    fn a_malformed_forwarded_address_is_not_used() {
+        assert_eq!(client_ip_of("not-an-ip", &[PROXY]), PROXY);
+    }
+}
@@ -0,0 +1,99 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::{get_user_role, list_api_keys, ApiScope, CsrfToken, UserId, UserRole};
+use crate::utils::e500;
+
+/// API密钥管理页面
+/// - 普通管理员只能看到自己的密钥，owner可以看到所有密钥
+pub async fn api_keys_page(
+    flash_messages: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let csrf_token = csrf_token.into_inner();
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let filter = match get_user_role(*user_id, &pool).await.map_err(e500)? {
+        UserRole::Owner => None,
+        UserRole::Admin => Some(*user_id),
+    };
+    let mut rows = String::new();
+    for key in list_api_keys(&pool, filter).await.map_err(e500)? {
+        let action = match key.revoked_at {
+            Some(revoked_at) => format!("Revoked at {}", revoked_at.format("%Y-%m-%d %H:%M:%S UTC")),
+            None => format!(
+                r#"<form action="/admin/api_keys/revoke" method="post">
+                    <input hidden type="text" name="api_key_id" value="{}">
+                    <input hidden type="text" name="csrf_token" value="{}">
+                    <button type="submit">Revoke</button>
+                </form>"#,
+                key.api_key_id, csrf_token,
+            ),
+        };
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>z2p_{}_…</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&key.name),
+            encode_minimal(&key.username),
+            key.key_prefix,
+            encode_minimal(&key.scopes.join(", ")),
+            key.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            key.last_used_at
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| "never".into()),
+            action,
+        )
+        .unwrap();
+    }
+
+    let mut scope_inputs = String::new();
+    for scope in ApiScope::ALL {
+        writeln!(
+            scope_inputs,
+            r#"<label><input type="checkbox" name="scope" value="{0}"> {0}</label><br>"#,
+            scope.as_str(),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API keys</title>
+</head>
+<body>
+    {msg_html}
+    <h2>API keys</h2>
+    <table>
+        <tr><th>Name</th><th>Owner</th><th>Key</th><th>Scopes</th><th>Created</th><th>Last used</th><th></th></tr>
+        {rows}
+    </table>
+    <h2>Create a new API key</h2>
+    <form action="/admin/api_keys" method="post">
+        <label>Name:<br>
+            <input type="text" placeholder="e.g. CI release notes" name="name">
+        </label>
+        <br>
+        {scope_inputs}
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+        <button type="submit">Create</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
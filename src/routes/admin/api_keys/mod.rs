@@ -0,0 +1,4 @@
+mod get;
+pub use get::api_keys_page;
+mod post;
+pub use post::*;
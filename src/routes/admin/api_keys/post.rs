@@ -0,0 +1,101 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{
+    get_user_role, insert_api_key, mark_api_key_revoked, ApiScope, UserId, UserRole,
+};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RevokeApiKeyFormData {
+    api_key_id: Uuid,
+}
+
+/// 创建API密钥
+/// - 表单中的'scope'字段可以出现多次，因此按键值对列表解析
+/// - 完整的密钥只在这个响应中展示一次
+#[tracing::instrument(
+    name = "Create an API key",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn create_api_key(
+    form: web::Form<Vec<(String, String)>>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut name = String::new();
+    let mut scopes = Vec::new();
+    for (field, value) in form.into_inner() {
+        match field.as_str() {
+            "name" => name = value.trim().to_owned(),
+            "scope" => match ApiScope::try_from(value) {
+                Ok(scope) if !scopes.contains(&scope) => scopes.push(scope),
+                Ok(_) => {}
+                Err(_) => {
+                    FlashMessage::error("Unknown API key scope.").send();
+                    return Ok(see_other("/admin/api_keys"));
+                }
+            },
+            _ => {}
+        }
+    }
+    if name.is_empty() {
+        FlashMessage::error("The API key needs a name.").send();
+        return Ok(see_other("/admin/api_keys"));
+    }
+    if scopes.is_empty() {
+        FlashMessage::error("Select at least one scope for the API key.").send();
+        return Ok(see_other("/admin/api_keys"));
+    }
+
+    let (_, api_key) = insert_api_key(&pool, **user_id, &name, &scopes)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API key created</title>
+</head>
+<body>
+    <p>The API key "{name}" has been created. Copy it now, it will not be shown again:</p>
+    <p><code id="api-key">{api_key}</code></p>
+    <p><a href="/admin/api_keys">&lt;- Back</a></p>
+</body>
+</html>"#,
+            name = encode_minimal(&name),
+        )))
+}
+
+/// 撤销API密钥；普通管理员只能撤销自己的密钥
+#[tracing::instrument(
+    name = "Revoke an API key",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, api_key_id=%form.api_key_id)
+)]
+pub async fn revoke_api_key(
+    form: web::Form<RevokeApiKeyFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let owner_filter = match get_user_role(**user_id, &pool).await.map_err(e500)? {
+        UserRole::Owner => None,
+        UserRole::Admin => Some(**user_id),
+    };
+    if mark_api_key_revoked(&pool, form.api_key_id, owner_filter)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The API key has been revoked.").send();
+    } else {
+        FlashMessage::error("The API key does not exist or has already been revoked.").send();
+    }
+    Ok(see_other("/admin/api_keys"))
+}
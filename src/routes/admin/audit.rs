@@ -0,0 +1,144 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::audit::{list_audit_log, AuditAction, AuditEntry, AuditLogFilter, AUDIT_LOG_PAGE_SIZE};
+use crate::authentication::{get_user_role, UserId, UserRole};
+use crate::utils::{e400, e500};
+
+/// 审计日志页面的过滤条件，日期格式为'YYYY-MM-DD'(UTC)，'until'当天也包含在内
+#[derive(serde::Deserialize)]
+pub struct AuditLogQuery {
+    actor: Option<String>,
+    action: Option<String>,
+    target: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+/// 查看审计日志
+/// - owner可以查看所有用户的操作，admin只能查看自己的操作
+pub async fn audit_log_page(
+    query: web::Query<AuditLogQuery>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let query = query.into_inner();
+    let is_owner = get_user_role(*user_id, &pool).await.map_err(e500)? == UserRole::Owner;
+
+    // 空的表单字段视为未填写
+    let actor = non_empty(query.actor);
+    let action = non_empty(query.action);
+    let target = non_empty(query.target);
+    let since = non_empty(query.since);
+    let until = non_empty(query.until);
+
+    let filter = AuditLogFilter {
+        actor_user_id: if is_owner { None } else { Some(*user_id) },
+        actor_username: if is_owner { actor.clone() } else { None },
+        action: action.clone().map(AuditAction::try_from).transpose().map_err(e400)?,
+        target: target.clone(),
+        since: since.as_deref().map(start_of_day).transpose().map_err(e400)?,
+        until: until.as_deref().map(end_of_day).transpose().map_err(e400)?,
+    };
+    let entries = list_audit_log(&pool, &filter).await.map_err(e500)?;
+
+    let mut rows = String::new();
+    for entry in &entries {
+        writeln!(rows, "<tr>{}</tr>", entry_cells(entry)).unwrap();
+    }
+    let mut action_options = String::from(r#"<option value="">Any</option>"#);
+    for a in AuditAction::ALL {
+        let selected = if action.as_deref() == Some(a.as_str()) { " selected" } else { "" };
+        write!(action_options, r#"<option value="{0}"{1}>{0}</option>"#, a.as_str(), selected).unwrap();
+    }
+    let actor_input = if is_owner {
+        format!(
+            r#"<label>User <input type="text" name="actor" value="{}"></label>"#,
+            encode_minimal(actor.as_deref().unwrap_or("")),
+        )
+    } else {
+        String::new()
+    };
+    let truncated_html = if entries.len() as i64 == AUDIT_LOG_PAGE_SIZE {
+        format!("<p><i>Showing the latest {} entries. Narrow the filters to see older ones.</i></p>", AUDIT_LOG_PAGE_SIZE)
+    } else {
+        String::new()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Audit log</title>
+</head>
+<body>
+    <h2>Audit log</h2>
+    <form action="/admin/audit" method="get">
+        {actor_input}
+        <label>Action <select name="action">{action_options}</select></label>
+        <label>Target <input type="text" name="target" value="{target}"></label>
+        <label>Since <input type="date" name="since" value="{since}"></label>
+        <label>Until <input type="date" name="until" value="{until}"></label>
+        <button type="submit">Filter</button>
+    </form>
+    <table>
+        <tr><th>Time</th><th>User</th><th>Action</th><th>Target</th><th>IP address</th><th>User agent</th></tr>
+        {rows}
+    </table>
+    {truncated_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            target = encode_minimal(target.as_deref().unwrap_or("")),
+            since = encode_minimal(since.as_deref().unwrap_or("")),
+            until = encode_minimal(until.as_deref().unwrap_or("")),
+        )))
+}
+
+fn entry_cells(entry: &AuditEntry) -> String {
+    let actor = match (&entry.actor_username, entry.actor_user_id) {
+        (Some(username), _) => encode_minimal(username),
+        (None, Some(user_id)) => format!("deleted user {}", user_id),
+        (None, None) => "unknown".to_string(),
+    };
+    format!(
+        "<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>",
+        entry.occurred_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        actor,
+        encode_minimal(&entry.action),
+        encode_minimal(entry.target.as_deref().unwrap_or("")),
+        encode_minimal(entry.ip_address.as_deref().unwrap_or("unknown")),
+        encode_minimal(entry.user_agent.as_deref().unwrap_or("unknown")),
+    )
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, anyhow::Error> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| anyhow::anyhow!("'{}' is not a date in the YYYY-MM-DD format.", value))
+}
+
+fn start_of_day(value: &str) -> Result<DateTime<Utc>, anyhow::Error> {
+    Ok(parse_date(value)?.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
+
+/// 当天结束的时刻，即次日零点
+fn end_of_day(value: &str) -> Result<DateTime<Utc>, anyhow::Error> {
+    let next_day = parse_date(value)?
+        .succ_opt()
+        .context("The date is out of range.")?;
+    Ok(next_day.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
@@ -5,18 +5,22 @@ use actix_web::http::header::ContentType;
 use actix_web::web;
 use anyhow::Context;
 use sqlx::PgPool;
+use crate::database::DatabasePools;
+use crate::authentication::CsrfToken;
 use crate::session_state::TypedSession;
 use crate::utils::e500;
 
 pub async fn admin_dashboard(
     session: TypedSession,
-    pool: web::Data<PgPool>,
+    pools: web::Data<DatabasePools>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_token = csrf_token.into_inner();
     let username = if let Some(user_id) = session
         .get_user_id()
         .map_err(e500)?
         {
-            get_username(user_id, &pool).await.map_err(e500)?
+            get_username(user_id, pools.read().await).await.map_err(e500)?
         } else {
             // 未登录的用户重定向到登录页面
             return Ok(HttpResponse::SeeOther()
@@ -40,8 +44,15 @@ pub async fn admin_dashboard(
                         <ol>
                             <li><a href="/admin/password">Change password</a></li>
                             <li><a href="/admin/newsletters">Pulish newsletters</a></li>
+                            <li><a href="/admin/newsletters/history">Past issues</a></li>
+                            <li><a href="/admin/subscribers">Subscribers</a></li>
+                            <li><a href="/admin/lists">Mailing lists</a></li>
+                            <li><a href="/admin/sessions">Active sessions</a></li>
+                            <li><a href="/admin/api_keys">API keys</a></li>
+                            <li><a href="/admin/audit">Audit log</a></li>
                             <li>
                                 <form name="logoutForm" action="/admin/logout" method="post">
+                                    <input hidden type="text" name="csrf_token" value="{csrf_token}">
                                     <input type="submit" value="Logout">
                                 </form>
                             </li>
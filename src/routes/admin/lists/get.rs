@@ -0,0 +1,88 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::CsrfToken;
+use crate::mailing_lists::list_mailing_lists;
+use crate::utils::e500;
+
+/// 邮件列表管理页面，默认列表不能删除
+pub async fn mailing_lists_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_token = csrf_token.into_inner();
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows = String::new();
+    for list in list_mailing_lists(&pool).await.map_err(e500)? {
+        let action = if list.is_default {
+            "Default".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/lists/delete" method="post">
+                    <input hidden type="text" name="list_id" value="{}">
+                    <input hidden type="text" name="csrf_token" value="{}">
+                    <button type="submit">Delete</button>
+                </form>"#,
+                list.list_id, csrf_token,
+            )
+        };
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&list.slug),
+            encode_minimal(&list.name),
+            encode_minimal(&list.description),
+            list.confirmed_subscribers,
+            list.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            action,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Mailing lists</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Mailing lists</h2>
+    <table>
+        <tr><th>Slug</th><th>Name</th><th>Description</th><th>Confirmed subscribers</th><th>Created</th><th></th></tr>
+        {rows}
+    </table>
+    <h2>Create a new mailing list</h2>
+    <form action="/admin/lists" method="post">
+        <label>Slug:<br>
+            <input type="text" placeholder="e.g. product-updates" name="slug">
+        </label>
+        <br>
+        <label>Name:<br>
+            <input type="text" placeholder="e.g. Product updates" name="name">
+        </label>
+        <br>
+        <label>Description:<br>
+            <input type="text" name="description">
+        </label>
+        <br>
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+        <button type="submit">Create</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
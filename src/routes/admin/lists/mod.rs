@@ -0,0 +1,4 @@
+mod get;
+pub use get::mailing_lists_page;
+mod post;
+pub use post::*;
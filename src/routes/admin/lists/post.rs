@@ -0,0 +1,112 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::UserId;
+use crate::domain::ListSlug;
+use crate::mailing_lists::{create_mailing_list, delete_mailing_list};
+use crate::request_metadata::RequestMetadata;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct CreateListFormData {
+    slug: String,
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteListFormData {
+    list_id: Uuid,
+}
+
+/// 创建邮件列表
+#[tracing::instrument(
+    name = "Create a mailing list via the admin page",
+    skip(form, pool, user_id, metadata),
+    fields(user_id=%*user_id, slug=%form.slug)
+)]
+pub async fn create_list(
+    form: web::Form<CreateListFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    metadata: RequestMetadata,
+) -> Result<HttpResponse, actix_web::Error> {
+    let CreateListFormData { slug, name, description } = form.0;
+    let slug = match ListSlug::parse(slug.trim().to_string()) {
+        Ok(slug) => slug,
+        Err(_) => {
+            FlashMessage::error(
+                "The slug must be 1 to 64 lowercase letters, digits or hyphens.",
+            )
+            .send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The mailing list needs a name.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    match create_mailing_list(&pool, &slug, name, description.trim())
+        .await
+        .map_err(e500)?
+    {
+        Some(list_id) => {
+            record_audit_event(
+                &pool,
+                **user_id,
+                AuditAction::CreateMailingList,
+                Some(&format!("mailing_list/{}", list_id)),
+                &metadata,
+            )
+            .await
+            .map_err(e500)?;
+            FlashMessage::info(format!("The mailing list '{}' has been created.", slug.as_ref()))
+                .send();
+        }
+        None => {
+            FlashMessage::error(format!(
+                "A mailing list with the slug '{}' already exists.",
+                slug.as_ref()
+            ))
+            .send();
+        }
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+/// 删除邮件列表，订阅者本身不受影响
+#[tracing::instrument(
+    name = "Delete a mailing list via the admin page",
+    skip(form, pool, user_id, metadata),
+    fields(user_id=%*user_id, list_id=%form.list_id)
+)]
+pub async fn delete_list(
+    form: web::Form<DeleteListFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    metadata: RequestMetadata,
+) -> Result<HttpResponse, actix_web::Error> {
+    match delete_mailing_list(&pool, form.list_id).await.map_err(e500)? {
+        Some(slug) => {
+            record_audit_event(
+                &pool,
+                **user_id,
+                AuditAction::DeleteMailingList,
+                Some(&format!("mailing_list/{}", form.list_id)),
+                &metadata,
+            )
+            .await
+            .map_err(e500)?;
+            FlashMessage::info(format!("The mailing list '{}' has been deleted.", slug)).send();
+        }
+        None => {
+            FlashMessage::error("The mailing list does not exist or is the default list.").send();
+        }
+    }
+    Ok(see_other("/admin/lists"))
+}
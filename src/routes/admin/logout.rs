@@ -1,16 +1,28 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{delete_session, SessionId, UserId};
+use crate::request_metadata::RequestMetadata;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 
 
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
-    if session.get_user_id().map_err(e500)?.is_none() {
-        Ok(see_other("/login"))
-    } else {
-        session.log_out();
-        FlashMessage::info("You have successfully logged out.").send();
-        Ok(see_other("/login"))
-    }
-}
\ No newline at end of file
+pub async fn log_out(
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+    pool: web::Data<PgPool>,
+    metadata: RequestMetadata,
+) -> Result<HttpResponse, actix_web::Error> {
+    delete_session(&pool, **session_id, **user_id)
+        .await
+        .map_err(e500)?;
+    record_audit_event(&pool, **user_id, AuditAction::Logout, None, &metadata)
+        .await
+        .map_err(e500)?;
+    session.log_out();
+    FlashMessage::info("You have successfully logged out.").send();
+    Ok(see_other("/login"))
+}
@@ -5,4 +5,14 @@ pub use password::*;
 mod logout;
 pub use logout::*;
 mod newsletters;
-pub use newsletters::*;
\ No newline at end of file
+pub use newsletters::*;
+mod sessions;
+pub use sessions::*;
+mod api_keys;
+pub use api_keys::*;
+mod audit;
+pub use audit::*;
+mod subscribers;
+pub use subscribers::*;
+mod lists;
+pub use lists::*;
\ No newline at end of file
@@ -1,15 +1,83 @@
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
 use std::fmt::Write;
+use crate::authentication::CsrfToken;
+use crate::issue_delivery::{count_recipients, Audience};
+use crate::mailing_lists::{
+    list_mailing_lists, parse_list_slugs, resolve_mailing_lists, ListSelectionError,
+};
+use crate::segments::Segment;
+use crate::utils::e500;
 
+/// 发布表单
+/// - 查询参数中的'lists'(可重复)和'segment'是当前选择的收件人范围，页面上显示匹配的收件人数量
+/// - 发布表单以隐藏字段携带同样的收件人范围，因此发出的数量与预览一致
 pub async fn publish_newsletter_form(
+    query: web::Query<Vec<(String, String)>>,
     flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_token = csrf_token.into_inner();
+
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let mut selected_lists = Vec::new();
+    let mut segment = String::new();
+    for (field, value) in query.into_inner() {
+        match field.as_str() {
+            "lists" => selected_lists.push(value),
+            "segment" => segment = value,
+            _ => {}
+        }
+    }
+
+    // 没有选择时默认列表预先勾选，与发布时的处理一致
+    let mut lists_html = String::new();
+    for list in list_mailing_lists(&pool).await.map_err(e500)? {
+        let checked = if selected_lists.is_empty() {
+            list.is_default
+        } else {
+            selected_lists.contains(&list.slug)
+        };
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="lists" value="{}"{}> {} ({} confirmed)</label><br>"#,
+            encode_minimal(&list.slug),
+            if checked { " checked" } else { "" },
+            encode_minimal(&list.name),
+            list.confirmed_subscribers,
+        )
+        .unwrap();
+    }
+    let recipients_html = match recipient_count(&pool, selected_lists.clone(), &segment).await? {
+        Ok(count) => format!(
+            "<p>This issue will be sent to {} confirmed subscriber(s).</p>",
+            count
+        ),
+        Err(message) => format!("<p><i>{}</i></p>", encode_minimal(&message)),
+    };
+    let mut audience_html = String::new();
+    for list in &selected_lists {
+        writeln!(
+            audience_html,
+            r#"<input hidden type="text" name="lists" value="{}">"#,
+            encode_minimal(list),
+        )
+        .unwrap();
+    }
+    writeln!(
+        audience_html,
+        r#"<input hidden type="text" name="segment" value="{}">"#,
+        encode_minimal(&segment),
+    )
+    .unwrap();
+    let segment = encode_minimal(&segment);
 
     let idempotency_key = uuid::Uuid::new_v4();
     Ok(HttpResponse::Ok()
@@ -23,6 +91,22 @@ pub async fn publish_newsletter_form(
 </head>
 <body>
     {msg_html}
+    <form action="/admin/newsletters" method="get">
+        <fieldset>
+            <legend>Recipients</legend>
+            {lists_html}
+            <label>Segment:
+                <input
+                    type="text"
+                    placeholder="e.g. beta AND NOT churned"
+                    name="segment"
+                    value="{segment}"
+                >
+            </label>
+            <button type="submit">Count recipients</button>
+        </fieldset>
+    </form>
+    {recipients_html}
     <form action="/admin/newsletters" method="post">
         <label>Title:<br>
             <input
@@ -50,11 +134,44 @@ pub async fn publish_newsletter_form(
             ></textarea>
         </label>
         <br>
+        <label>
+            <input type="checkbox" name="track">
+            Track opens and clicks
+        </label>
+        <br>
+        {audience_html}
         <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
         <button type="submit">Publish</button>
     </form>
+    <p><a href="/admin/newsletters/history">Past issues</a></p>
     <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>"#,
         )))
-}
\ No newline at end of file
+}
+
+/// 统计收件人数量，收件人范围无效时返回展示给用户的错误信息
+async fn recipient_count(
+    pool: &PgPool,
+    lists: Vec<String>,
+    segment: &str,
+) -> Result<Result<i64, String>, actix_web::Error> {
+    let lists = match parse_list_slugs(lists) {
+        Ok(lists) => lists,
+        Err(message) => return Ok(Err(message)),
+    };
+    let segment = match Segment::parse_optional(segment) {
+        Ok(segment) => segment,
+        Err(message) => return Ok(Err(message)),
+    };
+    let list_ids = match resolve_mailing_lists(pool, &lists).await {
+        Ok(list_ids) => list_ids,
+        Err(ListSelectionError::Invalid(message)) => return Ok(Err(message)),
+        Err(ListSelectionError::Unexpected(e)) => return Err(e500(e)),
+    };
+    let count = count_recipients(pool, &Audience { list_ids, segment })
+        .await
+        .map_err(e500)?;
+    Ok(Ok(count))
+}
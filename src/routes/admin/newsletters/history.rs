@@ -0,0 +1,71 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use htmlescape::encode_minimal;
+use std::fmt::Write;
+use crate::database::DatabasePools;
+use crate::tracking::{get_issue_history, IssueSummary};
+use crate::utils::e500;
+
+/// 往期简报，包括投递结果，以及开启追踪的简报的打开和点击统计
+/// - 比例按打开或点击过的订阅者数(unique)除以成功投递数计算，total是事件总数
+pub async fn issue_history_page(
+    pools: web::Data<DatabasePools>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut rows = String::new();
+    for issue in get_issue_history(pools.read().await).await.map_err(e500)? {
+        let (opens, clicks) = if issue.tracking_enabled {
+            (opens_cell(&issue), clicks_cell(&issue))
+        } else {
+            ("not tracked".to_string(), "not tracked".to_string())
+        };
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            issue.sent,
+            issue.failed,
+            opens,
+            clicks,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Past issues</title>
+</head>
+<body>
+    <h2>Past issues</h2>
+    <table>
+        <tr><th>Title</th><th>Published at</th><th>Sent</th><th>Failed</th><th>Opens</th><th>Clicks</th></tr>
+        {rows}
+    </table>
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+fn opens_cell(issue: &IssueSummary) -> String {
+    format!(
+        "{:.1}% ({} unique, {} total)",
+        issue.open_rate() * 100.0,
+        issue.unique_opens,
+        issue.opens
+    )
+}
+
+fn clicks_cell(issue: &IssueSummary) -> String {
+    format!(
+        "{:.1}% ({} unique, {} total)",
+        issue.click_rate() * 100.0,
+        issue.unique_clicks,
+        issue.clicks
+    )
+}
@@ -1,4 +1,6 @@
 mod get;
 pub use get::*;
 mod post;
-pub use post::*;
\ No newline at end of file
+pub use post::*;
+mod history;
+pub use history::*;
@@ -1,33 +1,62 @@
 use crate::authentication::UserId;
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::issue_delivery::{publish_issue, Audience, DeliveryContext, NewsletterContent};
+use crate::mailing_lists::{parse_list_slugs, resolve_mailing_lists, ListSelectionError};
 use crate::utils::{e400, e500, see_other};
-use crate::idempotency::{save_response, IdempotencyKey};
+use crate::idempotency::{save_response_snapshot, IdempotencyKey, ResponseSnapshot};
 use crate::idempotency::get_saved_response;
+use crate::request_metadata::RequestMetadata;
+use crate::segments::Segment;
+use crate::shutdown::BackgroundTasks;
 use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
-use anyhow::Context;
-use sqlx::PgPool;
 
-#[derive(serde::Deserialize)]
+/// - lists: 可以重复提交多个目标邮件列表，未勾选时发送给默认列表
+/// - segment: 标签的分群表达式，留空表示不按标签筛选
+/// - track: 勾选时追踪这期简报的打开和点击
+#[derive(Default)]
 pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
     idempotency_key: String,
+    lists: Vec<String>,
+    segment: String,
+    track: bool,
 }
 
+impl From<Vec<(String, String)>> for FormData {
+    fn from(fields: Vec<(String, String)>) -> Self {
+        let mut form = Self::default();
+        for (field, value) in fields {
+            match field.as_str() {
+                "title" => form.title = value,
+                "text_content" => form.text_content = value,
+                "html_content" => form.html_content = value,
+                "idempotency_key" => form.idempotency_key = value,
+                "lists" => form.lists.push(value),
+                "segment" => form.segment = value,
+                "track" => form.track = value == "on",
+                _ => {}
+            }
+        }
+        form
+    }
+}
+
+/// 表单中的'lists'字段可以出现多次，因此按键值对列表解析
+/// - 投递和保存幂等响应在后台任务中执行，请求被取消时也会完成，不会留下投递了一半却没有幂等记录的简报
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(form, pool, email_client, user_id),
+    skip(form, context, user_id, metadata, background_tasks),
     fields(user_id=%*user_id)
 )]
 pub async fn publish_newsletter(
-    form: web::Form<FormData>,
+    form: web::Form<Vec<(String, String)>>,
     user_id: ReqData<UserId>,
-    pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    context: DeliveryContext,
+    metadata: RequestMetadata,
+    background_tasks: web::Data<BackgroundTasks>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     // 必须重组表单，以避免干扰借用检查器
@@ -35,69 +64,47 @@ pub async fn publish_newsletter(
         title, 
         text_content, 
         html_content, 
-        idempotency_key
-    } = form.0;
+        idempotency_key,
+        lists,
+        segment,
+        track,
+    } = form.0.into();
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
-    if let Some(saved_response) = get_saved_response(&pool, &idempotency_key, *user_id)
+    if let Some(saved_response) = get_saved_response(&context.pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
     {
         return Ok(saved_response);
     }
-    let subscribers = get_confirmed_subscribers(&pool).await.map_err(e500)?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                // 不再使用 'form.0'
-                email_client
-                    .send_email(&subscriber.email, &title, &html_content, &text_content)
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })
-                    .map_err(e500)?;
-            }
-            Err(error) => {
-                tracing::warn!(
-                    error.cause_chain = ?error,
-                    error.message = %error,
-                    "Skipping a confirmed subscriber. Their stored contact details are invalid",
-                );
-            }
-        }
-    }
+    let lists = parse_list_slugs(lists).map_err(e400)?;
+    let segment = Segment::parse_optional(&segment).map_err(e400)?;
+    let list_ids = match resolve_mailing_lists(&context.pool, &lists).await {
+        Ok(list_ids) => list_ids,
+        Err(ListSelectionError::Invalid(message)) => return Err(e400(message)),
+        Err(ListSelectionError::Unexpected(e)) => return Err(e500(e)),
+    };
+    let audience = Audience { list_ids, segment };
+    let content = NewsletterContent {
+        title,
+        text_content,
+        html_content,
+        tracking_enabled: track,
+    };
+    let delivery = background_tasks.spawn(async move {
+        let report = publish_issue(&context, &content, *user_id, &audience, &metadata).await?;
+        let response = ResponseSnapshot::capture(see_other("/admin/newsletters"))?;
+        save_response_snapshot(&context.pool, &idempotency_key, *user_id, &response).await?;
+        Ok::<_, anyhow::Error>((report, response))
+    });
+    let (report, response) = delivery.await.map_err(e500)?.map_err(e500)?;
 
     FlashMessage::info("The newsletter issue has been published!").send();
-    let response = see_other("/admin/newsletters");
-    let response = save_response(&pool, &idempotency_key, *user_id, response)
-        .await
-        .map_err(e500)?;
-    Ok(response)
-}
-
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
+    if report.failed > 0 {
+        FlashMessage::error(format!(
+            "The newsletter issue could not be delivered to {} subscriber(s).",
+            report.failed
+        ))
+        .send();
+    }
+    response.to_response().map_err(e500)
 }
-
-/// 从Postgres数据库中获取所有已确认的订阅者
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-async fn get_confirmed_subscribers(
-    pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
-        r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-    )
-    .fetch_all(pool)
-    .await?
-    .into_iter()
-    .map(|r| match SubscriberEmail::parse(r.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(error) => Err(anyhow::anyhow!(error)),
-    })
-    .collect();
-    Ok(confirmed_subscribers)
-}
\ No newline at end of file
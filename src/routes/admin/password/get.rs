@@ -1,14 +1,17 @@
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
+use crate::authentication::CsrfToken;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 
 pub async fn change_password_form(
     session: TypedSession,
     flash_message: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_token = csrf_token.into_inner();
     if session.get_user_id().map_err(e500)?.is_none() {
         return Ok(see_other("/login"));
     }
@@ -55,6 +58,7 @@ pub async fn change_password_form(
                         >
                     </label>
                     <br>
+                    <input hidden type="text" name="csrf_token" value="{csrf_token}">
                     <button type="submit">Change password</button>
                 </form>
                 <p><a href="/admin/dashboard"><- Back</a></p>
@@ -7,6 +7,9 @@ use crate::utils::{e500, see_other};
 use crate::routes::admin::dashboard::get_username;
 use crate::authentication::{validate_credentials, AuthError, Credentials};
 use crate::authentication::UserId;
+use crate::configuration::PasswordHashingSettings;
+use crate::audit::{record_audit_event, AuditAction};
+use crate::request_metadata::RequestMetadata;
 
 
 #[derive(serde::Deserialize)]
@@ -19,7 +22,9 @@ pub struct FormData {
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    user_id: web::ReqData<UserId>
+    user_id: web::ReqData<UserId>,
+    hashing_settings: web::Data<PasswordHashingSettings>,
+    metadata: RequestMetadata,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     if user_id.is_nil() {
@@ -41,7 +46,7 @@ pub async fn change_password(
         username: username,
         password: form.0.current_password,
     };
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &pool, &hashing_settings).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -51,7 +56,15 @@ pub async fn change_password(
         };
     }
 
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
+    crate::authentication::change_password(
+        *user_id,
+        form.0.new_password,
+        &pool,
+        &hashing_settings,
+    )
+        .await
+        .map_err(e500)?;
+    record_audit_event(&pool, *user_id, AuditAction::ChangePassword, None, &metadata)
         .await
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
@@ -0,0 +1,116 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use crate::authentication::{get_user_role, list_sessions, ActiveSession, CsrfToken, SessionId, UserId, UserRole};
+use crate::utils::e500;
+
+pub async fn sessions_page(
+    flash_messages: IncomingFlashMessages,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let session_id = session_id.into_inner();
+    let csrf_token = csrf_token.into_inner();
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let own_sessions = list_sessions(&pool, Some(*user_id)).await.map_err(e500)?;
+    let mut own_rows = String::new();
+    for s in &own_sessions {
+        let action = if s.session_id == *session_id {
+            "<i>This session</i>".to_string()
+        } else {
+            revoke_session_form(s, &csrf_token)
+        };
+        writeln!(own_rows, "<tr>{}<td>{}</td></tr>", session_cells(s), action).unwrap();
+    }
+
+    // 只有owner可以查看和撤销所有用户的会话
+    let mut all_users_html = String::new();
+    if get_user_role(*user_id, &pool).await.map_err(e500)? == UserRole::Owner {
+        let all_sessions = list_sessions(&pool, None).await.map_err(e500)?;
+        let mut rows = String::new();
+        for s in all_sessions.iter().filter(|s| s.user_id != *user_id) {
+            writeln!(
+                rows,
+                r#"<tr><td>{username}</td>{cells}<td>
+                    <form action="/admin/sessions/revoke_user" method="post">
+                        <input hidden type="text" name="user_id" value="{user_id}">
+                        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+                        <button type="submit">Revoke all sessions of {username}</button>
+                    </form>
+                </td></tr>"#,
+                username = encode_minimal(&s.username),
+                cells = session_cells(s),
+                user_id = s.user_id,
+            )
+            .unwrap();
+        }
+        write!(
+            all_users_html,
+            r#"<h2>Sessions of other users</h2>
+    <table>
+        <tr><th>User</th><th>Signed in</th><th>Last active</th><th>IP address</th><th>User agent</th><th></th></tr>
+        {rows}
+    </table>"#,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Active sessions</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Your sessions</h2>
+    <table>
+        <tr><th>Signed in</th><th>Last active</th><th>IP address</th><th>User agent</th><th></th></tr>
+        {own_rows}
+    </table>
+    <form action="/admin/sessions/revoke_others" method="post">
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+        <button type="submit">Revoke all other sessions</button>
+    </form>
+    {all_users_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+fn session_cells(s: &ActiveSession) -> String {
+    format!(
+        "<td>{}</td><td>{}</td><td>{}</td><td>{}</td>",
+        s.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        s.last_active_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        encode_minimal(s.ip_address.as_deref().unwrap_or("unknown")),
+        encode_minimal(s.user_agent.as_deref().unwrap_or("unknown")),
+    )
+}
+
+fn revoke_session_form(s: &ActiveSession, csrf_token: &CsrfToken) -> String {
+    format!(
+        r#"<form action="/admin/sessions/revoke" method="post">
+            <input hidden type="text" name="session_id" value="{}">
+            <input hidden type="text" name="csrf_token" value="{}">
+            <button type="submit">Revoke</button>
+        </form>"#,
+        s.session_id,
+        csrf_token,
+    )
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::sessions_page;
+mod post;
+pub use post::*;
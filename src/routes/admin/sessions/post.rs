@@ -0,0 +1,80 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{
+    delete_other_sessions, delete_session, delete_user_sessions,
+    get_user_role, SessionId, UserId, UserRole,
+};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RevokeSessionFormData {
+    session_id: Uuid,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeUserSessionsFormData {
+    user_id: Uuid,
+}
+
+/// 撤销当前用户的某个会话
+#[tracing::instrument(
+    name = "Revoke a session",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn revoke_session(
+    form: web::Form<RevokeSessionFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if delete_session(&pool, form.session_id, **user_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The session has been revoked.").send();
+    } else {
+        FlashMessage::error("The session does not exist or has already been revoked.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+/// 撤销当前用户除当前会话以外的所有会话
+#[tracing::instrument(
+    name = "Revoke all other sessions",
+    skip(pool, user_id, session_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn revoke_other_sessions(
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let revoked = delete_other_sessions(&pool, **user_id, **session_id)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} other session(s) have been revoked.", revoked)).send();
+    Ok(see_other("/admin/sessions"))
+}
+
+/// 撤销某个用户的所有会话，仅owner可用
+#[tracing::instrument(
+    name = "Revoke all sessions of a user",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, target_user_id=%form.user_id)
+)]
+pub async fn revoke_user_sessions(
+    form: web::Form<RevokeUserSessionsFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if get_user_role(**user_id, &pool).await.map_err(e500)? != UserRole::Owner {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let revoked = delete_user_sessions(&pool, form.user_id)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} session(s) have been revoked.", revoked)).send();
+    Ok(see_other("/admin/sessions"))
+}
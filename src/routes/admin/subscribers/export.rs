@@ -0,0 +1,125 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use crate::consent::{get_consent_records, ConsentRecord};
+use crate::database::DatabasePools;
+use crate::subscribers::{list_subscribers, SubscriberRecord};
+use crate::utils::e500;
+
+const CSV_HEADER: [&str; 13] = [
+    "subscriber_id",
+    "email",
+    "name",
+    "status",
+    "subscribed_at",
+    "consent_source",
+    "consent_text_version",
+    "consent_given_at",
+    "consent_ip_address",
+    "consent_user_agent",
+    "consent_confirmed_at",
+    "confirmation_ip_address",
+    "confirmation_user_agent",
+];
+
+/// 以CSV格式导出所有订阅者及其同意记录
+/// - 每条同意记录一行；没有同意记录的订阅者(例如在记录同意证据之前注册的)输出一行，同意相关的列为空
+pub async fn export_subscribers(
+    pools: web::Data<DatabasePools>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let pool = pools.read().await;
+    let subscribers = list_subscribers(pool, None).await.map_err(e500)?;
+    let mut consents: HashMap<_, Vec<ConsentRecord>> = HashMap::new();
+    for record in get_consent_records(pool, None).await.map_err(e500)? {
+        consents.entry(record.subscriber_id).or_default().push(record);
+    }
+
+    let mut csv = csv_line(CSV_HEADER.iter().map(|h| h.to_string()));
+    for subscriber in &subscribers {
+        match consents.get(&subscriber.id) {
+            Some(records) => {
+                for record in records {
+                    csv.push_str(&csv_line(export_row(subscriber, Some(record))));
+                }
+            }
+            None => csv.push_str(&csv_line(export_row(subscriber, None))),
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscribers.csv".into())],
+        })
+        .body(csv))
+}
+
+fn export_row(
+    subscriber: &SubscriberRecord,
+    record: Option<&ConsentRecord>,
+) -> impl Iterator<Item = String> {
+    let timestamp = |t: Option<DateTime<Utc>>| t.map(|t| t.to_rfc3339()).unwrap_or_default();
+    let text = |s: Option<&String>| s.cloned().unwrap_or_default();
+    vec![
+        subscriber.id.to_string(),
+        subscriber.email.clone(),
+        subscriber.name.clone(),
+        subscriber.status.clone(),
+        subscriber.subscribed_at.to_rfc3339(),
+        text(record.map(|r| &r.source)),
+        text(record.map(|r| &r.consent_text_version)),
+        timestamp(record.map(|r| r.given_at)),
+        text(record.and_then(|r| r.ip_address.as_ref())),
+        text(record.and_then(|r| r.user_agent.as_ref())),
+        timestamp(record.and_then(|r| r.confirmed_at)),
+        text(record.and_then(|r| r.confirmation_ip_address.as_ref())),
+        text(record.and_then(|r| r.confirmation_user_agent.as_ref())),
+    ]
+    .into_iter()
+}
+
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    let mut line = fields.map(|f| csv_field(&f)).collect::<Vec<_>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+/// 按RFC 4180转义一个字段
+/// - 以'=+-@'开头的值加上单引号前缀，避免在电子表格中被当作公式执行
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::csv_field;
+
+    #[test]
+    fn plain_values_are_not_quoted() {
+        assert_eq!(csv_field("ursula_le_guin@gmail.com"), "ursula_le_guin@gmail.com");
+    }
+
+    #[test]
+    fn separators_and_quotes_are_escaped() {
+        assert_eq!(csv_field("le guin, ursula"), "\"le guin, ursula\"");
+        assert_eq!(csv_field("Mozilla/5.0 \"test\""), "\"Mozilla/5.0 \"\"test\"\"\"");
+        assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+    }
+
+    #[test]
+    fn formulas_are_neutralised() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+    }
+}
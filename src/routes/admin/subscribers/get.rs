@@ -0,0 +1,160 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+use crate::authentication::CsrfToken;
+use crate::consent::{get_consent_records, ConsentRecord};
+use crate::database::DatabasePools;
+use crate::mailing_lists::get_subscriber_lists;
+use crate::security_headers::CspNonce;
+use crate::segments::get_subscriber_tags;
+use crate::subscribers::{get_subscriber, list_subscribers};
+use crate::utils::e500;
+
+/// 订阅者列表
+pub async fn subscribers_page(
+    pools: web::Data<DatabasePools>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut rows = String::new();
+    for s in list_subscribers(pools.read().await, None).await.map_err(e500)? {
+        writeln!(
+            rows,
+            r#"<tr><td><a href="/admin/subscribers/{id}">{email}</a></td><td>{name}</td><td>{status}</td><td>{subscribed_at}</td></tr>"#,
+            id = s.id,
+            email = encode_minimal(&s.email),
+            name = encode_minimal(&s.name),
+            status = encode_minimal(&s.status),
+            subscribed_at = s.subscribed_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    <h2>Subscribers</h2>
+    <p><a href="/admin/subscribers/export">Export as CSV</a></p>
+    <table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+        {rows}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// 订阅者详情，包括其同意记录和标签，可以在这里添加或移除标签
+/// - 内联样式放在带CSP nonce的'<style>'中，'style'属性会被CSP拦截
+pub async fn subscriber_detail_page(
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+    csp_nonce: web::ReqData<CspNonce>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_token = csrf_token.into_inner();
+    let csp_nonce = csp_nonce.into_inner();
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let mut consent_rows = String::new();
+    for record in get_consent_records(&pool, Some(subscriber_id)).await.map_err(e500)? {
+        writeln!(consent_rows, "<tr>{}</tr>", consent_cells(&record)).unwrap();
+    }
+    let lists = get_subscriber_lists(&pool, subscriber_id).await.map_err(e500)?;
+
+    // 提示信息中可能包含提交的标签，需要转义
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut tag_rows = String::new();
+    for tag in get_subscriber_tags(&pool, subscriber_id).await.map_err(e500)? {
+        writeln!(
+            tag_rows,
+            r#"<li>{tag} <form action="/admin/subscribers/{subscriber_id}/tags/remove" method="post" class="inline">
+            <input hidden type="text" name="tag" value="{tag}">
+            <input hidden type="text" name="csrf_token" value="{csrf_token}">
+            <button type="submit">Remove</button>
+        </form></li>"#,
+            tag = encode_minimal(&tag),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber</title>
+    <style nonce="{csp_nonce}">form.inline {{ display: inline; }}</style>
+</head>
+<body>
+    {msg_html}
+    <h2>{email}</h2>
+    <ul>
+        <li>Name: {name}</li>
+        <li>Status: {status}</li>
+        <li>Subscribed at: {subscribed_at}</li>
+        <li>Mailing lists: {lists}</li>
+    </ul>
+    <h3>Tags</h3>
+    <ul>
+        {tag_rows}
+    </ul>
+    <form action="/admin/subscribers/{subscriber_id}/tags" method="post">
+        <label>Add tags (comma separated)
+            <input type="text" name="tags" placeholder="beta, churned">
+        </label>
+        <input hidden type="text" name="csrf_token" value="{csrf_token}">
+        <button type="submit">Add</button>
+    </form>
+    <h3>Consent records</h3>
+    <table>
+        <tr><th>Source</th><th>Consent text version</th><th>Given at</th><th>IP address</th><th>User agent</th><th>Confirmed at</th><th>Confirmation IP address</th><th>Confirmation user agent</th></tr>
+        {consent_rows}
+    </table>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+            email = encode_minimal(&subscriber.email),
+            name = encode_minimal(&subscriber.name),
+            status = encode_minimal(&subscriber.status),
+            subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            lists = encode_minimal(&lists.join(", ")),
+        )))
+}
+
+fn consent_cells(record: &ConsentRecord) -> String {
+    format!(
+        "<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>",
+        encode_minimal(&record.source),
+        encode_minimal(&record.consent_text_version),
+        record.given_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        encode_minimal(record.ip_address.as_deref().unwrap_or("unknown")),
+        encode_minimal(record.user_agent.as_deref().unwrap_or("unknown")),
+        record
+            .confirmed_at
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_else(|| "not confirmed".into()),
+        encode_minimal(record.confirmation_ip_address.as_deref().unwrap_or("")),
+        encode_minimal(record.confirmation_user_agent.as_deref().unwrap_or("")),
+    )
+}
@@ -0,0 +1,6 @@
+mod get;
+pub use get::*;
+mod export;
+pub use export::*;
+mod post;
+pub use post::*;
@@ -0,0 +1,103 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::UserId;
+use crate::request_metadata::RequestMetadata;
+use crate::segments::{add_subscriber_tags, parse_subscriber_tags, remove_subscriber_tags};
+use crate::subscribers::get_subscriber;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct AddTagsFormData {
+    tags: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveTagFormData {
+    tag: String,
+}
+
+/// 给订阅者添加标签，多个标签用逗号分隔
+#[tracing::instrument(
+    name = "Tag a subscriber via the admin page",
+    skip(form, pool, user_id, metadata),
+    fields(user_id=%*user_id)
+)]
+pub async fn tag_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<AddTagsFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    metadata: RequestMetadata,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    if get_subscriber(&pool, subscriber_id).await.map_err(e500)?.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let tags: Vec<String> = form
+        .0
+        .tags
+        .split(',')
+        .filter(|t| !t.trim().is_empty())
+        .map(String::from)
+        .collect();
+    match parse_subscriber_tags(tags) {
+        Ok(tags) if tags.is_empty() => {
+            FlashMessage::error("Enter at least one tag.").send();
+        }
+        Ok(tags) => {
+            add_subscriber_tags(&pool, subscriber_id, &tags)
+                .await
+                .map_err(e500)?;
+            record_audit_event(
+                &pool,
+                **user_id,
+                AuditAction::TagSubscriber,
+                Some(&format!("subscriber/{}", subscriber_id)),
+                &metadata,
+            )
+            .await
+            .map_err(e500)?;
+            FlashMessage::info("The tags have been added.").send();
+        }
+        Err(message) => FlashMessage::error(message).send(),
+    }
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+/// 移除订阅者的一个标签
+#[tracing::instrument(
+    name = "Untag a subscriber via the admin page",
+    skip(form, pool, user_id, metadata),
+    fields(user_id=%*user_id)
+)]
+pub async fn untag_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<RemoveTagFormData>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    metadata: RequestMetadata,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    match parse_subscriber_tags(vec![form.0.tag]) {
+        Ok(tags) => {
+            remove_subscriber_tags(&pool, subscriber_id, &tags)
+                .await
+                .map_err(e500)?;
+            record_audit_event(
+                &pool,
+                **user_id,
+                AuditAction::UntagSubscriber,
+                Some(&format!("subscriber/{}", subscriber_id)),
+                &metadata,
+            )
+            .await
+            .map_err(e500)?;
+            FlashMessage::info("The tag has been removed.").send();
+        }
+        Err(message) => FlashMessage::error(message).send(),
+    }
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
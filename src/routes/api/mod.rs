@@ -0,0 +1,68 @@
+//! '/api/v1'下的JSON接口，通过'Authorization: Bearer <API密钥>'认证
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use crate::authentication::{ApiKeyIdentity, ApiScope};
+use crate::mailing_lists::ListSelectionError;
+use crate::routes::error_chain_fmt;
+
+mod newsletters;
+pub use newsletters::*;
+mod subscribers;
+pub use subscribers::*;
+
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The API key is missing the '{}' scope.", .0.as_str())]
+    MissingScope(ApiScope),
+    #[error("The requested resource does not exist.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<ListSelectionError> for ApiError {
+    fn from(e: ListSelectionError) -> Self {
+        match e {
+            ListSelectionError::Invalid(message) => Self::ValidationError(message),
+            ListSelectionError::Unexpected(e) => Self::UnexpectedError(e),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiError::MissingScope(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// 错误以JSON返回；意外错误不向调用方暴露细节
+    fn error_response(&self) -> HttpResponse {
+        let message = match self {
+            ApiError::UnexpectedError(_) => "Something went wrong.".to_string(),
+            other => other.to_string(),
+        };
+        HttpResponse::build(self.status_code())
+            .json(serde_json::json!({ "error": message }))
+    }
+}
+
+/// 检查API密钥是否拥有所需的权限范围
+fn require_scope(identity: &ApiKeyIdentity, scope: ApiScope) -> Result<(), ApiError> {
+    if identity.has_scope(scope) {
+        Ok(())
+    } else {
+        Err(ApiError::MissingScope(scope))
+    }
+}
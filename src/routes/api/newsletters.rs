@@ -0,0 +1,156 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::authentication::{ApiKeyIdentity, ApiScope, UserId};
+use crate::idempotency::{get_saved_response, save_response_snapshot, IdempotencyKey, ResponseSnapshot};
+use crate::issue_delivery::{
+    get_delivery_status, publish_issue, Audience, DeliveryContext, NewsletterContent,
+};
+use crate::mailing_lists::{parse_list_slugs, resolve_mailing_lists};
+use crate::request_metadata::RequestMetadata;
+use crate::segments::Segment;
+use crate::shutdown::BackgroundTasks;
+use super::{require_scope, ApiError};
+
+/// 与表单中的'idempotency_key'作用相同，重复的请求直接返回第一次的响应
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// - lists: 可选，目标邮件列表的标识，未提供时发送给默认列表
+/// - segment: 可选，标签的分群表达式，例如'beta AND NOT churned'
+/// - track: 可选，为'true'时追踪这期简报的打开和点击
+#[derive(serde::Deserialize)]
+pub struct NewsletterBody {
+    title: String,
+    text_content: String,
+    html_content: String,
+    #[serde(default)]
+    lists: Vec<String>,
+    #[serde(default)]
+    segment: Option<String>,
+    #[serde(default)]
+    track: bool,
+}
+
+/// 发布一期邮件简报，返回投递结果(201)
+/// - 可选的'Idempotency-Key'请求头可以让调用方安全地重试
+#[tracing::instrument(
+    name = "Publish a newsletter issue via the API",
+    skip(request, body, identity, context, metadata, background_tasks),
+    fields(api_key_id=%identity.api_key_id, user_id=%identity.user_id)
+)]
+pub async fn api_publish_newsletter(
+    request: HttpRequest,
+    body: web::Json<NewsletterBody>,
+    identity: ReqData<ApiKeyIdentity>,
+    context: DeliveryContext,
+    metadata: RequestMetadata,
+    background_tasks: web::Data<BackgroundTasks>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&identity, ApiScope::PublishNewsletters)?;
+    publish(&request, body.0, identity.user_id, context, metadata, &background_tasks).await
+}
+
+/// 与'api_publish_newsletter'相同，但通过HTTP Basic认证('POST /newsletters')
+#[tracing::instrument(
+    name = "Publish a newsletter issue with basic auth",
+    skip(request, body, user_id, context, metadata, background_tasks),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_newsletter_with_basic_auth(
+    request: HttpRequest,
+    body: web::Json<NewsletterBody>,
+    user_id: ReqData<UserId>,
+    context: DeliveryContext,
+    metadata: RequestMetadata,
+    background_tasks: web::Data<BackgroundTasks>,
+) -> Result<HttpResponse, ApiError> {
+    publish(&request, body.0, **user_id, context, metadata, &background_tasks).await
+}
+
+/// 投递和保存幂等响应在后台任务中执行，请求被取消时也会完成
+async fn publish(
+    request: &HttpRequest,
+    body: NewsletterBody,
+    user_id: Uuid,
+    context: DeliveryContext,
+    metadata: RequestMetadata,
+    background_tasks: &BackgroundTasks,
+) -> Result<HttpResponse, ApiError> {
+    let NewsletterBody {
+        title,
+        text_content,
+        html_content,
+        lists,
+        segment,
+        track,
+    } = body;
+    if title.trim().is_empty() {
+        return Err(ApiError::ValidationError("The title cannot be empty.".into()));
+    }
+    let lists = parse_list_slugs(lists).map_err(ApiError::ValidationError)?;
+    let segment = Segment::parse_optional(segment.as_deref().unwrap_or_default())
+        .map_err(ApiError::ValidationError)?;
+
+    let idempotency_key = parse_idempotency_key(request)?;
+    if let Some(key) = &idempotency_key {
+        if let Some(saved_response) = get_saved_response(&context.pool, key, user_id).await? {
+            return Ok(saved_response);
+        }
+    }
+
+    let content = NewsletterContent {
+        title,
+        text_content,
+        html_content,
+        tracking_enabled: track,
+    };
+    let audience = Audience {
+        list_ids: resolve_mailing_lists(&context.pool, &lists).await?,
+        segment,
+    };
+    let delivery = background_tasks.spawn(async move {
+        let report = publish_issue(&context, &content, user_id, &audience, &metadata).await?;
+        let response = ResponseSnapshot::capture(HttpResponse::Created().json(report))?;
+        if let Some(key) = &idempotency_key {
+            save_response_snapshot(&context.pool, key, user_id, &response).await?;
+        }
+        Ok::<_, anyhow::Error>(response)
+    });
+    let response = delivery.await.context("The newsletter delivery task failed.")??;
+    Ok(response.to_response()?)
+}
+
+fn parse_idempotency_key(request: &HttpRequest) -> Result<Option<IdempotencyKey>, ApiError> {
+    let header = match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let key = header
+        .to_str()
+        .map_err(|_| ApiError::ValidationError("The idempotency key must be visible ASCII.".into()))?
+        .to_owned();
+    let key: IdempotencyKey = key
+        .try_into()
+        .map_err(|e: anyhow::Error| ApiError::ValidationError(e.to_string()))?;
+    Ok(Some(key))
+}
+
+/// 查询一期邮件简报的投递状态
+#[tracing::instrument(
+    name = "Get the delivery status via the API",
+    skip(identity, pool),
+    fields(api_key_id=%identity.api_key_id)
+)]
+pub async fn api_delivery_status(
+    newsletter_issue_id: web::Path<Uuid>,
+    identity: ReqData<ApiKeyIdentity>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&identity, ApiScope::ReadDeliveries)?;
+    match get_delivery_status(&pool, newsletter_issue_id.into_inner()).await? {
+        Some(status) => Ok(HttpResponse::Ok().json(status)),
+        None => Err(ApiError::NotFound),
+    }
+}
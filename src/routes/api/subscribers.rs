@@ -0,0 +1,121 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{ApiKeyIdentity, ApiScope};
+use crate::consent::{get_consent_records, ConsentRecord};
+use crate::database::DatabasePools;
+use crate::request_metadata::RequestMetadata;
+use crate::segments::{
+    add_subscriber_tags, get_subscriber_tags, list_subscriber_tags, parse_subscriber_tags,
+    remove_subscriber_tags,
+};
+use crate::subscribers::{get_subscriber, list_subscribers, SubscriberRecord};
+use super::{require_scope, ApiError};
+
+#[derive(serde::Deserialize)]
+pub struct SubscribersQuery {
+    status: Option<String>,
+}
+
+/// - add: 要添加的标签
+/// - remove: 要移除的标签，先添加后移除
+#[derive(serde::Deserialize)]
+pub struct TagsBody {
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+/// 订阅者信息及其同意记录和标签
+#[derive(serde::Serialize)]
+struct SubscriberWithConsents {
+    #[serde(flatten)]
+    subscriber: SubscriberRecord,
+    consents: Vec<ConsentRecord>,
+    tags: Vec<String>,
+}
+
+/// 列出订阅者及其同意记录和标签，可以通过'?status=confirmed'按状态过滤
+#[tracing::instrument(
+    name = "List subscribers via the API",
+    skip(query, pools, identity),
+    fields(api_key_id=%identity.api_key_id)
+)]
+pub async fn api_list_subscribers(
+    query: web::Query<SubscribersQuery>,
+    identity: ReqData<ApiKeyIdentity>,
+    pools: web::Data<DatabasePools>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&identity, ApiScope::ReadSubscribers)?;
+    let pool = pools.read().await;
+    let subscribers = list_subscribers(pool, query.status.as_deref()).await?;
+    let mut consents: HashMap<_, Vec<ConsentRecord>> = HashMap::new();
+    for record in get_consent_records(pool, None).await? {
+        consents.entry(record.subscriber_id).or_default().push(record);
+    }
+    let mut tags: HashMap<_, Vec<String>> = HashMap::new();
+    for (subscriber_id, tag) in list_subscriber_tags(pool).await? {
+        tags.entry(subscriber_id).or_default().push(tag);
+    }
+    let subscribers: Vec<_> = subscribers
+        .into_iter()
+        .map(|subscriber| SubscriberWithConsents {
+            consents: consents.remove(&subscriber.id).unwrap_or_default(),
+            tags: tags.remove(&subscriber.id).unwrap_or_default(),
+            subscriber,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "subscribers": subscribers })))
+}
+
+/// 添加或移除订阅者的标签，返回修改后的全部标签
+#[tracing::instrument(
+    name = "Tag a subscriber via the API",
+    skip(body, pool, identity, metadata),
+    fields(api_key_id=%identity.api_key_id)
+)]
+pub async fn api_update_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    body: web::Json<TagsBody>,
+    identity: ReqData<ApiKeyIdentity>,
+    pool: web::Data<PgPool>,
+    metadata: RequestMetadata,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&identity, ApiScope::WriteSubscribers)?;
+    let TagsBody { add, remove } = body.0;
+    let add = parse_subscriber_tags(add).map_err(ApiError::ValidationError)?;
+    let remove = parse_subscriber_tags(remove).map_err(ApiError::ValidationError)?;
+    let subscriber_id = subscriber_id.into_inner();
+    if get_subscriber(&pool, subscriber_id).await?.is_none() {
+        return Err(ApiError::NotFound);
+    }
+    let target = format!("subscriber/{}", subscriber_id);
+    if !add.is_empty() {
+        add_subscriber_tags(&pool, subscriber_id, &add).await?;
+        record_audit_event(
+            &pool,
+            identity.user_id,
+            AuditAction::TagSubscriber,
+            Some(&target),
+            &metadata,
+        )
+        .await?;
+    }
+    if !remove.is_empty() {
+        remove_subscriber_tags(&pool, subscriber_id, &remove).await?;
+        record_audit_event(
+            &pool,
+            identity.user_id,
+            AuditAction::UntagSubscriber,
+            Some(&target),
+            &metadata,
+        )
+        .await?;
+    }
+    let tags = get_subscriber_tags(&pool, subscriber_id).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "tags": tags })))
+}
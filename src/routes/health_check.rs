@@ -1,6 +1,147 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use crate::configuration::Settings;
+use crate::migrations::pending_migrations;
 
 
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
-}
\ No newline at end of file
+}
+
+/// 就绪检查需要访问的外部依赖
+pub struct ReadinessChecks {
+    redis_client: redis::Client,
+    /// 未开启邮件服务检查时为'None'
+    email_provider_url: Option<String>,
+    http_client: reqwest::Client,
+    timeout: Duration,
+}
+
+impl ReadinessChecks {
+    pub fn new(configuration: &Settings) -> Result<Self, anyhow::Error> {
+        let timeout = configuration.health.timeout();
+        let email_provider_url = configuration
+            .health
+            .check_email_provider
+            .then(|| configuration.email_client.base_url.clone());
+        Ok(Self {
+            redis_client: redis::Client::open(configuration.redis_uri.expose_secret().as_str())?,
+            email_provider_url,
+            http_client: reqwest::Client::builder().timeout(timeout).build()?,
+            timeout,
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ReadinessReport {
+    /// 'ok'：全部正常；'degraded'：只有非关键检查失败；'fail'：有关键检查失败
+    status: &'static str,
+    checks: Vec<CheckReport>,
+}
+
+/// 该接口不需要认证，失败原因只写入日志，不出现在响应中
+#[derive(serde::Serialize)]
+struct CheckReport {
+    name: &'static str,
+    /// 'up'或'down'
+    status: &'static str,
+    critical: bool,
+    latency_ms: u128,
+}
+
+/// 检查应用程序能否处理请求，返回每项检查的状态和耗时
+/// - 数据库、Redis和数据库迁移是关键检查，任一失败时返回503
+/// - 各项检查并发执行，每项都有超时时间
+pub async fn readiness_check(
+    pool: web::Data<PgPool>,
+    checks: web::Data<ReadinessChecks>,
+) -> HttpResponse {
+    let (database, redis, migrations) = tokio::join!(
+        run_check("database", true, checks.timeout, check_database(&pool)),
+        run_check("redis", true, checks.timeout, check_redis(&checks.redis_client)),
+        run_check("migrations", true, checks.timeout, check_migrations(&pool)),
+    );
+    let mut reports = vec![database, redis, migrations];
+    if let Some(url) = &checks.email_provider_url {
+        reports.push(
+            run_check(
+                "email_provider",
+                false,
+                checks.timeout,
+                check_email_provider(&checks.http_client, url),
+            )
+            .await,
+        );
+    }
+
+    let failed = |critical: bool| {
+        reports
+            .iter()
+            .any(|r| r.critical == critical && r.status == "down")
+    };
+    let (status, mut response) = if failed(true) {
+        ("fail", HttpResponse::ServiceUnavailable())
+    } else if failed(false) {
+        ("degraded", HttpResponse::Ok())
+    } else {
+        ("ok", HttpResponse::Ok())
+    };
+    response.json(ReadinessReport {
+        status,
+        checks: reports,
+    })
+}
+
+async fn run_check(
+    name: &'static str,
+    critical: bool,
+    timeout: Duration,
+    check: impl Future<Output = Result<(), anyhow::Error>>,
+) -> CheckReport {
+    let start = Instant::now();
+    let error = match tokio::time::timeout(timeout, check).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(format!("{:#}", e)),
+        Err(_) => Some(format!("Timed out after {}ms.", timeout.as_millis())),
+    };
+    if let Some(e) = &error {
+        tracing::warn!(check = name, error = %e, "Readiness check failed");
+    }
+    CheckReport {
+        name,
+        status: if error.is_none() { "up" } else { "down" },
+        critical,
+        latency_ms: start.elapsed().as_millis(),
+    }
+}
+
+async fn check_database(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+async fn check_redis(client: &redis::Client) -> Result<(), anyhow::Error> {
+    let mut connection = client.get_async_connection().await?;
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut connection)
+        .await?;
+    Ok(())
+}
+
+async fn check_migrations(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let pending = pending_migrations(pool).await?;
+    if !pending.is_empty() {
+        anyhow::bail!("Pending migrations: {:?}.", pending);
+    }
+    Ok(())
+}
+
+/// 邮件服务返回任何HTTP响应都说明可以访问
+async fn check_email_provider(client: &reqwest::Client, url: &str) -> Result<(), anyhow::Error> {
+    client.get(url).send().await?;
+    Ok(())
+}
@@ -1,8 +1,9 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::{IncomingFlashMessages};
 use std::fmt::Write;
 use hmac::{Hmac, Mac};
 use secrecy::ExposeSecret;
+use crate::authentication::OidcClient;
 use crate::startup::HmacSecret;
 
 
@@ -31,7 +32,10 @@ impl QueryParams {
 }
 
 // 不再需要访问原始的请求了
-pub async fn login_form(flash_message: IncomingFlashMessages) -> HttpResponse {
+pub async fn login_form(
+    flash_message: IncomingFlashMessages,
+    oidc_client: Option<web::Data<OidcClient>>,
+) -> HttpResponse {
     let mut error_html = String::new();
 
     // 展示所有的消息层级，而不仅仅是错误
@@ -39,6 +43,15 @@ pub async fn login_form(flash_message: IncomingFlashMessages) -> HttpResponse {
         writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    // 配置了OIDC时，在密码表单旁提供单点登录的入口
+    let sso_html = match oidc_client {
+        Some(client) => format!(
+            r#"<p><a href="/login/oidc">Log in with {}</a></p>"#,
+            htmlescape::encode_minimal(client.display_name()),
+        ),
+        None => String::new(),
+    };
+
     HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -68,6 +81,7 @@ pub async fn login_form(flash_message: IncomingFlashMessages) -> HttpResponse {
                             </label>
                             <button type="submit">Login</button>
                         </form>
+                        {sso_html}
                     </body>
                 </html>
             "#,
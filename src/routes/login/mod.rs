@@ -2,3 +2,5 @@ mod get;
 pub use get::login_form;
 mod post;
 pub use post::login;
+mod oidc;
+pub use oidc::{oidc_callback, oidc_login};
@@ -0,0 +1,107 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use crate::audit::{record_audit_event, AuditAction};
+use crate::authentication::{find_or_provision_oidc_user, start_session, OidcClient};
+use crate::configuration::PasswordHashingSettings;
+use crate::request_metadata::RequestMetadata;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// 跳转到身份提供方，开始单点登录
+/// - 未配置OIDC时返回404
+#[tracing::instrument(name = "GET /login/oidc", skip(client, session))]
+pub async fn oidc_login(
+    client: Option<web::Data<OidcClient>>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let client = match client {
+        Some(client) => client,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    match client.authorization_url().await {
+        Ok((url, login_state)) => {
+            session.insert_oidc_login_state(&login_state).map_err(e500)?;
+            Ok(see_other(url.as_str()))
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to start the single sign-on",
+            );
+            FlashMessage::error("Single sign-on is currently unavailable.").send();
+            Ok(see_other("/login"))
+        }
+    }
+}
+
+/// 身份提供方完成认证后的回调
+#[tracing::instrument(
+    name = "GET /login/oidc/callback",
+    skip(query, client, session, pool, hashing_settings, metadata),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn oidc_callback(
+    query: web::Query<CallbackQuery>,
+    client: Option<web::Data<OidcClient>>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    hashing_settings: web::Data<PasswordHashingSettings>,
+    metadata: RequestMetadata,
+) -> Result<HttpResponse, actix_web::Error> {
+    let client = match client {
+        Some(client) => client,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    // 无论成功与否，保存的state只能使用一次
+    let login_state = session.take_oidc_login_state().map_err(e500)?;
+
+    let identity = match (login_state, &query.code, &query.state, &query.error) {
+        (Some(login_state), Some(code), Some(state), None) => {
+            client.exchange_code(code, state, &login_state).await
+        }
+        (_, _, _, Some(error)) => Err(anyhow::anyhow!("The identity provider returned '{}'", error)),
+        _ => Err(anyhow::anyhow!("The callback does not belong to a pending single sign-on")),
+    };
+    let identity = match identity {
+        Ok(identity) => identity,
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Single sign-on failed",
+            );
+            FlashMessage::error("Single sign-on failed.").send();
+            return Ok(see_other("/login"));
+        }
+    };
+
+    let user_id = find_or_provision_oidc_user(&pool, &client, &identity, &hashing_settings)
+        .await
+        .map_err(e500)?;
+    match user_id {
+        Some(user_id) => {
+            tracing::Span::current()
+                .record("user_id", tracing::field::display(&user_id));
+            start_session(&session, &pool, user_id, &metadata)
+                .await
+                .map_err(e500)?;
+            record_audit_event(&pool, user_id, AuditAction::Login, Some("sso"), &metadata)
+                .await
+                .map_err(e500)?;
+            Ok(see_other("/admin/dashboard"))
+        }
+        None => {
+            FlashMessage::error("No account is associated with this identity.").send();
+            Ok(see_other("/login"))
+        }
+    }
+}
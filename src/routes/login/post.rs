@@ -9,8 +9,13 @@ use sqlx::PgPool;
 use crate::authentication::validate_credentials;
 use crate::authentication::Credentials;
 use crate::authentication::AuthError;
+use crate::authentication::start_session;
+use crate::audit::{record_audit_event, AuditAction};
+use crate::configuration::PasswordHashingSettings;
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
+use crate::request_metadata::RequestMetadata;
+use crate::metrics::METRICS;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
@@ -20,7 +25,7 @@ pub struct FormData {
 
 #[tracing::instrument(
     name="POST /login"
-    skip(form, pool, session),
+    skip(form, pool, session, hashing_settings, metadata),
     fields(
         username=tracing::field::Empty,
         user_id=tracing::field::Empty,
@@ -30,6 +35,8 @@ pub async fn login(
     form: web::Form<FormData>, 
     pool: web::Data<PgPool>,
     session: TypedSession,
+    hashing_settings: web::Data<PasswordHashingSettings>,
+    metadata: RequestMetadata,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials = Credentials {
         username: form.0.username,
@@ -37,16 +44,17 @@ pub async fn login(
     };
     tracing::Span::current()
         .record("username", &tracing::field::display(&credentials.username));
-    match validate_credentials(credentials, &pool).await {
+    match validate_credentials(credentials, &pool, &hashing_settings).await {
         Ok(user_id) => {
+            METRICS.logins.with_label_values(&["success"]).inc();
             tracing::Span::current()
                 .record("user_id", &tracing::field::display(&user_id));
-            // 用户登录时轮换会话令牌
-            session.renew();
-            session.insert_user_id(user_id)
-                .map_err(|e| login_redirect(
-                    LoginError::UnexpectedError(e.into())
-                ))?;
+            start_session(&session, &pool, user_id, &metadata)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            record_audit_event(&pool, user_id, AuditAction::Login, Some("password"), &metadata)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
                 .finish()
@@ -54,7 +62,10 @@ pub async fn login(
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    METRICS.logins.with_label_values(&["failure"]).inc();
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
             Err(login_redirect(e))
@@ -4,9 +4,17 @@ mod subscriptions;
 pub use subscriptions::*;
 mod subscriptions_confirm;
 pub use subscriptions_confirm::*;
+mod subscriptions_data;
+pub use subscriptions_data::*;
+mod subscriptions_preferences;
+pub use subscriptions_preferences::*;
+mod tracking;
+pub use tracking::*;
 mod home;
 pub use home::*;
 mod login;
 pub use login::*;
 mod admin;
-pub use admin::*;
\ No newline at end of file
+pub use admin::*;
+mod api;
+pub use api::*;
\ No newline at end of file
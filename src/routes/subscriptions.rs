@@ -1,40 +1,113 @@
+use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse};
 use actix_web::ResponseError;
 use anyhow::Context;
 use chrono::Utc;
+use htmlescape::encode_minimal;
 use rand::{thread_rng, Rng};
 use rand::distributions::Alphanumeric;
 use reqwest::StatusCode;
 use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Write;
 use uuid::Uuid;
 use unicode_segmentation::UnicodeSegmentation;
 use crate::{domain::{NewSubscriber, SubscriberEmail, SubscriberName}, email_client::EmailClient};
-use crate::startup::ApplicationBaseUrl;
-
-#[derive(serde::Deserialize)]
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::configuration::ConsentSettings;
+use crate::consent::{insert_consent_record, parse_consent_label, ConsentEvidence, DEFAULT_CONSENT_SOURCE};
+use crate::mailing_lists::{add_list_memberships, list_mailing_lists, parse_list_slugs, resolve_mailing_lists, ListSelectionError};
+use crate::metrics::METRICS;
+use crate::request_metadata::RequestMetadata;
+use crate::subscriber_data::is_erased_email;
+use crate::utils::e500;
+
+/// - source: 可选，提交注册的表单或渠道
+/// - topics: 可选，可以重复提交多个邮件列表，未提交时订阅默认列表
 pub struct FormData {
     email: String,
     name: String,
+    source: Option<String>,
+    topics: Vec<String>,
+}
+
+/// 注册表单，列出所有邮件列表供订阅者选择，默认列表预先勾选
+pub async fn subscribe_form(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = list_mailing_lists(&pool).await.map_err(e500)?;
+    let mut topics_html = String::new();
+    for list in &lists {
+        writeln!(
+            topics_html,
+            r#"<label><input type="checkbox" name="topics" value="{}"{}> {}</label> <small>{}</small><br>"#,
+            encode_minimal(&list.slug),
+            if list.is_default { " checked" } else { "" },
+            encode_minimal(&list.name),
+            encode_minimal(&list.description),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribe</title>
+</head>
+<body>
+    <form action="/subscriptions" method="post">
+        <label>Name <input type="text" name="name"></label><br>
+        <label>Email <input type="email" name="email"></label><br>
+        <fieldset>
+            <legend>Topics</legend>
+            {topics_html}
+        </fieldset>
+        <input hidden type="text" name="source" value="{DEFAULT_CONSENT_SOURCE}">
+        <button type="submit">Subscribe</button>
+    </form>
+</body>
+</html>"#,
+        )))
 }
 
 // 讲一个跨度绑定到函数上
+// 表单中的'topics'字段可以出现多次，因此按键值对列表解析
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(form, pool, email_client, base_url, hmac_secret, consent_settings, metadata),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name,
+        subscriber_email = tracing::field::Empty,
+        subscriber_name = tracing::field::Empty,
     )
 )]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    form: web::Form<Vec<(String, String)>>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    consent_settings: web::Data<ConsentSettings>,
+    metadata: RequestMetadata,
 ) -> Result<HttpResponse, SubscriberError> {
-    let new_subscriber = form.0
+    let form: FormData = form.0
         .try_into()
         .map_err(SubscriberError::ValidationError)?;
+    tracing::Span::current()
+        .record("subscriber_email", tracing::field::display(&form.email))
+        .record("subscriber_name", tracing::field::display(&form.name));
+    let consent_evidence = parse_consent_evidence(&form, &consent_settings)
+        .map_err(SubscriberError::ValidationError)?;
+    let new_subscriber: NewSubscriber = form
+        .try_into()
+        .map_err(SubscriberError::ValidationError)?;
+    // 与正常注册返回相同的响应，不泄露该邮箱曾被删除
+    if is_erased_email(&pool, &hmac_secret, new_subscriber.email.as_ref()).await? {
+        tracing::info!("Ignoring a subscription for an erased email address");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let list_ids = resolve_mailing_lists(&pool, &new_subscriber.topics).await?;
     let mut transaction = pool.begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool.")?;
@@ -42,6 +115,8 @@ pub async fn subscribe(
     let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
+    insert_consent_record(&mut transaction, subscriber_id, &consent_evidence, &metadata).await?;
+    add_list_memberships(&mut transaction, subscriber_id, &list_ids).await?;
 
     let subscription_token = generate_subscription_token();
     // '?'操作符帮我们自动调用'Into' trait,这样无须显示的调用'map_err'方法
@@ -51,6 +126,7 @@ pub async fn subscribe(
     transaction.commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    METRICS.subscriptions.inc();
 
     send_confirmation_email(
         &email_client, 
@@ -102,11 +178,48 @@ pub fn is_valid_name(s: &str) -> bool {
     !(is_empty_or_whitespace || is_too_long || contains_forbidden_characters)
 }
 
+/// 解析表单中的同意信息，未提交来源时使用默认来源
+/// - 同意声明的版本总是使用服务端配置的当前版本，不信任客户端提交的版本
+fn parse_consent_evidence(
+    form: &FormData,
+    consent_settings: &ConsentSettings,
+) -> Result<ConsentEvidence, String> {
+    let source = match form.source.as_deref().map(str::trim) {
+        Some(source) if !source.is_empty() => parse_consent_label(source.to_string(), "source")?,
+        _ => DEFAULT_CONSENT_SOURCE.to_string(),
+    };
+    Ok(ConsentEvidence {
+        source,
+        consent_text_version: consent_settings.text_version.clone(),
+    })
+}
+
 /// 正确解析出表单中的name、email信息
 pub fn parse_subscriber(form: FormData) -> Result<NewSubscriber, String> {
-        let name = SubscriberName::parse(form.name)?;
-        let email = SubscriberEmail::parse(form.email)?;
-        Ok(NewSubscriber {name, email})
+        form.try_into()
+}
+
+impl TryFrom<Vec<(String, String)>> for FormData {
+    type Error = String;
+    fn try_from(fields: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let (mut email, mut name, mut source) = (None, None, None);
+        let mut topics = Vec::new();
+        for (field, value) in fields {
+            match field.as_str() {
+                "email" => email = Some(value),
+                "name" => name = Some(value),
+                "source" => source = Some(value),
+                "topics" => topics.push(value),
+                _ => {}
+            }
+        }
+        Ok(Self {
+            email: email.ok_or("The email is missing.")?,
+            name: name.ok_or("The name is missing.")?,
+            source,
+            topics,
+        })
+    }
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -114,7 +227,8 @@ impl TryFrom<FormData> for NewSubscriber {
     fn try_from(form: FormData) -> Result<Self, Self::Error> {
         let name = SubscriberName::parse(form.name)?;
         let email = SubscriberEmail::parse(form.email)?;
-        Ok(Self { email, name})
+        let topics = parse_list_slugs(form.topics)?;
+        Ok(Self { email, name, topics })
     }
 }
 
@@ -153,7 +267,7 @@ pub async fn send_confirmation_email(
 }
 
 /// 生成随机的长度为25个字符且大小写敏感的订阅令牌
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -292,6 +406,15 @@ impl std::fmt::Debug for SubscriberError {
     }
 }
 
+impl From<ListSelectionError> for SubscriberError {
+    fn from(e: ListSelectionError) -> Self {
+        match e {
+            ListSelectionError::Invalid(message) => Self::ValidationError(message),
+            ListSelectionError::Unexpected(e) => Self::UnexpectedError(e),
+        }
+    }
+}
+
 impl ResponseError for SubscriberError {
     fn status_code(&self) -> reqwest::StatusCode {
         match self {
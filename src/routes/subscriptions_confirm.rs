@@ -1,6 +1,12 @@
 use actix_web::{HttpResponse, web};
-use sqlx::PgPool;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
+use crate::consent::record_consent_confirmation;
+use crate::metrics::METRICS;
+use crate::request_metadata::RequestMetadata;
+use crate::startup::HmacSecret;
+use crate::subscriber_data::is_erased_email;
 
 /// 在传入的请求中所预期的所有查询参数
 /// 参数类型web:Query<Parameters> 仅在成功
@@ -11,13 +17,16 @@ pub struct Parameters {
 }
 
 /// 根据token变更subscriber状态
+/// - 修改邮箱与确认在同一个事务中完成；新邮箱已不可用时返回400，邮箱保持不变
 #[tracing::instrument(
     name = "Confirm a pending subscriber",
-    skip(parameters, pool),
+    skip(parameters, pool, hmac_secret, metadata),
 )]
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+    metadata: RequestMetadata,
 ) -> HttpResponse {
     let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
         Ok(id) => id,
@@ -26,28 +35,153 @@ pub async fn confirm(
     match id {
         None => HttpResponse::Unauthorized().finish(),
         Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
+            let mut transaction = match pool.begin().await {
+                Ok(transaction) => transaction,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+            let email_change = apply_email_change(
+                &mut transaction,
+                &pool,
+                &hmac_secret,
+                &parameters.subscription_token,
+            )
+            .await;
+            match email_change {
+                Ok(EmailChangeOutcome::Unavailable) => {
+                    return HttpResponse::BadRequest()
+                        .body("The new email address is no longer available.");
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to apply an email change",
+                    );
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+            if confirm_subscriber(&mut transaction, subscriber_id).await.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            if transaction.commit().await.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            if let Err(e) = record_consent_confirmation(&pool, subscriber_id, &metadata).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to record the consent confirmation",
+                );
                 return HttpResponse::InternalServerError().finish();
             }
+            METRICS.confirmations.inc();
             HttpResponse::Ok().finish()
         }
     }
 }
 
+/// 确认链接对订阅者邮箱的影响
+#[derive(Debug, PartialEq, Eq)]
+pub enum EmailChangeOutcome {
+    /// 注册时发出的令牌，不涉及修改邮箱
+    NotRequested,
+    Applied,
+    /// 新邮箱在申请之后已被其他订阅者使用或已被删除，修改未生效
+    Unavailable,
+}
+
+/// 如果令牌是修改邮箱时发出的，用令牌中的新邮箱替换订阅者的邮箱
+/// - 申请时的检查可能已经过时，这里在确认的事务中重新检查新邮箱
+/// - 令牌使用后即删除，注册时发出的令牌不受影响
+#[tracing::instrument(
+    name = "Apply a confirmed email change",
+    skip(transaction, pool, hmac_secret, subscription_token),
+)]
+pub async fn apply_email_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    pool: &PgPool,
+    hmac_secret: &HmacSecret,
+    subscription_token: &str,
+) -> Result<EmailChangeOutcome, anyhow::Error> {
+    let change = sqlx::query!(
+        r#"
+        SELECT subscriber_id, new_email AS "new_email!"
+        FROM subscription_tokens
+        WHERE subscription_token = $1 AND new_email IS NOT NULL
+        FOR UPDATE
+        "#,
+        subscription_token,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up an email change token.")?;
+    let (subscriber_id, new_email) = match change {
+        Some(change) => (change.subscriber_id, change.new_email),
+        None => return Ok(EmailChangeOutcome::NotRequested),
+    };
+
+    // 墓碑只会增加，不需要在事务中检查
+    if is_erased_email(pool, hmac_secret, &new_email).await? {
+        return Ok(EmailChangeOutcome::Unavailable);
+    }
+    let is_taken = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM subscriptions WHERE lower(email) = lower($1) AND id <> $2
+        ) AS "is_taken!"
+        "#,
+        new_email,
+        subscriber_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to check whether the new email is already subscribed.")?
+    .is_taken;
+    if is_taken {
+        return Ok(EmailChangeOutcome::Unavailable);
+    }
+
+    let updated = sqlx::query!(
+        r#"UPDATE subscriptions SET email = $1 WHERE id = $2"#,
+        new_email,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await;
+    match updated {
+        // 与同时注册的订阅者发生冲突
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => {
+            return Ok(EmailChangeOutcome::Unavailable);
+        }
+        updated => {
+            updated.context("Failed to update the email of a subscriber.")?;
+        }
+    }
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscription_token = $1"#,
+        subscription_token,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete an email change token.")?;
+    Ok(EmailChangeOutcome::Applied)
+}
+
 /// 将status字段从pending_conform变更为confirmed
 #[tracing::instrument(
     name = "Mark subscriber as confirmed",
-    skip(pool, subscriber_id),
+    skip(transaction, subscriber_id),
 )]
 pub async fn confirm_subscriber(
-    pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
         subscriber_id,
     )
-    .execute(pool)
+    .execute(transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
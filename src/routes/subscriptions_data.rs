@@ -0,0 +1,186 @@
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscriber_data::{
+    data_request_token, erase_subscriber_data, export_subscriber_data, find_subscriber_by_email,
+    verify_data_request_token, DataRequestKind,
+};
+use crate::utils::{e400, e500};
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestFormData {
+    email: String,
+    request_type: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestToken {
+    token: String,
+}
+
+/// 订阅者申请导出或删除自己数据的表单
+pub async fn data_request_form() -> HttpResponse {
+    page(
+        "Your data",
+        r#"<form action="/subscriptions/data" method="post">
+        <label>Email <input type="email" name="email"></label>
+        <label><input type="radio" name="request_type" value="export" checked> Send me a copy of my data</label>
+        <label><input type="radio" name="request_type" value="erase"> Delete my data</label>
+        <button type="submit">Send me a link</button>
+    </form>"#,
+    )
+}
+
+/// 向申请的邮箱发送带签名的链接，只有能收到该邮件的人才能导出或删除数据
+/// - 无论邮箱是否订阅、邮件是否发送成功，返回的页面都相同，避免泄露订阅者名单
+#[tracing::instrument(
+    name = "Request a subscriber data link",
+    skip(form, pool, email_client, base_url, hmac_secret),
+    fields(request_type = %form.request_type)
+)]
+pub async fn request_data_link(
+    form: web::Form<DataRequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DataRequestFormData { email, request_type } = form.0;
+    let kind = DataRequestKind::try_from(request_type).map_err(e400)?;
+    let email = SubscriberEmail::parse(email).map_err(e400)?;
+
+    if find_subscriber_by_email(&pool, email.as_ref()).await.map_err(e500)?.is_some() {
+        let token = data_request_token(&hmac_secret, kind, email.as_ref());
+        let link = format!(
+            "{}/subscriptions/data/{}?token={}",
+            base_url.0,
+            kind.as_str(),
+            urlencoding::encode(&token),
+        );
+        let (subject, action) = match kind {
+            DataRequestKind::Export => ("Your data export", "download a copy of the data we hold on you"),
+            DataRequestKind::Erasure => ("Delete your data", "permanently delete the data we hold on you"),
+        };
+        let plain_body = format!(
+            "Visit {} to {}.\nThe link expires in 24 hours. If you did not ask for this, you can ignore this email.",
+            link, action,
+        );
+        let html_body = format!(
+            "Click <a href=\"{}\">here</a> to {}.<br />\
+            The link expires in 24 hours. If you did not ask for this, you can ignore this email.",
+            link, action,
+        );
+        if let Err(e) = email_client
+            .send_email(&email, subject, &html_body, &plain_body)
+            .await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a subscriber data link",
+            );
+        }
+    }
+
+    Ok(page(
+        "Your data",
+        &format!(
+            "<p>If {} is subscribed, we have sent it a link to complete your request.</p>",
+            encode_minimal(email.as_ref()),
+        ),
+    ))
+}
+
+/// 以JSON格式下载为该邮箱保存的所有数据
+#[tracing::instrument(name = "Export subscriber data", skip(query, pool, hmac_secret))]
+pub async fn export_data(
+    query: web::Query<DataRequestToken>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match verify_data_request_token(&hmac_secret, DataRequestKind::Export, &query.token) {
+        Ok(email) => email,
+        Err(e) => return Ok(invalid_link(e)),
+    };
+    match export_subscriber_data(&pool, &email).await.map_err(e500)? {
+        Some(export) => Ok(HttpResponse::Ok()
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename("subscriber-data.json".into())],
+            })
+            .json(export)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// 删除数据前的确认页面
+/// - 链接只展示确认表单，避免邮件客户端预取链接时误删数据
+pub async fn erase_data_form(
+    query: web::Query<DataRequestToken>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    let email = match verify_data_request_token(&hmac_secret, DataRequestKind::Erasure, &query.token) {
+        Ok(email) => email,
+        Err(e) => return invalid_link(e),
+    };
+    page(
+        "Delete your data",
+        &format!(
+            r#"<p>This will permanently delete your subscription and all data we hold on {}.</p>
+    <form action="/subscriptions/data/erase" method="post">
+        <input hidden type="text" name="token" value="{}">
+        <button type="submit">Delete my data</button>
+    </form>"#,
+            encode_minimal(&email),
+            encode_minimal(&query.token),
+        ),
+    )
+}
+
+/// 删除为该邮箱保存的所有数据
+#[tracing::instrument(name = "Erase subscriber data", skip(form, pool, hmac_secret))]
+pub async fn erase_data(
+    form: web::Form<DataRequestToken>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match verify_data_request_token(&hmac_secret, DataRequestKind::Erasure, &form.token) {
+        Ok(email) => email,
+        Err(e) => return Ok(invalid_link(e)),
+    };
+    // 重复提交时数据已经不存在，结果相同
+    erase_subscriber_data(&pool, &hmac_secret, &email).await.map_err(e500)?;
+    Ok(page("Delete your data", "<p>Your data has been deleted.</p>"))
+}
+
+pub(super) fn invalid_link(e: anyhow::Error) -> HttpResponse {
+    tracing::warn!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Rejected a signed subscriber link",
+    );
+    HttpResponse::Unauthorized()
+        .content_type(ContentType::html())
+        .body("<p>The link is invalid or has expired.</p>")
+}
+
+pub(super) fn page(title: &str, body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    {body}
+</body>
+</html>"#,
+        ))
+}
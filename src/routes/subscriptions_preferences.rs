@@ -0,0 +1,330 @@
+use actix_web::error::InternalError;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+use crate::domain::{ListSlug, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::mailing_lists::{
+    get_subscriber_lists, list_mailing_lists, parse_list_slugs, resolve_mailing_lists,
+    ListSelectionError,
+};
+use crate::preferences::{
+    store_email_change_token, unsubscribe, update_preferences, verify_preferences_token,
+    EmailFormat, PauseChange, PreferencesUpdate, PAUSE_OPTIONS_IN_DAYS,
+};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscriber_data::{find_subscriber_by_email, is_erased_email};
+use crate::subscribers::{get_subscriber, SubscriberRecord};
+use crate::utils::{e500, see_other};
+use super::generate_subscription_token;
+use super::subscriptions_data::{invalid_link, page};
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesToken {
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChangeEmailFormData {
+    token: String,
+    email: String,
+}
+
+/// 偏好设置页面，通过每期简报末尾的链接访问
+pub async fn preferences_page(
+    query: web::Query<PreferencesToken>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = subscriber_from_token(&pool, &hmac_secret, &query.token).await?;
+    if subscriber.status == "unsubscribed" {
+        return Ok(page(
+            "Your subscription",
+            "<p>You have unsubscribed from our newsletter.</p>",
+        ));
+    }
+
+    // 提示信息中可能包含订阅者提交的内容，需要转义
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let memberships = get_subscriber_lists(&pool, subscriber.id).await.map_err(e500)?;
+    let mut topics_html = String::new();
+    for list in list_mailing_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            topics_html,
+            r#"<label><input type="checkbox" name="topics" value="{}"{}> {}</label><br>"#,
+            encode_minimal(&list.slug),
+            if memberships.contains(&list.slug) { " checked" } else { "" },
+            encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+    let mut format_html = String::new();
+    for (format, label) in [
+        (EmailFormat::Html, "HTML with a plain-text fallback"),
+        (EmailFormat::Text, "Plain text only"),
+    ] {
+        writeln!(
+            format_html,
+            r#"<label><input type="radio" name="email_format" value="{}"{}> {}</label><br>"#,
+            format.as_str(),
+            if subscriber.email_format == format.as_str() { " checked" } else { "" },
+            label,
+        )
+        .unwrap();
+    }
+    // 暂停中的订阅者默认保持暂停，避免修改其他选项时意外恢复投递
+    let mut pause_html = match subscriber.paused_until {
+        Some(until) if until > chrono::Utc::now() => format!(
+            r#"<option value="keep" selected>Stay paused until {}</option><option value="">Resume now</option>"#,
+            until.format("%Y-%m-%d"),
+        ),
+        _ => r#"<option value="">Keep sending</option>"#.to_string(),
+    };
+    for days in PAUSE_OPTIONS_IN_DAYS {
+        write!(pause_html, r#"<option value="{0}">Pause for {0} days</option>"#, days).unwrap();
+    }
+    let token = encode_minimal(&query.token);
+
+    Ok(page(
+        "Your subscription",
+        &format!(
+            r#"{msg_html}
+    <h2>Your subscription</h2>
+    <form action="/subscriptions/preferences" method="post">
+        <label>Name <input type="text" name="name" value="{name}"></label><br>
+        <fieldset>
+            <legend>Topics</legend>
+            {topics_html}
+        </fieldset>
+        <fieldset>
+            <legend>Format</legend>
+            {format_html}
+        </fieldset>
+        <label>Delivery <select name="pause_days">{pause_html}</select></label><br>
+        <input hidden type="text" name="token" value="{token}">
+        <button type="submit">Save preferences</button>
+    </form>
+    <h2>Change your email address</h2>
+    <p>Your subscription is sent to {email}. We will send a confirmation link to the new address.</p>
+    <form action="/subscriptions/preferences/email" method="post">
+        <label>New email <input type="email" name="email"></label>
+        <input hidden type="text" name="token" value="{token}">
+        <button type="submit">Change email</button>
+    </form>
+    <h2>Unsubscribe</h2>
+    <form action="/subscriptions/preferences/unsubscribe" method="post">
+        <input hidden type="text" name="token" value="{token}">
+        <button type="submit">Unsubscribe</button>
+    </form>"#,
+            name = encode_minimal(&subscriber.name),
+            email = encode_minimal(&subscriber.email),
+        ),
+    ))
+}
+
+/// 保存偏好设置
+/// - 表单中的'topics'字段可以出现多次，因此按键值对列表解析
+/// - 至少要选择一个邮件列表，不想再收到简报应该退订
+#[tracing::instrument(name = "Save subscriber preferences", skip(form, pool, hmac_secret))]
+pub async fn save_preferences(
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (mut token, mut name, mut email_format, mut pause_days) =
+        (String::new(), String::new(), String::new(), String::new());
+    let mut topics = Vec::new();
+    for (field, value) in form.into_inner() {
+        match field.as_str() {
+            "token" => token = value,
+            "name" => name = value,
+            "email_format" => email_format = value,
+            "pause_days" => pause_days = value,
+            "topics" => topics.push(value),
+            _ => {}
+        }
+    }
+    let subscriber = subscriber_from_token(&pool, &hmac_secret, &token).await?;
+    if subscriber.status == "unsubscribed" {
+        return Ok(preferences_redirect(&token));
+    }
+
+    let (name, email_format, pause, topics) =
+        match parse_preferences(name, email_format, pause_days, topics) {
+            Ok(parsed) => parsed,
+            Err(message) => {
+                FlashMessage::error(message).send();
+                return Ok(preferences_redirect(&token));
+            }
+        };
+    let list_ids = match resolve_mailing_lists(&pool, &topics).await {
+        Ok(list_ids) => list_ids,
+        Err(ListSelectionError::Invalid(message)) => {
+            FlashMessage::error(message).send();
+            return Ok(preferences_redirect(&token));
+        }
+        Err(ListSelectionError::Unexpected(e)) => return Err(e500(e)),
+    };
+    let update = PreferencesUpdate {
+        name,
+        email_format,
+        list_ids,
+        pause,
+    };
+    update_preferences(&pool, subscriber.id, &update).await.map_err(e500)?;
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(preferences_redirect(&token))
+}
+
+/// 修改邮箱：确认链接发送到新邮箱，确认后才生效
+#[tracing::instrument(
+    name = "Request an email change",
+    skip(form, pool, email_client, base_url, hmac_secret)
+)]
+pub async fn change_email(
+    form: web::Form<ChangeEmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ChangeEmailFormData { token, email } = form.0;
+    let subscriber = subscriber_from_token(&pool, &hmac_secret, &token).await?;
+    if subscriber.status == "unsubscribed" {
+        return Ok(preferences_redirect(&token));
+    }
+    let new_email = match SubscriberEmail::parse(email.trim().to_string()) {
+        Ok(new_email) => new_email,
+        Err(message) => {
+            FlashMessage::error(message).send();
+            return Ok(preferences_redirect(&token));
+        }
+    };
+    // 新邮箱已被使用或已被删除时不发送确认链接，但响应与成功时相同，
+    // 避免通过这个页面探测某个邮箱是否订阅过；确认时会再次检查
+    let unavailable = find_subscriber_by_email(&pool, new_email.as_ref())
+        .await
+        .map_err(e500)?
+        .is_some()
+        || is_erased_email(&pool, &hmac_secret, new_email.as_ref()).await.map_err(e500)?;
+    if !unavailable {
+        send_email_change_link(&pool, &email_client, &base_url.0, subscriber.id, &new_email)
+            .await
+            .map_err(e500)?;
+    }
+
+    FlashMessage::info(format!(
+        "We have sent a confirmation link to {}. Your email address will change once you confirm it.",
+        new_email.as_ref(),
+    ))
+    .send();
+    Ok(preferences_redirect(&token))
+}
+
+/// 保存修改邮箱的令牌，并将确认链接发送到新邮箱
+async fn send_email_change_link(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let subscription_token = generate_subscription_token();
+    store_email_change_token(pool, subscriber_id, &subscription_token, new_email.as_ref()).await?;
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        base_url, subscription_token,
+    );
+    let plain_body = format!(
+        "Visit {} to confirm your new email address.",
+        confirmation_link
+    );
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to confirm your new email address.",
+        confirmation_link
+    );
+    email_client
+        .send_email(new_email, "Confirm your new email address", &html_body, &plain_body)
+        .await
+        .context("Failed to send the email change confirmation.")?;
+    Ok(())
+}
+
+/// 退订
+#[tracing::instrument(name = "Unsubscribe via the preferences page", skip(form, pool, hmac_secret))]
+pub async fn unsubscribe_via_preferences(
+    form: web::Form<PreferencesToken>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = subscriber_from_token(&pool, &hmac_secret, &form.token).await?;
+    unsubscribe(&pool, subscriber.id).await.map_err(e500)?;
+    Ok(page(
+        "Your subscription",
+        "<p>You have been unsubscribed. You will not receive any more newsletters.</p>",
+    ))
+}
+
+/// 校验令牌并查询对应的订阅者
+/// - 令牌无效时返回401，订阅者的数据已被删除时返回404
+async fn subscriber_from_token(
+    pool: &PgPool,
+    hmac_secret: &HmacSecret,
+    token: &str,
+) -> Result<SubscriberRecord, actix_web::Error> {
+    let subscriber_id = match verify_preferences_token(hmac_secret, token) {
+        Ok(subscriber_id) => subscriber_id,
+        Err(e) => {
+            let response = invalid_link(e);
+            return Err(InternalError::from_response("Invalid preferences link", response).into());
+        }
+    };
+    match get_subscriber(pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => Ok(subscriber),
+        None => Err(InternalError::from_response(
+            "Unknown subscriber",
+            HttpResponse::NotFound().finish(),
+        )
+        .into()),
+    }
+}
+
+/// 解析偏好设置表单，错误信息直接展示给订阅者
+fn parse_preferences(
+    name: String,
+    email_format: String,
+    pause_days: String,
+    topics: Vec<String>,
+) -> Result<(SubscriberName, EmailFormat, PauseChange, Vec<ListSlug>), String> {
+    let name = SubscriberName::parse(name.trim().to_string())?;
+    let email_format = EmailFormat::try_from(email_format)?;
+    let pause = match pause_days.trim() {
+        "keep" => PauseChange::Keep,
+        "" => PauseChange::Resume,
+        days => match days.parse::<i64>() {
+            Ok(days) if PAUSE_OPTIONS_IN_DAYS.contains(&days) => PauseChange::PauseForDays(days),
+            _ => return Err(format!("{} is not a valid pause period.", days)),
+        },
+    };
+    let topics = parse_list_slugs(topics)?;
+    if topics.is_empty() {
+        return Err("Select at least one topic, or unsubscribe instead.".into());
+    }
+    Ok((name, email_format, pause, topics))
+}
+
+fn preferences_redirect(token: &str) -> HttpResponse {
+    see_other(&format!(
+        "/subscriptions/preferences?token={}",
+        urlencoding::encode(token)
+    ))
+}
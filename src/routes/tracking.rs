@@ -0,0 +1,63 @@
+use actix_web::http::header::{CacheControl, CacheDirective, LOCATION};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use crate::startup::HmacSecret;
+use crate::tracking::{record_tracking_event, verify_click_token, verify_open_token, TrackingEventKind};
+use crate::utils::e500;
+
+/// 1x1的透明GIF
+const TRACKING_PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// 追踪像素，记录一次打开
+/// - 禁止缓存，否则重复打开不会再次请求
+#[tracing::instrument(name = "Track an open", skip(token, pool, hmac_secret))]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let recipient = match verify_open_token(&hmac_secret, &token) {
+        Ok(recipient) => recipient,
+        Err(e) => return Ok(invalid_tracking_link(e)),
+    };
+    record_tracking_event(&pool, &recipient, TrackingEventKind::Open, None)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(TRACKING_PIXEL))
+}
+
+/// 点击跳转，记录一次点击后重定向到令牌中的目标地址
+#[tracing::instrument(name = "Track a click", skip(token, pool, hmac_secret))]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (recipient, url) = match verify_click_token(&hmac_secret, &token) {
+        Ok(verified) => verified,
+        Err(e) => return Ok(invalid_tracking_link(e)),
+    };
+    record_tracking_event(&pool, &recipient, TrackingEventKind::Click, Some(&url))
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .finish())
+}
+
+fn invalid_tracking_link(e: anyhow::Error) -> HttpResponse {
+    tracing::warn!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Rejected a tracking link",
+    );
+    HttpResponse::NotFound().finish()
+}
@@ -0,0 +1,159 @@
+use actix_web_lab::middleware::Next;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    HeaderMap, HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY,
+    STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use actix_web::{web, HttpMessage};
+use anyhow::Context;
+use rand::{thread_rng, RngCore};
+use crate::configuration::SecurityHeadersSettings;
+use crate::utils::e500;
+
+/// 当前请求的CSP nonce
+/// - 内联的'<script>'/'<style>'需要带上'nonce="{nonce}"'才会被浏览器执行
+/// - 处理函数可以通过'ReqData<CspNonce>'获取
+#[derive(Debug, Clone)]
+pub struct CspNonce(String);
+
+impl std::fmt::Display for CspNonce {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl AsRef<str> for CspNonce {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// 根据配置预先构建好的安全响应头
+/// - 除CSP外的响应头在启动时解析一次，配置非法时启动失败
+pub struct SecurityHeaders {
+    content_security_policy: Option<String>,
+    static_headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl SecurityHeaders {
+    pub fn new(
+        settings: &SecurityHeadersSettings,
+        enable_hsts: bool,
+    ) -> Result<Self, anyhow::Error> {
+        let mut static_headers = Vec::new();
+        if let Some(value) = &settings.frame_options {
+            static_headers.push((X_FRAME_OPTIONS, parse_header_value(value)?));
+        }
+        if let Some(value) = &settings.referrer_policy {
+            static_headers.push((REFERRER_POLICY, parse_header_value(value)?));
+        }
+        if settings.content_type_options {
+            static_headers.push((X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")));
+        }
+        if enable_hsts {
+            let value = format!("max-age={}; includeSubDomains", settings.hsts_max_age_seconds);
+            static_headers.push((STRICT_TRANSPORT_SECURITY, parse_header_value(&value)?));
+        }
+
+        if let Some(policy) = &settings.content_security_policy {
+            // nonce只包含base64字符，用一个示例nonce检查策略本身是否合法即可
+            parse_header_value(&policy.replace("{nonce}", "bm9uY2U="))?;
+        }
+
+        Ok(Self {
+            content_security_policy: settings.content_security_policy.clone(),
+            static_headers,
+        })
+    }
+
+    fn apply(&self, headers: &mut HeaderMap, nonce: &CspNonce) {
+        for (name, value) in &self.static_headers {
+            insert_if_missing(headers, name, value.clone());
+        }
+        if let Some(policy) = &self.content_security_policy {
+            let policy = policy.replace("{nonce}", nonce.as_ref());
+            if let Ok(value) = HeaderValue::from_str(&policy) {
+                insert_if_missing(headers, &CONTENT_SECURITY_POLICY, value);
+            }
+        }
+    }
+}
+
+/// 为每个响应(包括错误响应)添加安全响应头
+/// - 处理函数已经设置的同名响应头不会被覆盖
+pub async fn add_security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let security_headers = req
+        .app_data::<web::Data<SecurityHeaders>>()
+        .ok_or_else(|| e500("The security headers are not registered."))?
+        .clone();
+
+    let nonce = generate_nonce();
+    req.extensions_mut().insert(nonce.clone());
+
+    let http_request = req.request().clone();
+    let mut response = match next.call(req).await {
+        Ok(response) => response.map_into_boxed_body(),
+        // 中间件返回的错误在这里转换为响应，以便同样带上安全响应头
+        Err(e) => ServiceResponse::from_err(e, http_request),
+    };
+    security_headers.apply(response.headers_mut(), &nonce);
+    Ok(response)
+}
+
+/// 生成16字节的随机数，并进行base64编码
+fn generate_nonce() -> CspNonce {
+    let mut bytes = [0u8; 16];
+    thread_rng().fill_bytes(&mut bytes);
+    CspNonce(base64::encode(bytes))
+}
+
+fn parse_header_value(value: &str) -> Result<HeaderValue, anyhow::Error> {
+    HeaderValue::from_str(value)
+        .with_context(|| format!("'{}' is not a valid header value.", value))
+}
+
+fn insert_if_missing(headers: &mut HeaderMap, name: &HeaderName, value: HeaderValue) {
+    if !headers.contains_key(name) {
+        headers.insert(name.clone(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_nonce, SecurityHeaders};
+    use crate::configuration::SecurityHeadersSettings;
+
+    fn settings() -> SecurityHeadersSettings {
+        SecurityHeadersSettings {
+            content_security_policy: Some("script-src 'nonce-{nonce}'".into()),
+            frame_options: Some("DENY".into()),
+            referrer_policy: None,
+            content_type_options: true,
+            hsts_max_age_seconds: 60,
+        }
+    }
+
+    #[test]
+    fn nonces_are_unique() {
+        assert_ne!(generate_nonce().0, generate_nonce().0);
+    }
+
+    #[test]
+    fn hsts_is_only_sent_when_enabled() {
+        let headers = SecurityHeaders::new(&settings(), false).unwrap();
+        assert_eq!(headers.static_headers.len(), 2);
+        let headers = SecurityHeaders::new(&settings(), true).unwrap();
+        assert_eq!(headers.static_headers.len(), 3);
+    }
+
+    #[test]
+    fn an_invalid_header_value_is_rejected() {
+        let mut settings = settings();
+        settings.frame_options = Some("DENY\n".into());
+        assert!(SecurityHeaders::new(&settings, false).is_err());
+    }
+}
@@ -0,0 +1,416 @@
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+use crate::domain::SubscriberTag;
+
+/// 一个分群表达式中最多可以引用的标签数，避免生成过大的查询
+const MAX_SEGMENT_TAGS: usize = 32;
+/// 分群表达式的最大长度，在解析前检查
+const MAX_SEGMENT_LENGTH: usize = 1024;
+/// 'NOT'和括号的最大嵌套层数，避免解析、生成SQL和释放时递归过深
+const MAX_SEGMENT_DEPTH: usize = 32;
+
+/// 由标签和'AND'、'OR'、'NOT'、括号组成的分群表达式，例如'beta AND NOT churned'
+/// - 关键字不区分大小写；优先级从高到低为'NOT'、'AND'、'OR'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    LeftParen,
+    RightParen,
+}
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, String> {
+        if s.len() > MAX_SEGMENT_LENGTH {
+            return Err(format!(
+                "A segment can be at most {} characters long.",
+                MAX_SEGMENT_LENGTH
+            ));
+        }
+        let tokens = tokenize(s)?;
+        let tag_count = tokens
+            .iter()
+            .filter(|t| matches!(t, Token::Word(w) if keyword(w).is_none()))
+            .count();
+        if tag_count > MAX_SEGMENT_TAGS {
+            return Err(format!(
+                "A segment can reference at most {} tags.",
+                MAX_SEGMENT_TAGS
+            ));
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            depth: 0,
+        };
+        let segment = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(segment),
+            Some(token) => Err(format!("Unexpected {} in the segment.", describe(token))),
+        }
+    }
+
+    /// 解析表单或请求中可选的分群表达式，空白表示不按标签筛选
+    pub fn parse_optional(s: &str) -> Result<Option<Segment>, String> {
+        if s.trim().is_empty() {
+            Ok(None)
+        } else {
+            Segment::parse(s).map(Some)
+        }
+    }
+
+    /// 将表达式转换为SQL条件，追加到'builder'中
+    /// - 条件引用外层查询中别名为's'的'subscriptions'表
+    /// - 标签通过绑定参数传入，不会拼接到SQL中
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Segment::Tag(tag) => {
+                builder
+                    .push("EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ")
+                    .push_bind(tag.as_ref().to_owned())
+                    .push(")");
+            }
+            Segment::Not(inner) => {
+                builder.push("NOT (");
+                inner.push_sql(builder);
+                builder.push(")");
+            }
+            Segment::And(left, right) | Segment::Or(left, right) => {
+                let operator = if matches!(self, Segment::And(..)) { " AND " } else { " OR " };
+                builder.push("(");
+                left.push_sql(builder);
+                builder.push(operator);
+                right.push_sql(builder);
+                builder.push(")");
+            }
+        }
+    }
+}
+
+/// 规范化的表达式，所有二元运算都带括号
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Segment::Tag(tag) => write!(f, "{}", tag.as_ref()),
+            Segment::Not(inner) => write!(f, "NOT {}", inner),
+            Segment::And(left, right) => write!(f, "({} AND {})", left, right),
+            Segment::Or(left, right) => write!(f, "({} OR {})", left, right),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Keyword {
+    And,
+    Or,
+    Not,
+}
+
+fn keyword(word: &str) -> Option<Keyword> {
+    match word.to_ascii_uppercase().as_str() {
+        "AND" => Some(Keyword::And),
+        "OR" => Some(Keyword::Or),
+        "NOT" => Some(Keyword::Not),
+        _ => None,
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(w) => format!("'{}'", w),
+        Token::LeftParen => "'('".into(),
+        Token::RightParen => "')'".into(),
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::LeftParen);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::RightParen);
+        } else if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    word.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Word(word));
+        } else {
+            return Err(format!("'{}' is not allowed in a segment.", c));
+        }
+    }
+    if tokens.is_empty() {
+        return Err("The segment is empty.".into());
+    }
+    Ok(tokens)
+}
+
+/// 递归下降解析器
+/// - or  := and ('OR' and)*
+/// - and := not ('AND' not)*
+/// - not := 'NOT' not | tag | '(' or ')'
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// 当前所在的'NOT'和括号的嵌套层数
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is(&self, expected: Keyword) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if keyword(w) == Some(expected))
+    }
+
+    fn parse_or(&mut self) -> Result<Segment, String> {
+        let mut segment = self.parse_and()?;
+        while self.next_is(Keyword::Or) {
+            self.next();
+            segment = Segment::Or(Box::new(segment), Box::new(self.parse_and()?));
+        }
+        Ok(segment)
+    }
+
+    fn parse_and(&mut self) -> Result<Segment, String> {
+        let mut segment = self.parse_not()?;
+        while self.next_is(Keyword::And) {
+            self.next();
+            segment = Segment::And(Box::new(segment), Box::new(self.parse_not()?));
+        }
+        Ok(segment)
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_SEGMENT_DEPTH {
+            return Err(format!(
+                "A segment can nest 'NOT' and parentheses at most {} levels deep.",
+                MAX_SEGMENT_DEPTH
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_not(&mut self) -> Result<Segment, String> {
+        match self.next() {
+            Some(Token::Word(w)) => match keyword(&w) {
+                Some(Keyword::Not) => {
+                    self.enter()?;
+                    let segment = Segment::Not(Box::new(self.parse_not()?));
+                    self.depth -= 1;
+                    Ok(segment)
+                }
+                Some(_) => Err(format!("Expected a tag but found '{}'.", w)),
+                None => Ok(Segment::Tag(SubscriberTag::parse(w)?)),
+            },
+            Some(Token::LeftParen) => {
+                self.enter()?;
+                let segment = self.parse_or()?;
+                self.depth -= 1;
+                match self.next() {
+                    Some(Token::RightParen) => Ok(segment),
+                    _ => Err("A '(' in the segment is not closed.".into()),
+                }
+            }
+            Some(Token::RightParen) => Err("Unexpected ')' in the segment.".into()),
+            None => Err("The segment ends unexpectedly.".into()),
+        }
+    }
+}
+
+/// 给订阅者添加标签，已有的标签保持不变
+#[tracing::instrument(name = "Tag a subscriber", skip(pool, tags))]
+pub async fn add_subscriber_tags(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> Result<(), anyhow::Error> {
+    let tags: Vec<String> = tags.iter().map(|t| t.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag, tagged_at)
+        SELECT $1, t.tag, now() FROM UNNEST($2::text[]) AS t(tag)
+        ON CONFLICT (subscriber_id, tag) DO NOTHING
+        "#,
+        subscriber_id,
+        &tags[..],
+    )
+    .execute(pool)
+    .await
+    .context("Failed to tag a subscriber.")?;
+    Ok(())
+}
+
+/// 移除订阅者的标签，不存在的标签被忽略
+#[tracing::instrument(name = "Untag a subscriber", skip(pool, tags))]
+pub async fn remove_subscriber_tags(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> Result<(), anyhow::Error> {
+    let tags: Vec<String> = tags.iter().map(|t| t.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = ANY($2)"#,
+        subscriber_id,
+        &tags[..],
+    )
+    .execute(pool)
+    .await
+    .context("Failed to remove tags from a subscriber.")?;
+    Ok(())
+}
+
+/// 查询订阅者的标签，按标签排序
+#[tracing::instrument(name = "Get the tags of a subscriber", skip(pool))]
+pub async fn get_subscriber_tags(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    let tags = sqlx::query!(
+        r#"SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag"#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the tags of a subscriber.")?
+    .into_iter()
+    .map(|r| r.tag)
+    .collect();
+    Ok(tags)
+}
+
+/// 查询所有订阅者的标签，返回(订阅者ID, 标签)，按标签排序
+#[tracing::instrument(name = "List subscriber tags", skip(pool))]
+pub async fn list_subscriber_tags(pool: &PgPool) -> Result<Vec<(Uuid, String)>, anyhow::Error> {
+    let tags = sqlx::query!(r#"SELECT subscriber_id, tag FROM subscriber_tags ORDER BY tag"#)
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve subscriber tags.")?
+        .into_iter()
+        .map(|r| (r.subscriber_id, r.tag))
+        .collect();
+    Ok(tags)
+}
+
+/// 解析一组标签，去掉首尾空白和重复的标签
+pub fn parse_subscriber_tags(tags: Vec<String>) -> Result<Vec<SubscriberTag>, String> {
+    let mut parsed: Vec<SubscriberTag> = Vec::new();
+    for tag in tags {
+        let tag = SubscriberTag::parse(tag.trim().to_string())?;
+        if !parsed.contains(&tag) {
+            parsed.push(tag);
+        }
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_subscriber_tags, Segment};
+    use claim::assert_err;
+    use sqlx::{Postgres, QueryBuilder};
+
+    fn canonical(s: &str) -> String {
+        Segment::parse(s).unwrap().to_string()
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        assert_eq!(canonical("beta AND NOT churned"), "(beta AND NOT churned)");
+        assert_eq!(canonical("a OR b AND c"), "(a OR (b AND c))");
+        assert_eq!(canonical("(a OR b) AND c"), "((a OR b) AND c)");
+        assert_eq!(canonical("NOT NOT a"), "NOT NOT a");
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive() {
+        assert_eq!(canonical("beta and not churned"), "(beta AND NOT churned)");
+    }
+
+    #[test]
+    fn malformed_segments_are_rejected() {
+        for segment in [
+            "",
+            "   ",
+            "beta AND",
+            "AND beta",
+            "beta churned",
+            "(beta",
+            "beta)",
+            "()",
+            "Beta",
+            "beta; DROP TABLE subscriptions",
+            "beta OR 'churned'",
+        ] {
+            assert_err!(Segment::parse(segment), "{}", segment);
+        }
+    }
+
+    #[test]
+    fn a_blank_optional_segment_means_no_segment() {
+        assert_eq!(Segment::parse_optional("  "), Ok(None));
+        assert!(Segment::parse_optional("beta").unwrap().is_some());
+    }
+
+    #[test]
+    fn too_many_tags_are_rejected() {
+        let segment = (0..33).map(|i| format!("t{}", i)).collect::<Vec<_>>().join(" OR ");
+        assert_err!(Segment::parse(&segment));
+    }
+
+    #[test]
+    fn deeply_nested_segments_are_rejected() {
+        let nested = format!("{}a", "NOT ".repeat(32));
+        assert_eq!(canonical(&nested), nested);
+        assert_err!(Segment::parse(&format!("{}a", "NOT ".repeat(33))));
+        assert_err!(Segment::parse(&format!("{}a{}", "(".repeat(33), ")".repeat(33))));
+        assert_err!(Segment::parse(&format!("{}a", "NOT ".repeat(100_000))));
+    }
+
+    #[test]
+    fn tags_are_bound_as_parameters() {
+        let mut builder: QueryBuilder<'_, Postgres> = QueryBuilder::new("");
+        Segment::parse("beta AND NOT churned").unwrap().push_sql(&mut builder);
+        assert_eq!(
+            builder.sql(),
+            "(EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $1) \
+            AND NOT (EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $2)))"
+        );
+    }
+
+    #[test]
+    fn parsed_tags_are_trimmed_and_deduplicated() {
+        let tags = parse_subscriber_tags(vec![" beta".into(), "beta".into(), "churned".into()]).unwrap();
+        let tags: Vec<&str> = tags.iter().map(|t| t.as_ref()).collect();
+        assert_eq!(tags, ["beta", "churned"]);
+        assert_err!(parse_subscriber_tags(vec!["Beta".into()]));
+    }
+}
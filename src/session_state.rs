@@ -1,5 +1,6 @@
 use actix_session::Session;
 use uuid::Uuid;
+use crate::authentication::OidcLoginState;
 use actix_session::SessionExt;
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
@@ -9,6 +10,11 @@ pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const CREATED_AT_KEY: &'static str = "created_at";
+    const LAST_ACTIVE_AT_KEY: &'static str = "last_active_at";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
+    const OIDC_LOGIN_KEY: &'static str = "oidc_login";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -22,6 +28,53 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, serde_json::Error> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    /// 登录时间，Unix时间戳(秒)
+    pub fn insert_created_at(&self, timestamp: i64) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::CREATED_AT_KEY, timestamp)
+    }
+
+    pub fn get_created_at(&self) -> Result<Option<i64>, serde_json::Error> {
+        self.0.get(Self::CREATED_AT_KEY)
+    }
+
+    /// 最近一次活动的时间，Unix时间戳(秒)
+    /// - 每次更新都会改变会话状态，从而延长Redis中会话的过期时间
+    pub fn insert_last_active_at(&self, timestamp: i64) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::LAST_ACTIVE_AT_KEY, timestamp)
+    }
+
+    pub fn get_last_active_at(&self) -> Result<Option<i64>, serde_json::Error> {
+        self.0.get(Self::LAST_ACTIVE_AT_KEY)
+    }
+
+    pub fn insert_csrf_token(&self, token: &str) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, serde_json::Error> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
+    /// 跳转到身份提供方之前保存state/nonce/PKCE verifier，回调时取出
+    pub fn insert_oidc_login_state(&self, state: &OidcLoginState) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::OIDC_LOGIN_KEY, state)
+    }
+
+    /// 取出并删除保存的OIDC登录状态，确保每个state只能使用一次
+    pub fn take_oidc_login_state(&self) -> Result<Option<OidcLoginState>, serde_json::Error> {
+        let state = self.0.get(Self::OIDC_LOGIN_KEY)?;
+        self.0.remove(Self::OIDC_LOGIN_KEY);
+        Ok(state)
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
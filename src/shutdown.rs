@@ -0,0 +1,130 @@
+use std::future::Future;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::Instrument;
+
+/// 关闭时需要等待完成的后台任务
+/// - 请求被取消(客户端断开连接、超过关闭的宽限期)时，后台任务仍会继续执行
+/// - 宽限期结束时触发'interrupted'，长时间运行的任务应尽快停止，并记录已完成的进度
+#[derive(Clone)]
+pub struct BackgroundTasks {
+    tracker: TaskTracker,
+    interrupted: CancellationToken,
+    runtime: Handle,
+}
+
+impl BackgroundTasks {
+    /// 任务在'runtime'上执行
+    /// - 处理函数运行在actix工作线程各自的运行时中，这些运行时在服务器停止后被销毁，
+    ///   因此需要传入主运行时，后台任务才能在服务器停止后继续执行
+    pub fn new(runtime: Handle) -> Self {
+        Self {
+            tracker: TaskTracker::new(),
+            interrupted: CancellationToken::new(),
+            runtime,
+        }
+    }
+
+    /// 在后台执行任务，任务继承当前的跨度
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tracker
+            .spawn_on(task.instrument(tracing::Span::current()), &self.runtime)
+    }
+
+    pub fn interrupted(&self) -> &CancellationToken {
+        &self.interrupted
+    }
+
+    /// 到达'deadline'时触发'interrupted'
+    /// - 与停止服务器同时进行，服务器停止之前宽限期就可能结束
+    pub async fn interrupt_at(&self, deadline: Instant) {
+        tokio::time::sleep_until(deadline).await;
+        if self.interrupted.is_cancelled() {
+            return;
+        }
+        if !self.tracker.is_empty() {
+            tracing::warn!(
+                tasks = self.tracker.len(),
+                "The shutdown grace period is over. Interrupting background tasks"
+            );
+        }
+        self.interrupted.cancel();
+    }
+
+    /// 等待所有后台任务完成，在服务器停止、不再有新任务之后调用
+    /// - 超过'deadline'时触发'interrupted'，然后等待任务停止
+    pub async fn drain(&self, deadline: Instant) {
+        self.tracker.close();
+        tokio::select! {
+            _ = self.tracker.wait() => {}
+            _ = self.interrupt_at(deadline) => self.tracker.wait().await,
+        }
+    }
+}
+
+/// 等待SIGTERM或Ctrl-C
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl-C.");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM.")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::runtime::Handle;
+    use tokio::time::Instant;
+    use super::BackgroundTasks;
+
+    #[tokio::test]
+    async fn drain_waits_for_tasks_that_finish_within_the_grace_period() {
+        let tasks = BackgroundTasks::new(Handle::current());
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = finished.clone();
+        tasks.spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        tasks.drain(Instant::now() + Duration::from_secs(5)).await;
+
+        assert!(finished.load(Ordering::SeqCst));
+        assert!(!tasks.interrupted().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn drain_interrupts_tasks_after_the_grace_period() {
+        let tasks = BackgroundTasks::new(Handle::current());
+        let interrupted = tasks.interrupted().clone();
+        tasks.spawn(async move { interrupted.cancelled().await });
+
+        tasks.drain(Instant::now() + Duration::from_millis(50)).await;
+
+        assert!(tasks.interrupted().is_cancelled());
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use crate::startup::HmacSecret;
+
+/// 生成用'HmacSecret'签名的令牌，格式为'base64url(payload).base64url(HMAC-SHA256)'
+/// - 'purpose'参与签名，为一种用途签发的令牌不能用于另一种用途
+/// - 令牌只防篡改，不加密，payload中不应包含机密信息
+pub fn sign_token(secret: &HmacSecret, purpose: &str, payload: &str) -> String {
+    let tag = mac(secret, purpose, payload).finalize().into_bytes();
+    format!(
+        "{}.{}",
+        base64::encode_config(payload, base64::URL_SAFE_NO_PAD),
+        base64::encode_config(tag, base64::URL_SAFE_NO_PAD),
+    )
+}
+
+/// 校验'sign_token'生成的令牌，返回其中的payload
+pub fn verify_token(
+    secret: &HmacSecret,
+    purpose: &str,
+    token: &str,
+) -> Result<String, anyhow::Error> {
+    let (payload, tag) = token
+        .split_once('.')
+        .context("The token is malformed.")?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+        .context("The token payload is not valid base64.")?;
+    let payload = String::from_utf8(payload).context("The token payload is not valid UTF-8.")?;
+    let tag = base64::decode_config(tag, base64::URL_SAFE_NO_PAD)
+        .context("The token signature is not valid base64.")?;
+    mac(secret, purpose, &payload)
+        .verify_slice(&tag)
+        .context("The token signature is invalid.")?;
+    Ok(payload)
+}
+
+fn mac(secret: &HmacSecret, purpose: &str, payload: &str) -> Hmac<sha2::Sha256> {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(
+        secret.0.expose_secret().as_bytes()
+    ).unwrap();
+    mac.update(purpose.as_bytes());
+    mac.update(b"\n");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign_token, verify_token};
+    use crate::startup::HmacSecret;
+    use secrecy::Secret;
+
+    fn secret(value: &str) -> HmacSecret {
+        HmacSecret(Secret::new(value.to_string()))
+    }
+
+    #[test]
+    fn a_signed_token_round_trips() {
+        let token = sign_token(&secret("key"), "test", "ursula_le_guin@gmail.com");
+        assert_eq!(
+            verify_token(&secret("key"), "test", &token).unwrap(),
+            "ursula_le_guin@gmail.com"
+        );
+    }
+
+    #[test]
+    fn a_token_for_another_purpose_is_rejected() {
+        let token = sign_token(&secret("key"), "export", "payload");
+        assert!(verify_token(&secret("key"), "erase", &token).is_err());
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = sign_token(&secret("key"), "test", "payload");
+        assert!(verify_token(&secret("another key"), "test", &token).is_err());
+    }
+
+    #[test]
+    fn a_tampered_payload_is_rejected() {
+        let token = sign_token(&secret("key"), "test", "payload");
+        let (_, tag) = token.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{}",
+            base64::encode_config("another payload", base64::URL_SAFE_NO_PAD),
+            tag
+        );
+        assert!(verify_token(&secret("key"), "test", &forged).is_err());
+        assert!(verify_token(&secret("key"), "test", "not a token").is_err());
+    }
+}
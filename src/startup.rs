@@ -1,11 +1,38 @@
 use crate::email_client::EmailClient;
-use crate::routes::{admin_dashboard, health_check, home, login, login_form, publish_newsletter, publish_newsletter_form, subscribe};
+use crate::routes::{admin_dashboard, health_check, readiness_check, ReadinessChecks, home, login, login_form, publish_newsletter, publish_newsletter_form, subscribe};
 use crate::configuration::Settings;
 use crate::configuration::DatabaseSettings;
+use crate::configuration::PasswordHashingSettings;
+use crate::configuration::SessionSettings;
+use crate::configuration::Environment;
+use crate::configuration::ConsentSettings;
+use crate::configuration::MetricsSettings;
+use crate::migrations::run_startup_migrations;
+use crate::subscriber_data::upgrade_legacy_tombstones;
+use crate::metrics::{metrics_endpoint, record_http_metrics, MetricsToken};
+use crate::security_headers::{add_security_headers, SecurityHeaders};
+use crate::shutdown::BackgroundTasks;
+use crate::request_metadata::TrustedProxies;
+use crate::database::DatabasePools;
 use crate::routes::confirm;
+use crate::routes::{data_request_form, erase_data, erase_data_form, export_data, request_data_link};
 use crate::routes::{change_password, change_password_form};
 use crate::routes::log_out;
-use crate::authentication::reject_anonymous_users;
+use crate::routes::{revoke_other_sessions, revoke_session, revoke_user_sessions, sessions_page};
+use crate::routes::{api_keys_page, create_api_key, revoke_api_key};
+use crate::routes::{api_delivery_status, api_list_subscribers, api_publish_newsletter, api_update_subscriber_tags};
+use crate::routes::publish_newsletter_with_basic_auth;
+use crate::routes::{oidc_callback, oidc_login};
+use crate::routes::audit_log_page;
+use crate::routes::{export_subscribers, subscriber_detail_page, subscribers_page};
+use crate::routes::{tag_subscriber, untag_subscriber};
+use crate::routes::{create_list, delete_list, mailing_lists_page, subscribe_form};
+use crate::routes::{change_email, preferences_page, save_preferences, unsubscribe_via_preferences};
+use crate::routes::{issue_history_page, track_click, track_open};
+use crate::authentication::{csrf_protect, reject_anonymous_users, reject_invalid_api_keys};
+use crate::authentication::reject_invalid_basic_credentials;
+use crate::authentication::OidcClient;
+use crate::authentication::dummy_password_hash;
 use actix_web_lab::middleware::from_fn;
 use actix_web::cookie::Key;
 use actix_web::web::Data;
@@ -16,17 +43,27 @@ use actix_web::{web, App, HttpServer};
 use actix_web_flash_messages::FlashMessagesFramework;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 use tracing_actix_web::TracingLogger;
 use secrecy::Secret;
 use secrecy::ExposeSecret;
 use actix_session::SessionMiddleware;
 use actix_session::storage::RedisSessionStore;
+use actix_session::SessionLength;
 
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
 pub struct Application {
     port: u16,
     server: Server,
+    /// 在单独端口上提供'/metrics'时的端口和服务器
+    metrics: Option<(u16, Server)>,
+    background_tasks: BackgroundTasks,
+    shutdown_grace_period: Duration,
+    shutdown: CancellationToken,
+    email_client: EmailClient,
 }
 
 impl Application {
@@ -34,13 +71,26 @@ impl Application {
     /// 根据配置信息初始化/配置应用程序
     /// 现在是异步的！返回anyhow::Error而不是std::io::Error
     /// anyhow::Error通用错误类型,错误类型擦除：可以包装任何实现了 std::error::Error trait 的错误类型
+    /// 启动前先校验配置，一次报告所有问题
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
+        configuration.validate()?;
+        // 启动时计算好登录时使用的占位哈希值
+        dummy_password_hash(&configuration.password_hashing)?;
         let connection_pool = get_connection_pool(&configuration.database);
+        run_startup_migrations(&connection_pool, configuration.database.migration_mode).await?;
+        let upgraded = upgrade_legacy_tombstones(
+            &connection_pool,
+            &HmacSecret(configuration.application.hmac_secret.clone()),
+        )
+        .await?;
+        if upgraded > 0 {
+            tracing::info!(upgraded, "Replaced legacy tombstones with keyed hashes");
+        }
 
         let sender_email = configuration
             .email_client
             .sender()
-            .expect("Invalid sender email address.");
+            .map_err(anyhow::Error::msg)?;
 
         let timeout = configuration.email_client.timeout();
         let base_url = configuration.email_client.base_url.clone();
@@ -58,27 +108,131 @@ impl Application {
             configuration.application.host,
             configuration.application.port,
         );
+        // 只有在确定使用HTTPS时才启用HSTS，否则浏览器会拒绝后续的HTTP访问
+        let enable_hsts = configuration.database.require_ssl
+            || configuration.environment == Environment::Production;
+        let security_headers = SecurityHeaders::new(&configuration.security_headers, enable_hsts)?;
+        let oidc_client = configuration
+            .oidc
+            .clone()
+            .map(|settings| OidcClient::new(settings, &configuration.application.base_url));
+        let readiness_checks = ReadinessChecks::new(&configuration)?;
+        let db_pools = DatabasePools::new(
+            connection_pool.clone(),
+            get_replica_pool(&configuration.database),
+        );
+        // 'build'在主运行时中调用
+        let background_tasks = BackgroundTasks::new(tokio::runtime::Handle::current());
+        let shutdown_grace_period = configuration.application.shutdown_grace_period();
+
+        let metrics = match configuration.metrics.port {
+            Some(metrics_port) => {
+                let address = format!("{}:{}", configuration.application.host, metrics_port);
+                let listener = TcpListener::bind(&address)?;
+                let metrics_port = listener.local_addr().unwrap().port();
+                Some((metrics_port, run_metrics_server(listener, connection_pool.clone())?))
+            }
+            None => None,
+        };
+
         let listener = TcpListener::bind(&address)?;
         let port = listener.local_addr().unwrap().port();
         let server = run(
             listener, 
             connection_pool, 
-            email_client,
+            db_pools,
+            email_client.clone(),
             configuration.application.base_url,
             configuration.application.hmac_secret,
-            configuration.redis_uri
+            TrustedProxies(configuration.application.trusted_proxies),
+            configuration.redis_uri,
+            configuration.password_hashing,
+            configuration.session,
+            configuration.consent,
+            security_headers,
+            oidc_client,
+            configuration.metrics,
+            readiness_checks,
+            background_tasks.clone(),
+            shutdown_grace_period,
         ).await?;
 
-        Ok(Self { port, server})
+        Ok(Self {
+            port,
+            server,
+            metrics,
+            background_tasks,
+            shutdown_grace_period,
+            shutdown: CancellationToken::new(),
+            email_client,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
-    // 运行应用程序
+    /// 在单独端口上提供'/metrics'时返回该端口
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.metrics.as_ref().map(|(port, _)| *port)
+    }
+
+    /// 与服务器共享的邮件客户端，重新加载配置时修改其发件人和超时时间
+    pub fn email_client(&self) -> EmailClient {
+        self.email_client.clone()
+    }
+
+    /// 触发后应用程序开始优雅关闭，main.rs在收到SIGTERM或Ctrl-C时触发
+    pub fn shutdown_trigger(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// 运行应用程序，直到服务器退出或关闭被触发
+    /// - 关闭时停止接受新连接，等待进行中的请求完成，最多等待宽限期
+    /// - 然后等待后台的简报投递；宽限期结束时(无论服务器是否已经停止)中断投递，
+    ///   尚未发送的订阅者记为投递失败
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let server_handle = self.server.handle();
+        let metrics_handle = self.metrics.as_ref().map(|(_, server)| server.handle());
+        let server = self.server;
+        let metrics = self.metrics;
+        let servers = async move {
+            match metrics {
+                Some((_, metrics_server)) => tokio::try_join!(server, metrics_server).map(|_| ()),
+                None => server.await,
+            }
+        };
+        tokio::pin!(servers);
+
+        tokio::select! {
+            outcome = &mut servers => return outcome,
+            _ = self.shutdown.cancelled() => {}
+        }
+        tracing::info!(
+            grace_period_seconds = self.shutdown_grace_period.as_secs(),
+            "Shutting down"
+        );
+        let deadline = Instant::now() + self.shutdown_grace_period;
+        let stop_metrics = async {
+            if let Some(handle) = metrics_handle {
+                handle.stop(true).await;
+            }
+        };
+        let background_tasks = &self.background_tasks;
+        let stopping = async {
+            // 停止服务器的同时继续驱动服务器，等待进行中的请求完成
+            let (_, _, outcome) =
+                tokio::join!(server_handle.stop(true), stop_metrics, &mut servers);
+            background_tasks.drain(deadline).await;
+            outcome
+        };
+        tokio::pin!(stopping);
+        let outcome = tokio::select! {
+            outcome = &mut stopping => outcome,
+            _ = background_tasks.interrupt_at(deadline) => stopping.await,
+        };
+        tracing::info!("Shutdown complete");
+        outcome
     }
 
 }
@@ -91,21 +245,62 @@ pub fn get_connection_pool(
         .connect_lazy_with(configuration.with_db())
 }
 
+/// 配置了只读副本时返回副本的连接池，与主库一样在第一次使用时才建立连接
+pub fn get_replica_pool(
+    configuration: &DatabaseSettings
+) -> Option<PgPool> {
+    configuration.replica_with_db().map(|options| {
+        PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_secs(2))
+            .connect_lazy_with(options)
+    })
+}
+
 pub struct ApplicationBaseUrl(pub String);
 
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
+    db_pools: DatabasePools,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: Secret<String>,
+    trusted_proxies: TrustedProxies,
     redis_uri: Secret<String>,
+    password_hashing: PasswordHashingSettings,
+    session_settings: SessionSettings,
+    consent_settings: ConsentSettings,
+    security_headers: SecurityHeaders,
+    oidc_client: Option<OidcClient>,
+    metrics_settings: MetricsSettings,
+    readiness_checks: ReadinessChecks,
+    background_tasks: BackgroundTasks,
+    shutdown_grace_period: Duration,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = web::Data::new(db_pool);
+    let db_pools = web::Data::new(db_pools);
     let email_client = web::Data::new(email_client);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let trusted_proxies = Data::new(trusted_proxies);
+    let password_hashing = Data::new(password_hashing);
+    // Redis中的会话状态在闲置超时后过期；每次请求都会刷新最近活动时间，从而续期
+    let session_state_ttl = actix_web::cookie::time::Duration::seconds(
+        session_settings.idle_timeout_seconds as i64
+    );
+    let session_settings = Data::new(session_settings);
+    let consent_settings = Data::new(consent_settings);
+    let security_headers = Data::new(security_headers);
+    let oidc_client = oidc_client.map(Data::new);
+    let readiness_checks = Data::new(readiness_checks);
+    let background_tasks = Data::new(background_tasks);
+    // 使用单独的端口时，应用端口上不提供'/metrics'
+    let metrics_token = match metrics_settings.port {
+        Some(_) => None,
+        None => metrics_settings.bearer_token.map(|token| Data::new(MetricsToken(token))),
+    };
 
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
+    let hmac_secret = Data::new(HmacSecret(hmac_secret));
     let message_store = CookieMessageStore::builder(
         secret_key.clone()
     ).build();
@@ -114,24 +309,53 @@ async fn run(
 
     // TracingLogger一个专门为 actix-web 框架设计的中间件,基于tracing而非log实现,
     // 能自带request_id等跨度信息，使用其代替 actix-web::Logger,
+    // 启用了'opentelemetry_0_18'特性，请求中带有'traceparent'时，根跨度会延续调用方的链路
     let server = HttpServer::new(move || {
-            App::new()
+            let app = App::new()
                 .wrap(message_framework.clone())
-                .wrap(SessionMiddleware::new(
-                    redis_store.clone(), 
-                    secret_key.clone()
-                ))
+                .wrap(
+                    SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                        .session_length(SessionLength::BrowserSession {
+                            state_ttl: Some(session_state_ttl),
+                        })
+                        .build()
+                )
+                .wrap(from_fn(add_security_headers))
+                .wrap(from_fn(record_http_metrics))
                 // 替换"Logger::default()"
                 .wrap(TracingLogger::default())
                 .route("/", web::get().to(home))
                 .route("/login", web::get().to(login_form))
                 .route("/login", web::post().to(login))
+                .route("/login/oidc", web::get().to(oidc_login))
+                .route("/login/oidc/callback", web::get().to(oidc_callback))
                 .route("/health_check", web::get().to(health_check))
-                .route("/newsletters", web::post().to(publish_newsletter))
+                .route("/health/live", web::get().to(health_check))
+                .route("/health/ready", web::get().to(readiness_check))
+                // 只接受JSON请求体，跨站表单无法伪造，因此不需要CSRF令牌
+                .service(
+                    web::resource("/newsletters")
+                        .wrap(from_fn(reject_invalid_basic_credentials))
+                        .route(web::post().to(publish_newsletter_with_basic_auth))
+                )
+                .route("/subscriptions", web::get().to(subscribe_form))
                 .route("/subscriptions", web::post().to(subscribe))
                 .route("/subscriptions/confirm", web::get().to(confirm))
+                .route("/subscriptions/data", web::get().to(data_request_form))
+                .route("/subscriptions/data", web::post().to(request_data_link))
+                .route("/subscriptions/data/export", web::get().to(export_data))
+                .route("/subscriptions/data/erase", web::get().to(erase_data_form))
+                .route("/subscriptions/data/erase", web::post().to(erase_data))
+                .route("/subscriptions/preferences", web::get().to(preferences_page))
+                .route("/subscriptions/preferences", web::post().to(save_preferences))
+                .route("/subscriptions/preferences/email", web::post().to(change_email))
+                .route("/subscriptions/preferences/unsubscribe", web::post().to(unsubscribe_via_preferences))
+                .route("/t/o/{token}", web::get().to(track_open))
+                .route("/t/c/{token}", web::get().to(track_click))
                 .service(
                     web::scope("/admin")
+                                // 后注册的中间件先执行：先拒绝匿名用户，再校验CSRF令牌
+                                .wrap(from_fn(csrf_protect))
                                 .wrap(from_fn(reject_anonymous_users))
                                 .route("/dashboard", web::get().to(admin_dashboard))
                                 .route("/password", web::get().to(change_password_form))
@@ -139,13 +363,76 @@ async fn run(
                                 .route("/logout", web::post().to(log_out))
                                 .route("/newsletters", web::get().to(publish_newsletter_form))
                                 .route("/newsletters", web::post().to(publish_newsletter))
+                                .route("/newsletters/history", web::get().to(issue_history_page))
+                                .route("/sessions", web::get().to(sessions_page))
+                                .route("/sessions/revoke", web::post().to(revoke_session))
+                                .route("/sessions/revoke_others", web::post().to(revoke_other_sessions))
+                                .route("/sessions/revoke_user", web::post().to(revoke_user_sessions))
+                                .route("/api_keys", web::get().to(api_keys_page))
+                                .route("/api_keys", web::post().to(create_api_key))
+                                .route("/api_keys/revoke", web::post().to(revoke_api_key))
+                                .route("/audit", web::get().to(audit_log_page))
+                                .route("/subscribers", web::get().to(subscribers_page))
+                                // 必须先于'/subscribers/{subscriber_id}'注册
+                                .route("/subscribers/export", web::get().to(export_subscribers))
+                                .route("/subscribers/{subscriber_id}", web::get().to(subscriber_detail_page))
+                                .route("/subscribers/{subscriber_id}/tags", web::post().to(tag_subscriber))
+                                .route("/subscribers/{subscriber_id}/tags/remove", web::post().to(untag_subscriber))
+                                .route("/lists", web::get().to(mailing_lists_page))
+                                .route("/lists", web::post().to(create_list))
+                                .route("/lists/delete", web::post().to(delete_list))
+                )
+                .service(
+                    web::scope("/api/v1")
+                                .wrap(from_fn(reject_invalid_api_keys))
+                                .route("/newsletters", web::post().to(api_publish_newsletter))
+                                .route("/newsletters/{newsletter_issue_id}", web::get().to(api_delivery_status))
+                                .route("/subscribers", web::get().to(api_list_subscribers))
+                                .route("/subscribers/{subscriber_id}/tags", web::post().to(api_update_subscriber_tags))
                 )
                 .app_data(db_pool.clone())
+                .app_data(db_pools.clone())
                 .app_data(email_client.clone())
                 .app_data(base_url.clone())
+                .app_data(trusted_proxies.clone())
+                .app_data(password_hashing.clone())
+                .app_data(session_settings.clone())
+                .app_data(consent_settings.clone())
+                .app_data(hmac_secret.clone())
+                .app_data(security_headers.clone())
+                .app_data(readiness_checks.clone())
+                .app_data(background_tasks.clone());
+            // 只有配置了OIDC时才注册，处理函数据此决定是否提供单点登录
+            let app = match &oidc_client {
+                Some(oidc_client) => app.app_data(oidc_client.clone()),
+                None => app,
+            };
+            match &metrics_token {
+                Some(metrics_token) => app
+                    .app_data(metrics_token.clone())
+                    .route("/metrics", web::get().to(metrics_endpoint)),
+                None => app,
+            }
     })
+    // 由'Application::run_until_stopped'处理关闭信号，以便在服务器停止后等待后台任务
+    .disable_signals()
+    .shutdown_timeout(shutdown_grace_period.as_secs())
     .listen(listener)?
     .run();
 
+    Ok(server)
+}
+
+/// 只提供'/metrics'的服务器，不需要认证
+fn run_metrics_server(listener: TcpListener, db_pool: PgPool) -> Result<Server, anyhow::Error> {
+    let db_pool = web::Data::new(db_pool);
+    let server = HttpServer::new(move || {
+            App::new()
+                .route("/metrics", web::get().to(metrics_endpoint))
+                .app_data(db_pool.clone())
+    })
+    .disable_signals()
+    .listen(listener)?
+    .run();
     Ok(server)
 }
\ No newline at end of file
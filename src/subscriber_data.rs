@@ -0,0 +1,366 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::consent::{get_consent_records, ConsentRecord};
+use crate::mailing_lists::get_subscriber_lists;
+use crate::segments::get_subscriber_tags;
+use crate::signed_token::{sign_token, verify_token};
+use crate::startup::HmacSecret;
+use crate::subscribers::SubscriberRecord;
+use crate::tracking::{get_tracking_events, TrackingEventRecord};
+
+/// 数据请求链接的有效期
+const DATA_REQUEST_LINK_VALIDITY_SECONDS: i64 = 24 * 60 * 60;
+
+/// 订阅者可以发起的数据请求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataRequestKind {
+    /// 导出我们保存的该邮箱的所有数据
+    Export,
+    /// 删除该邮箱的所有数据
+    Erasure,
+}
+
+impl DataRequestKind {
+    /// 用于URL路径和表单
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataRequestKind::Export => "export",
+            DataRequestKind::Erasure => "erase",
+        }
+    }
+
+    fn token_purpose(&self) -> &'static str {
+        match self {
+            DataRequestKind::Export => "subscriber_data_export",
+            DataRequestKind::Erasure => "subscriber_data_erasure",
+        }
+    }
+}
+
+impl TryFrom<String> for DataRequestKind {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "export" => Ok(Self::Export),
+            "erase" => Ok(Self::Erasure),
+            other => Err(format!("{} is not a known data request.", other)),
+        }
+    }
+}
+
+/// 生成数据请求链接中的令牌，令牌中包含邮箱和过期时间
+pub fn data_request_token(secret: &HmacSecret, kind: DataRequestKind, email: &str) -> String {
+    let expires_at = Utc::now().timestamp() + DATA_REQUEST_LINK_VALIDITY_SECONDS;
+    sign_token(secret, kind.token_purpose(), &format!("{}:{}", expires_at, email))
+}
+
+/// 校验数据请求链接中的令牌，返回发起请求的邮箱
+pub fn verify_data_request_token(
+    secret: &HmacSecret,
+    kind: DataRequestKind,
+    token: &str,
+) -> Result<String, anyhow::Error> {
+    let payload = verify_token(secret, kind.token_purpose(), token)?;
+    let (expires_at, email) = payload
+        .split_once(':')
+        .context("The token payload is malformed.")?;
+    let expires_at: i64 = expires_at.parse().context("The expiry of the token is malformed.")?;
+    if Utc::now().timestamp() > expires_at {
+        anyhow::bail!("The token has expired.");
+    }
+    Ok(email.to_string())
+}
+
+/// 某个订阅者的一次投递记录
+#[derive(Debug, serde::Serialize)]
+pub struct DeliveryRecord {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub error_message: Option<String>,
+    pub attempted_at: DateTime<Utc>,
+}
+
+/// 为某个邮箱保存的所有数据
+#[derive(Debug, serde::Serialize)]
+pub struct SubscriberDataExport {
+    pub subscription: SubscriberRecord,
+    /// 所在邮件列表的标识
+    pub lists: Vec<String>,
+    pub tags: Vec<String>,
+    pub subscription_tokens: Vec<String>,
+    pub deliveries: Vec<DeliveryRecord>,
+    pub consents: Vec<ConsentRecord>,
+    /// 开启追踪的简报中记录的打开和点击
+    pub tracking_events: Vec<TrackingEventRecord>,
+}
+
+/// 查找邮箱对应的订阅者，邮箱不区分大小写
+#[tracing::instrument(name = "Find a subscriber by email", skip(pool, email))]
+pub async fn find_subscriber_by_email(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<SubscriberRecord>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, subscribed_at, email_format, paused_until
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        "#,
+        email,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up a subscriber by email.")?;
+    Ok(subscriber)
+}
+
+/// 导出为某个邮箱保存的所有数据，该邮箱没有订阅时返回'None'
+#[tracing::instrument(name = "Export the data of a subscriber", skip(pool, email))]
+pub async fn export_subscriber_data(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<SubscriberDataExport>, anyhow::Error> {
+    let subscription = match find_subscriber_by_email(pool, email).await? {
+        Some(subscription) => subscription,
+        None => return Ok(None),
+    };
+    let subscription_tokens = sqlx::query!(
+        r#"SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscription.id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscription tokens of a subscriber.")?
+    .into_iter()
+    .map(|r| r.subscription_token)
+    .collect();
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT
+            d.newsletter_issue_id,
+            i.title,
+            d.status,
+            d.error_message,
+            d.attempted_at
+        FROM issue_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.attempted_at
+        "#,
+        subscription.id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery history of a subscriber.")?;
+    let consents = get_consent_records(pool, Some(subscription.id)).await?;
+    let lists = get_subscriber_lists(pool, subscription.id).await?;
+    let tags = get_subscriber_tags(pool, subscription.id).await?;
+    let tracking_events = get_tracking_events(pool, subscription.id).await?;
+
+    Ok(Some(SubscriberDataExport {
+        subscription,
+        lists,
+        tags,
+        subscription_tokens,
+        deliveries,
+        consents,
+        tracking_events,
+    }))
+}
+
+/// 删除为某个邮箱保存的所有数据，并留下该邮箱的哈希值作为墓碑
+/// - 投递记录和同意记录随订阅者一起删除
+/// - 返回'false'表示该邮箱没有订阅
+#[tracing::instrument(name = "Erase the data of a subscriber", skip(pool, hmac_secret, email))]
+pub async fn erase_subscriber_data(
+    pool: &PgPool,
+    hmac_secret: &HmacSecret,
+    email: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let subscriber_id = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1) FOR UPDATE"#,
+        email,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to look up a subscriber by email.")?
+    .map(|r| r.id);
+    let subscriber_id = match subscriber_id {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(false),
+    };
+
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the subscription tokens of a subscriber.")?;
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete a subscriber.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO erased_subscribers (email_hash, erased_at, keyed)
+        VALUES ($1, now(), true)
+        ON CONFLICT (email_hash) DO UPDATE SET erased_at = EXCLUDED.erased_at
+        "#,
+        email_tombstone_hash(hmac_secret, email),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to record the tombstone of an erased subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+    Ok(true)
+}
+
+/// 该邮箱的数据是否曾经应订阅者的要求被删除
+/// - 注册和修改邮箱时拒绝这些邮箱，避免再次保存被要求删除的地址
+#[tracing::instrument(name = "Check whether an email was erased", skip(pool, hmac_secret, email))]
+pub async fn is_erased_email(
+    pool: &PgPool,
+    hmac_secret: &HmacSecret,
+    email: &str,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT email_hash FROM erased_subscribers WHERE email_hash = $1"#,
+        email_tombstone_hash(hmac_secret, email),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the erased subscribers.")?;
+    Ok(row.is_some())
+}
+
+/// 墓碑中保存的哈希值：用'HmacSecret'计算的HMAC-SHA256
+/// - 邮箱先去掉首尾空白并转为小写，再计算SHA-256，HMAC的输入是该SHA-256
+/// - 没有密钥就无法通过枚举常见邮箱来还原墓碑
+pub fn email_tombstone_hash(hmac_secret: &HmacSecret, email: &str) -> String {
+    let email_digest = hex::encode(Sha256::digest(email.trim().to_lowercase().as_bytes()));
+    keyed_tombstone_hash(hmac_secret, &email_digest)
+}
+
+fn keyed_tombstone_hash(hmac_secret: &HmacSecret, email_digest: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.0.expose_secret().as_bytes()).unwrap();
+    mac.update(b"erased_subscriber\n");
+    mac.update(email_digest.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// 把旧版本留下的墓碑('keyed'为false，保存的是邮箱的SHA-256)换成HMAC
+/// - 启动时在迁移之后调用，返回更新的墓碑数
+#[tracing::instrument(name = "Upgrade legacy tombstones", skip(pool, hmac_secret))]
+pub async fn upgrade_legacy_tombstones(
+    pool: &PgPool,
+    hmac_secret: &HmacSecret,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let legacy_tombstones = sqlx::query!(
+        r#"SELECT email_hash, erased_at FROM erased_subscribers WHERE NOT keyed FOR UPDATE"#,
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to retrieve the legacy tombstones.")?;
+    for tombstone in &legacy_tombstones {
+        // 同一个邮箱可能已经有新的墓碑，此时只删除旧的墓碑
+        sqlx::query!(
+            r#"
+            INSERT INTO erased_subscribers (email_hash, erased_at, keyed)
+            VALUES ($1, $2, true)
+            ON CONFLICT (email_hash) DO NOTHING
+            "#,
+            keyed_tombstone_hash(hmac_secret, &tombstone.email_hash),
+            tombstone.erased_at,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to record a keyed tombstone.")?;
+        sqlx::query!(
+            r#"DELETE FROM erased_subscribers WHERE email_hash = $1 AND NOT keyed"#,
+            tombstone.email_hash,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete a legacy tombstone.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to upgrade the legacy tombstones.")?;
+    Ok(legacy_tombstones.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data_request_token, email_tombstone_hash, verify_data_request_token, DataRequestKind};
+    use crate::signed_token::sign_token;
+    use crate::startup::HmacSecret;
+    use secrecy::Secret;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("key".to_string()))
+    }
+
+    #[test]
+    fn a_data_request_token_carries_the_email() {
+        let token = data_request_token(&secret(), DataRequestKind::Export, "a@example.com");
+        assert_eq!(
+            verify_data_request_token(&secret(), DataRequestKind::Export, &token).unwrap(),
+            "a@example.com"
+        );
+    }
+
+    #[test]
+    fn an_export_token_cannot_be_used_for_erasure() {
+        let token = data_request_token(&secret(), DataRequestKind::Export, "a@example.com");
+        assert!(verify_data_request_token(&secret(), DataRequestKind::Erasure, &token).is_err());
+    }
+
+    #[test]
+    fn an_expired_token_is_rejected() {
+        let expired_at = chrono::Utc::now().timestamp() - 1;
+        let token = sign_token(
+            &secret(),
+            DataRequestKind::Erasure.token_purpose(),
+            &format!("{}:a@example.com", expired_at),
+        );
+        assert!(verify_data_request_token(&secret(), DataRequestKind::Erasure, &token).is_err());
+    }
+
+    #[test]
+    fn the_tombstone_hash_ignores_case_and_whitespace() {
+        assert_eq!(
+            email_tombstone_hash(&secret(), " Ursula_Le_Guin@Gmail.com"),
+            email_tombstone_hash(&secret(), "ursula_le_guin@gmail.com")
+        );
+    }
+
+    #[test]
+    fn the_tombstone_hash_depends_on_the_key() {
+        let other_secret = HmacSecret(Secret::new("another key".to_string()));
+        assert_ne!(
+            email_tombstone_hash(&secret(), "ursula_le_guin@gmail.com"),
+            email_tombstone_hash(&other_secret, "ursula_le_guin@gmail.com")
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// 管理页面和导出中使用的订阅者信息
+#[derive(Debug, serde::Serialize)]
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    /// 'html'或'text'
+    pub email_format: String,
+    pub paused_until: Option<DateTime<Utc>>,
+}
+
+/// 列出订阅者，按订阅时间排序
+/// - 'status'为'None'时返回所有状态的订阅者
+#[tracing::instrument(
+    name = "List subscribers",
+    skip(pool),
+)]
+pub async fn list_subscribers(
+    pool: &PgPool,
+    status: Option<&str>,
+) -> Result<Vec<SubscriberRecord>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, subscribed_at, email_format, paused_until
+        FROM subscriptions
+        WHERE $1::text IS NULL OR status = $1
+        ORDER BY subscribed_at
+        "#,
+        status,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the list of subscribers.")?;
+    Ok(subscribers)
+}
+
+/// 查询单个订阅者，不存在时返回'None'
+#[tracing::instrument(
+    name = "Get a subscriber",
+    skip(pool),
+)]
+pub async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberRecord>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, subscribed_at, email_format, paused_until
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber.")?;
+    Ok(subscriber)
+}
@@ -1,18 +1,41 @@
 use tracing::{Subscriber, subscriber::set_global_default};
-use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt};
+use tracing_subscriber::{reload, EnvFilter, Registry, layer::SubscriberExt};
 use tracing_subscriber::fmt::MakeWriter;
 use tracing_log::LogTracer;
 use tracing_bunyan_formatter::{JsonStorageLayer, BunyanFormattingLayer};
 use tokio::task::JoinHandle;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::trace::{self as sdktrace, Tracer};
+use opentelemetry::sdk::Resource;
+use opentelemetry::trace::TraceError;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use std::collections::HashMap;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use crate::configuration::OtlpSettings;
 
-/// 获取tracing-subscriber中的注册表类型
+/// 修改订阅器的日志过滤规则，例如'info,sqlx=warn'
+#[derive(Clone)]
+pub struct LogFilter(reload::Handle<EnvFilter, Registry>);
+
+impl LogFilter {
+    pub fn reload(&self, filter: &str) -> Result<(), anyhow::Error> {
+        let filter = EnvFilter::try_new(filter)?;
+        self.0.reload(filter)?;
+        Ok(())
+    }
+}
+
+/// 获取tracing-subscriber中的注册表类型，以及修改其日志过滤规则的'LogFilter'
 /// - std::io:stdout 输出到终端，即日志可见，输出到屏幕
 /// - std::io::sink 输出到空设备，即日志被丢弃，不可见
+/// - otlp_tracer不为'None'时，跨度同时通过OpenTelemetry导出
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
-) -> impl Subscriber + Send + Sync 
+    otlp_tracer: Option<Tracer>,
+) -> (impl Subscriber + Send + Sync, LogFilter)
     where
         // 该语法结构是高阶trait bound,意思是Sink会实现MakeWrite trait
         Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
@@ -21,16 +44,85 @@ pub fn get_subscriber<Sink>(
         .unwrap_or_else(|_| 
             EnvFilter::new(env_filter)
         );
+    let (env_filter, handle) = reload::Layer::new(env_filter);
 
     let formatting_layer = BunyanFormattingLayer::new(
         name, 
         sink,
     );
  
-    Registry::default()
+    // 'Option<Layer>'也实现了Layer，为'None'时不做任何处理
+    let otel_layer = otlp_tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer));
+
+    let subscriber = Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(otel_layer);
+    (subscriber, LogFilter(handle))
+}
+
+/// 创建通过OTLP批量导出跨度的tracer，并使用W3C trace context('traceparent')在服务之间传递链路
+/// - 批量导出在tokio运行时中进行，因此需要在运行时中调用
+/// - 退出前调用'opentelemetry::global::shutdown_tracer_provider'，导出剩余的跨度
+pub fn init_otlp_tracer(service_name: &str, settings: &OtlpSettings) -> Result<Tracer, TraceError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_endpoint(traces_endpoint(&settings.endpoint))
+        .with_timeout(settings.timeout());
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(sdktrace::config().with_resource(Resource::new(vec![
+            KeyValue::new("service.name", service_name.to_string()),
+        ])))
+        .install_batch(opentelemetry::runtime::Tokio)
+}
+
+/// HTTP导出器直接使用配置的地址，收集器的地址需要补上OTLP/HTTP的'/v1/traces'路径
+fn traces_endpoint(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint)
+    }
+}
+
+/// 测试中修改OpenTelemetry全局状态时持有，同一时间只有一个测试能修改
+/// - 释放时恢复为不输出请求头的传播器；安装了tracer provider的测试需要自己关闭它
+#[cfg(test)]
+pub(crate) struct GlobalTelemetryGuard {
+    _lock: tokio::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+static GLOBAL_TELEMETRY: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[cfg(test)]
+impl GlobalTelemetryGuard {
+    pub(crate) async fn acquire() -> Self {
+        Self {
+            _lock: GLOBAL_TELEMETRY.lock().await,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Drop for GlobalTelemetryGuard {
+    fn drop(&mut self) {
+        global::set_text_map_propagator(opentelemetry::trace::noop::NoopTextMapPropagator::new());
+    }
+}
+
+/// 当前跨度的trace context，作为请求头添加到发往其他服务的请求上
+/// - 未配置OTLP时传播器不输出任何请求头
+pub fn trace_context_headers() -> HashMap<String, String> {
+    let context = tracing::Span::current().context();
+    let mut headers = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+    headers
 }
 
 /// 设置应用程序全局默认的tracing-subscriber订阅器
@@ -50,4 +142,113 @@ pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
 {
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::{call_and_read_body, init_service, TestRequest};
+    use actix_web::{web, App};
+    use opentelemetry::sdk::propagation::TraceContextPropagator;
+    use opentelemetry::sdk::trace::TracerProvider;
+    use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+    use tracing_actix_web::TracingLogger;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use crate::configuration::OtlpSettings;
+    use super::{get_subscriber, init_otlp_tracer, traces_endpoint, GlobalTelemetryGuard};
+
+    const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    async fn current_trace_id() -> String {
+        tracing::Span::current()
+            .context()
+            .span()
+            .span_context()
+            .trace_id()
+            .to_string()
+    }
+
+    #[test]
+    fn the_traces_path_is_appended_to_the_collector_address() {
+        assert_eq!(traces_endpoint("http://collector:4318"), "http://collector:4318/v1/traces");
+        assert_eq!(traces_endpoint("http://collector:4318/"), "http://collector:4318/v1/traces");
+        assert_eq!(
+            traces_endpoint("http://collector:4318/v1/traces"),
+            "http://collector:4318/v1/traces"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spans_are_exported_to_the_otlp_collector() {
+        let _global = GlobalTelemetryGuard::acquire().await;
+        // 用'MockServer'代替收集器，接收导出请求
+        let collector = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/traces"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&collector)
+            .await;
+        let settings = OtlpSettings {
+            endpoint: collector.uri(),
+            timeout_milliseconds: 1000,
+        };
+        let tracer = init_otlp_tracer("zero2prod", &settings).unwrap();
+        let (subscriber, _) = get_subscriber("test".into(), "info".into(), std::io::sink, Some(tracer));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("Span exported over OTLP").in_scope(|| {});
+        });
+        // 关闭时导出剩余的跨度，该调用会阻塞当前线程
+        tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider)
+            .await
+            .unwrap();
+
+        let requests = collector.received_requests().await.unwrap();
+        let span_name = b"Span exported over OTLP";
+        assert!(requests
+            .iter()
+            .filter(|r| r.url.path() == "/v1/traces")
+            .any(|r| r.body.windows(span_name.len()).any(|w| w == span_name)));
+    }
+
+    #[actix_web::test]
+    async fn incoming_requests_continue_the_callers_trace() {
+        let _global = GlobalTelemetryGuard::acquire().await;
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = TracerProvider::builder().build();
+        let (subscriber, _) = get_subscriber(
+            "test".into(),
+            "info".into(),
+            std::io::sink,
+            Some(provider.tracer("test")),
+        );
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let app = init_service(
+            App::new()
+                .wrap(TracingLogger::default())
+                .route("/", web::get().to(current_trace_id)),
+        )
+        .await;
+
+        let request = TestRequest::get()
+            .uri("/")
+            .insert_header(("traceparent", TRACEPARENT))
+            .to_request();
+        let trace_id = call_and_read_body(&app, request).await;
+
+        assert_eq!(trace_id, "0af7651916cd43dd8448eb211c80319c");
+    }
+
+    #[test]
+    fn the_log_filter_can_be_reloaded() {
+        let (subscriber, log_filter) = get_subscriber("test".into(), "info".into(), std::io::sink, None);
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(!tracing::enabled!(tracing::Level::DEBUG));
+            log_filter.reload("debug").unwrap();
+            assert!(tracing::enabled!(tracing::Level::DEBUG));
+        });
+        assert!(log_filter.reload("not a [valid filter").is_err());
+    }
+}
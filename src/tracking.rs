@@ -0,0 +1,347 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::signed_token::{sign_token, verify_token};
+use crate::startup::HmacSecret;
+
+/// 打开追踪像素和点击跳转链接的令牌用途
+const OPEN_TOKEN_PURPOSE: &str = "tracking_open";
+const CLICK_TOKEN_PURPOSE: &str = "tracking_click";
+
+/// 追踪到的事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackingEventKind {
+    /// 邮件客户端加载了追踪像素
+    Open,
+    /// 订阅者点击了简报中的链接
+    Click,
+}
+
+impl TrackingEventKind {
+    pub const ALL: [TrackingEventKind; 2] = [TrackingEventKind::Open, TrackingEventKind::Click];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackingEventKind::Open => "open",
+            TrackingEventKind::Click => "click",
+        }
+    }
+}
+
+impl TryFrom<String> for TrackingEventKind {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        TrackingEventKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("{} is not a known tracking event.", s))
+    }
+}
+
+/// 从追踪令牌中取出的信息
+#[derive(Debug, PartialEq, Eq)]
+pub struct TrackedRecipient {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Uuid,
+}
+
+/// 某个订阅者在某期简报中的追踪像素地址
+pub fn open_tracking_url(
+    base_url: &str,
+    secret: &HmacSecret,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> String {
+    let payload = format!("{}:{}", newsletter_issue_id, subscriber_id);
+    format!("{}/t/o/{}", base_url, sign_token(secret, OPEN_TOKEN_PURPOSE, &payload))
+}
+
+/// 经过'/t/c/{token}'跳转到'url'的链接
+/// - 目标地址在令牌中签名，跳转接口不会被当作开放重定向使用
+pub fn click_tracking_url(
+    base_url: &str,
+    secret: &HmacSecret,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    url: &str,
+) -> String {
+    let payload = format!("{}:{}:{}", newsletter_issue_id, subscriber_id, url);
+    format!("{}/t/c/{}", base_url, sign_token(secret, CLICK_TOKEN_PURPOSE, &payload))
+}
+
+/// 校验追踪像素的令牌
+pub fn verify_open_token(secret: &HmacSecret, token: &str) -> Result<TrackedRecipient, anyhow::Error> {
+    let payload = verify_token(secret, OPEN_TOKEN_PURPOSE, token)?;
+    let (issue, subscriber) = payload
+        .split_once(':')
+        .context("The token payload is malformed.")?;
+    parse_recipient(issue, subscriber)
+}
+
+/// 校验点击跳转的令牌，返回收件人和目标地址
+pub fn verify_click_token(
+    secret: &HmacSecret,
+    token: &str,
+) -> Result<(TrackedRecipient, String), anyhow::Error> {
+    let payload = verify_token(secret, CLICK_TOKEN_PURPOSE, token)?;
+    let mut parts = payload.splitn(3, ':');
+    let (issue, subscriber, url) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(issue), Some(subscriber), Some(url)) => (issue, subscriber, url),
+        _ => anyhow::bail!("The token payload is malformed."),
+    };
+    Ok((parse_recipient(issue, subscriber)?, url.to_owned()))
+}
+
+fn parse_recipient(issue: &str, subscriber: &str) -> Result<TrackedRecipient, anyhow::Error> {
+    Ok(TrackedRecipient {
+        newsletter_issue_id: Uuid::parse_str(issue).context("The token has no issue id.")?,
+        subscriber_id: Uuid::parse_str(subscriber).context("The token has no subscriber id.")?,
+    })
+}
+
+/// 给某个订阅者的HTML内容加上追踪
+/// - 'href'为'http'或'https'地址的链接改为经过点击跳转，其他链接(例如'mailto:')保持不变
+/// - 在末尾插入追踪像素
+/// - 纯文本内容不做处理，只收纯文本的订阅者不会被追踪
+pub fn add_tracking(
+    html: &str,
+    base_url: &str,
+    secret: &HmacSecret,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> String {
+    let tracked = rewrite_links(html, |url| {
+        click_tracking_url(base_url, secret, newsletter_issue_id, subscriber_id, url)
+    });
+    format!(
+        r#"{}<img src="{}" width="1" height="1" alt="" />"#,
+        tracked,
+        open_tracking_url(base_url, secret, newsletter_issue_id, subscriber_id),
+    )
+}
+
+/// 用'rewrite'替换所有'href'属性中的'http'和'https'地址
+/// - 属性值是经过HTML转义的，交给'rewrite'之前先解码
+fn rewrite_links(html: &str, rewrite: impl Fn(&str) -> String) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("href=") {
+        let value_start = start + "href=".len();
+        let quote = match rest[value_start..].chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => {
+                output.push_str(&rest[..value_start]);
+                rest = &rest[value_start..];
+                continue;
+            }
+        };
+        let url_start = value_start + 1;
+        let url_end = match rest[url_start..].find(quote) {
+            Some(end) => url_start + end,
+            None => break,
+        };
+        let raw_url = &rest[url_start..url_end];
+        let url = htmlescape::decode_html(raw_url).unwrap_or_else(|_| raw_url.to_owned());
+        output.push_str(&rest[..url_start]);
+        if url.starts_with("http://") || url.starts_with("https://") {
+            output.push_str(&rewrite(&url));
+        } else {
+            output.push_str(raw_url);
+        }
+        rest = &rest[url_end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// 记录一次打开或点击
+/// - 订阅者的数据已被删除时不记录
+#[tracing::instrument(name = "Record a tracking event", skip(pool, recipient, url))]
+pub async fn record_tracking_event(
+    pool: &PgPool,
+    recipient: &TrackedRecipient,
+    kind: TrackingEventKind,
+    url: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO tracking_events (newsletter_issue_id, subscriber_id, kind, url, occurred_at)
+        SELECT $1, $2, $3, $4, now()
+        WHERE EXISTS (SELECT 1 FROM subscriptions WHERE id = $2)
+        "#,
+        recipient.newsletter_issue_id,
+        recipient.subscriber_id,
+        kind.as_str(),
+        url,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record a tracking event.")?;
+    Ok(())
+}
+
+/// 某个订阅者的追踪记录，用于数据导出
+#[derive(Debug, serde::Serialize)]
+pub struct TrackingEventRecord {
+    pub newsletter_issue_id: Uuid,
+    pub kind: String,
+    pub url: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// 查询某个订阅者的所有追踪记录，按时间排序
+#[tracing::instrument(name = "Get the tracking events of a subscriber", skip(pool))]
+pub async fn get_tracking_events(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<TrackingEventRecord>, anyhow::Error> {
+    let events = sqlx::query_as!(
+        TrackingEventRecord,
+        r#"
+        SELECT newsletter_issue_id, kind, url, occurred_at
+        FROM tracking_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the tracking events of a subscriber.")?;
+    Ok(events)
+}
+
+/// 往期简报的投递和追踪统计
+pub struct IssueSummary {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub tracking_enabled: bool,
+    pub sent: i64,
+    pub failed: i64,
+    pub opens: i64,
+    pub unique_opens: i64,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+}
+
+impl IssueSummary {
+    /// 打开过的订阅者占成功投递数的比例
+    pub fn open_rate(&self) -> f64 {
+        rate(self.unique_opens, self.sent)
+    }
+
+    /// 点击过的订阅者占成功投递数的比例
+    pub fn click_rate(&self) -> f64 {
+        rate(self.unique_clicks, self.sent)
+    }
+}
+
+fn rate(count: i64, sent: i64) -> f64 {
+    if sent == 0 {
+        0.0
+    } else {
+        count as f64 / sent as f64
+    }
+}
+
+/// 列出往期简报及其统计，最新的在前
+#[tracing::instrument(name = "Get the issue history", skip(pool))]
+pub async fn get_issue_history(pool: &PgPool) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            i.tracking_enabled,
+            (SELECT COUNT(*) FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.status = 'sent') AS "sent!",
+            (SELECT COUNT(*) FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.status = 'failed') AS "failed!",
+            COUNT(e.subscriber_id) FILTER (WHERE e.kind = 'open') AS "opens!",
+            COUNT(DISTINCT e.subscriber_id) FILTER (WHERE e.kind = 'open') AS "unique_opens!",
+            COUNT(e.subscriber_id) FILTER (WHERE e.kind = 'click') AS "clicks!",
+            COUNT(DISTINCT e.subscriber_id) FILTER (WHERE e.kind = 'click') AS "unique_clicks!"
+        FROM newsletter_issues i
+        LEFT JOIN tracking_events e ON e.newsletter_issue_id = i.newsletter_issue_id
+        GROUP BY i.newsletter_issue_id
+        ORDER BY i.published_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the issue history.")?;
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_tracking, click_tracking_url, open_tracking_url, rewrite_links, verify_click_token,
+        verify_open_token, TrackedRecipient, TrackingEventKind,
+    };
+    use crate::startup::HmacSecret;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("key".to_string()))
+    }
+
+    fn token(url: &str) -> &str {
+        url.rsplit('/').next().unwrap()
+    }
+
+    #[test]
+    fn tracking_event_kinds_round_trip() {
+        for kind in TrackingEventKind::ALL {
+            assert_eq!(TrackingEventKind::try_from(kind.as_str().to_string()), Ok(kind));
+        }
+        assert!(TrackingEventKind::try_from("bounce".to_string()).is_err());
+    }
+
+    #[test]
+    fn an_open_token_carries_the_issue_and_the_subscriber() {
+        let (issue, subscriber) = (Uuid::new_v4(), Uuid::new_v4());
+        let url = open_tracking_url("http://127.0.0.1", &secret(), issue, subscriber);
+        assert_eq!(
+            verify_open_token(&secret(), token(&url)).unwrap(),
+            TrackedRecipient { newsletter_issue_id: issue, subscriber_id: subscriber }
+        );
+    }
+
+    #[test]
+    fn a_click_token_carries_the_target_url() {
+        let (issue, subscriber) = (Uuid::new_v4(), Uuid::new_v4());
+        let target = "https://example.com/a:b?c=d";
+        let url = click_tracking_url("http://127.0.0.1", &secret(), issue, subscriber, target);
+        let (recipient, url) = verify_click_token(&secret(), token(&url)).unwrap();
+        assert_eq!(recipient.subscriber_id, subscriber);
+        assert_eq!(url, target);
+    }
+
+    #[test]
+    fn an_open_token_cannot_be_used_as_a_click_token() {
+        let url = open_tracking_url("http://127.0.0.1", &secret(), Uuid::new_v4(), Uuid::new_v4());
+        assert!(verify_click_token(&secret(), token(&url)).is_err());
+    }
+
+    #[test]
+    fn only_http_links_are_rewritten() {
+        let html = r#"<a href="https://example.com/?a=1&amp;b=2">x</a> <a href='mailto:a@b.c'>y</a> <a href=bare>z</a>"#;
+        let rewritten = rewrite_links(html, |url| format!("[{}]", url));
+        assert_eq!(
+            rewritten,
+            r#"<a href="[https://example.com/?a=1&b=2]">x</a> <a href='mailto:a@b.c'>y</a> <a href=bare>z</a>"#
+        );
+    }
+
+    #[test]
+    fn a_tracking_pixel_is_appended() {
+        let html = add_tracking("<p>Hi</p>", "http://127.0.0.1", &secret(), Uuid::new_v4(), Uuid::new_v4());
+        assert!(html.starts_with("<p>Hi</p><img src=\"http://127.0.0.1/t/o/"));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::helper::{spawn_app, assert_is_redirect_to};
+use crate::helper::{spawn_app, spawn_app_with, assert_is_redirect_to};
 
 #[tokio::test]
 async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
@@ -34,4 +34,34 @@ async fn logout_clears_session_state() {
     // 第五部分，尝试加载管理面板
     let response = app.get_admin_dashboard().await;
     assert_is_redirect_to(&response, "/login");
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn an_idle_session_expires() {
+    let app = spawn_app_with(|c| c.session.idle_timeout_seconds = 1).await;
+    app.test_user.login(&app).await;
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Your session has expired. Please log in again.</i></p>"));
+}
+
+#[tokio::test]
+async fn a_session_expires_after_its_absolute_lifetime_even_if_active() {
+    let app = spawn_app_with(|c| c.session.absolute_timeout_seconds = 3).await;
+    app.test_user.login(&app).await;
+
+    // 持续活动也无法超过最长有效期
+    for _ in 0..3 {
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        let response = app.get_admin_dashboard().await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
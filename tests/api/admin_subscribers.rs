@@ -0,0 +1,95 @@
+use crate::helper::{assert_is_redirect_to, spawn_app};
+use crate::newsletter::{create_confirmed_subscriber, create_unconfirmed_subscriber};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    let app = spawn_app().await;
+
+    for path in ["", "/export", format!("/{}", Uuid::new_v4()).as_str()] {
+        let response = app.get_admin_subscribers(path).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_with_a_link_to_their_details() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    let html_page = app.get_admin_subscribers("").await.text().await.unwrap();
+
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/subscribers/{}">ursula_le_guin@gmail.com</a>"#,
+        subscriber_id
+    )));
+    assert!(html_page.contains("<td>pending_confirmation</td>"));
+}
+
+#[tokio::test]
+async fn the_subscriber_detail_view_shows_the_consent_evidence() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    let response = app.get_admin_subscribers(&format!("/{}", subscriber_id)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+
+    assert!(html_page.contains("<h2>ursula_le_guin@gmail.com</h2>"));
+    assert!(html_page.contains("<li>Status: confirmed</li>"));
+    assert!(html_page.contains("<li>Mailing lists: newsletter</li>"));
+    assert!(html_page.contains("<td>signup_form</td>"));
+    assert!(html_page.contains("<td>2025-10-01</td>"));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+    assert!(!html_page.contains("not confirmed"));
+}
+
+#[tokio::test]
+async fn an_unknown_subscriber_is_not_found() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_admin_subscribers(&format!("/{}", Uuid::new_v4())).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_export_includes_the_consent_evidence() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_admin_subscribers("/export").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"].to_str().unwrap().starts_with("text/csv"));
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .contains("subscribers.csv"));
+    let csv = response.text().await.unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("subscriber_id,email,name,status,subscribed_at,consent_source"));
+    let fields: Vec<_> = lines[1].split(',').collect();
+    assert_eq!(fields[1], "ursula_le_guin@gmail.com");
+    assert_eq!(fields[3], "confirmed");
+    assert_eq!(fields[5], "signup_form");
+    assert_eq!(fields[6], "2025-10-01");
+    assert_eq!(fields[8], "127.0.0.1");
+    // 确认时间已记录
+    assert!(!fields[10].is_empty());
+}
@@ -0,0 +1,256 @@
+use crate::helper::{spawn_app, TestApp};
+use crate::newsletter::{create_confirmed_subscriber, create_unconfirmed_subscriber};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const ALL_SCOPES: [&str; 4] = [
+    "newsletters:publish",
+    "subscribers:read",
+    "subscribers:write",
+    "deliveries:read",
+];
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Release notes",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+async fn post_api_newsletters(
+    app: &TestApp,
+    api_key: &str,
+    body: &serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(&format!("{}/api/v1/newsletters", &app.address))
+        .bearer_auth(api_key)
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn post_api_newsletters_idempotently(
+    app: &TestApp,
+    api_key: &str,
+    idempotency_key: &str,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(&format!("{}/api/v1/newsletters", &app.address))
+        .bearer_auth(api_key)
+        .header("Idempotency-Key", idempotency_key)
+        .json(&newsletter_body())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_api(app: &TestApp, api_key: &str, path: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(&format!("{}/api/v1{}", &app.address, path))
+        .bearer_auth(api_key)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn requests_without_an_api_key_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(&format!("{}/api/v1/newsletters", &app.address))
+        .json(&newsletter_body())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], r#"Bearer realm="api""#);
+}
+
+#[tokio::test]
+async fn requests_with_an_unknown_api_key_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = get_api(&app, "z2p_unknown_key", "/subscribers").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_revoked_api_key_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&ALL_SCOPES).await;
+    assert_eq!(get_api(&app, &api_key, "/subscribers").await.status().as_u16(), 200);
+
+    let html_page = app.get_api_keys_html().await;
+    let marker = r#"name="api_key_id" value=""#;
+    let start = html_page.find(marker).unwrap() + marker.len();
+    let api_key_id = &html_page[start..start + 36];
+    app.post_revoke_api_key(api_key_id).await;
+
+    assert_eq!(get_api(&app, &api_key, "/subscribers").await.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_api_key_itself_is_never_shown_on_the_admin_page() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:read"]).await;
+
+    let html_page = app.get_api_keys_html().await;
+
+    assert!(html_page.contains("test key"));
+    assert!(!html_page.contains(&api_key));
+}
+
+#[tokio::test]
+async fn an_api_key_without_the_required_scope_is_forbidden() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:read"]).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_api_newsletters(&app, &api_key, &newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn newsletters_published_via_the_api_are_delivered_and_tracked() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&ALL_SCOPES).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_api_newsletters(&app, &api_key, &newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["sent"], 1);
+    assert_eq!(report["failed"], 0);
+
+    let issue_id = report["newsletter_issue_id"].as_str().unwrap();
+    let response = get_api(&app, &api_key, &format!("/newsletters/{}", issue_id)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let status: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(status["title"], "Release notes");
+    assert_eq!(status["sent"], 1);
+    assert_eq!(status["failed"], 0);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_reported() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&ALL_SCOPES).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_api_newsletters(&app, &api_key, &newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["sent"], 0);
+    assert_eq!(report["failed"], 1);
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&ALL_SCOPES).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let mut body = newsletter_body();
+    body["lists"] = serde_json::json!(["newsletter", "does-not-exist"]);
+    let response = post_api_newsletters(&app, &api_key, &body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["error"], "does-not-exist is not a known mailing list.");
+}
+
+#[tokio::test]
+async fn the_delivery_status_of_an_unknown_issue_is_not_found() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["deliveries:read"]).await;
+
+    let response = get_api(&app, &api_key, &format!("/newsletters/{}", uuid::Uuid::new_v4())).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn publishing_via_the_api_honours_the_idempotency_key() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&ALL_SCOPES).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let response1 = post_api_newsletters_idempotently(&app, &api_key, &idempotency_key).await;
+    let response2 = post_api_newsletters_idempotently(&app, &api_key, &idempotency_key).await;
+
+    assert_eq!(response1.status().as_u16(), 201);
+    assert_eq!(response2.status().as_u16(), 201);
+    assert_eq!(response1.text().await.unwrap(), response2.text().await.unwrap());
+    // Mock在Drop时验证邮件只发送了一次
+}
+
+#[tokio::test]
+async fn subscribers_can_be_listed_and_filtered_by_status() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:read"]).await;
+
+    let response = get_api(&app, &api_key, "/subscribers").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let subscribers = body["subscribers"].as_array().unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscribers[0]["status"], "pending_confirmation");
+    let consents = subscribers[0]["consents"].as_array().unwrap();
+    assert_eq!(consents.len(), 1);
+    assert_eq!(consents[0]["source"], "signup_form");
+    assert!(consents[0]["confirmed_at"].is_null());
+
+    let response = get_api(&app, &api_key, "/subscribers?status=confirmed").await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["subscribers"].as_array().unwrap().is_empty());
+}
@@ -0,0 +1,245 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use crate::mailing_lists::create_confirmed_subscriber_with_topics;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+struct AuditRow {
+    actor_user_id: Option<Uuid>,
+    action: String,
+    target: Option<String>,
+    user_agent: Option<String>,
+}
+
+async fn audit_rows(app: &TestApp) -> Vec<AuditRow> {
+    sqlx::query_as!(
+        AuditRow,
+        "SELECT actor_user_id, action, target, user_agent FROM audit_log ORDER BY occurred_at",
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn make_owner(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET role = 'owner' WHERE user_id = $1",
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_audit_log() {
+    let app = spawn_app().await;
+    let response = app.get_audit_log("").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logging_in_and_out_is_recorded() {
+    let app = spawn_app().await;
+
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+
+    let rows = audit_rows(&app).await;
+    let actions: Vec<_> = rows.iter().map(|r| r.action.as_str()).collect();
+    assert_eq!(actions, ["login", "logout"]);
+    assert!(rows.iter().all(|r| r.actor_user_id == Some(app.test_user.user_id)));
+    assert_eq!(rows[0].target.as_deref(), Some("password"));
+    // reqwest默认不发送User-Agent
+    assert!(rows[0].user_agent.is_none());
+}
+
+#[tokio::test]
+async fn a_failed_login_is_not_recorded() {
+    let app = spawn_app().await;
+
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    }))
+    .await;
+
+    assert!(audit_rows(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn changing_the_password_is_recorded() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let new_password = Uuid::new_v4().to_string();
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    let rows = audit_rows(&app).await;
+    assert_eq!(rows.last().unwrap().action, "change_password");
+}
+
+#[tokio::test]
+async fn publishing_a_newsletter_is_recorded_with_the_issue_as_target() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let rows = audit_rows(&app).await;
+    let row = rows.last().unwrap();
+    assert_eq!(row.action, "publish_newsletter");
+    assert_eq!(row.target, Some(format!("newsletter_issue/{}", issue_id)));
+}
+
+#[tokio::test]
+async fn tagging_a_subscriber_is_recorded_with_the_subscriber_as_target() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id =
+        create_confirmed_subscriber_with_topics(&app, "ursula_le_guin@gmail.com", &[]).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+
+    let csrf_token = app.csrf_token().await;
+    app.api_client
+        .post(&format!("{}/admin/subscribers/{}/tags", &app.address, subscriber_id))
+        .form(&[("tags", "beta"), ("csrf_token", csrf_token.as_str())])
+        .send()
+        .await
+        .unwrap();
+    reqwest::Client::new()
+        .post(&format!("{}/api/v1/subscribers/{}/tags", &app.address, subscriber_id))
+        .bearer_auth(&api_key)
+        .json(&serde_json::json!({ "remove": ["beta"] }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let rows: Vec<_> = audit_rows(&app)
+        .await
+        .into_iter()
+        .filter(|r| r.action.ends_with("tag_subscriber"))
+        .collect();
+    let target = Some(format!("subscriber/{}", subscriber_id));
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].action, "tag_subscriber");
+    assert_eq!(rows[1].action, "untag_subscriber");
+    assert!(rows.iter().all(|r| r.target == target));
+    assert!(rows.iter().all(|r| r.actor_user_id == Some(app.test_user.user_id)));
+}
+
+#[tokio::test]
+async fn the_audit_log_cannot_be_modified() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let update = sqlx::query!("UPDATE audit_log SET action = 'nothing'")
+        .execute(&app.db_pool)
+        .await;
+    assert!(update.is_err());
+    let delete = sqlx::query!("DELETE FROM audit_log")
+        .execute(&app.db_pool)
+        .await;
+    assert!(delete.is_err());
+    assert_eq!(audit_rows(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered_by_action() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_audit_log_html("action=logout").await;
+    assert_eq!(html_page.matches("<td>logout</td>").count(), 1);
+    assert!(!html_page.contains("<td>login</td>"));
+
+    let html_page = app.get_audit_log_html("action=").await;
+    assert_eq!(html_page.matches("<td>login</td>").count(), 2);
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered_by_date() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let today = chrono::Utc::now().date_naive();
+
+    let html_page = app.get_audit_log_html(&format!("since={0}&until={0}", today)).await;
+    assert!(html_page.contains("<td>login</td>"));
+
+    let yesterday = today.pred_opt().unwrap();
+    let html_page = app.get_audit_log_html(&format!("until={}", yesterday)).await;
+    assert!(!html_page.contains("<td>login</td>"));
+}
+
+#[tokio::test]
+async fn an_invalid_filter_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_audit_log("since=yesterday").await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.get_audit_log("action=delete_everything").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn an_admin_only_sees_their_own_actions() {
+    let app = spawn_app().await;
+    let other_user = TestUser::generate();
+    other_user.argon2_store(&app.db_pool).await;
+    other_user.login(&app).await;
+    app.post_logout().await;
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_audit_log_html("").await;
+    assert!(!html_page.contains(&other_user.username));
+    assert!(html_page.contains(&app.test_user.username));
+    // admin无法按用户过滤
+    assert!(!html_page.contains(r#"name="actor""#));
+}
+
+#[tokio::test]
+async fn an_owner_sees_and_filters_the_actions_of_all_users() {
+    let app = spawn_app().await;
+    make_owner(&app).await;
+    let other_user = TestUser::generate();
+    other_user.argon2_store(&app.db_pool).await;
+    other_user.login(&app).await;
+    app.post_logout().await;
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_audit_log_html("").await;
+    assert!(html_page.contains(&other_user.username));
+    assert!(html_page.contains(&app.test_user.username));
+
+    let html_page = app
+        .get_audit_log_html(&format!("actor={}", other_user.username))
+        .await;
+    assert!(html_page.contains("<td>logout</td>"));
+    assert!(!html_page.contains(&format!("<td>{}</td>", app.test_user.username)));
+}
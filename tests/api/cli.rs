@@ -0,0 +1,199 @@
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::authentication::{change_password, create_user, get_user_id, UserRole};
+use zero2prod::cli::{confirm_subscriber_by_email, run_command, send_test_email, Command};
+use zero2prod::configuration::get_configuration;
+use zero2prod::idempotency::prune_saved_responses;
+use crate::helper::{assert_is_redirect_to, spawn_app};
+use crate::newsletter::create_unconfirmed_subscriber;
+
+#[tokio::test]
+async fn created_admins_can_log_in() {
+    let app = spawn_app().await;
+    let hashing = get_configuration().unwrap().password_hashing;
+
+    let user_id = create_user(
+        &app.db_pool,
+        "ursula",
+        Secret::new("a-long-enough-password".into()),
+        UserRole::Owner,
+        &hashing,
+    )
+    .await
+    .unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula",
+            "password": "a-long-enough-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let role = sqlx::query!("SELECT role FROM users WHERE user_id = $1", user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .role;
+    assert_eq!(role, "owner");
+}
+
+#[tokio::test]
+async fn usernames_must_be_unique() {
+    let app = spawn_app().await;
+    let hashing = get_configuration().unwrap().password_hashing;
+
+    let outcome = create_user(
+        &app.db_pool,
+        &app.test_user.username,
+        Secret::new(Uuid::new_v4().to_string()),
+        UserRole::Admin,
+        &hashing,
+    )
+    .await;
+
+    assert!(outcome.unwrap_err().to_string().contains("already exists"));
+}
+
+#[tokio::test]
+async fn a_reset_password_replaces_the_old_one() {
+    let app = spawn_app().await;
+    let hashing = get_configuration().unwrap().password_hashing;
+    let user_id = get_user_id(&app.db_pool, &app.test_user.username)
+        .await
+        .unwrap()
+        .unwrap();
+
+    change_password(user_id, Secret::new("a-brand-new-password".into()), &app.db_pool, &hashing)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "a-brand-new-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn pending_subscribers_can_be_confirmed_by_email() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    confirm_subscriber_by_email(&app.db_pool, "Ursula_Le_Guin@gmail.com")
+        .await
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    let consent = sqlx::query!("SELECT confirmation_user_agent FROM subscription_consents")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        consent.confirmation_user_agent.as_deref(),
+        Some("zero2prod confirm-subscriber")
+    );
+}
+
+#[tokio::test]
+async fn confirming_an_unknown_email_fails() {
+    let app = spawn_app().await;
+
+    let outcome = confirm_subscriber_by_email(&app.db_pool, "nobody@example.com").await;
+
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn test_emails_go_through_the_email_provider() {
+    let app = spawn_app().await;
+    let mut settings = get_configuration().unwrap().email_client;
+    settings.base_url = app.email_server.uri();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    send_test_email(&settings, "ursula_le_guin@gmail.com").await.unwrap();
+}
+
+#[tokio::test]
+async fn only_old_idempotent_responses_are_pruned() {
+    let app = spawn_app().await;
+    let user_id = get_user_id(&app.db_pool, &app.test_user.username)
+        .await
+        .unwrap()
+        .unwrap();
+    for (key, age) in [("old", "3 days"), ("recent", "1 hour")] {
+        sqlx::query(
+            r#"
+            INSERT INTO idempotency (
+                user_id, idempotency_key, response_status_code,
+                response_headers, response_body, created_at
+            )
+            VALUES ($1, $2, 200, '{}', '', now() - $3::interval)
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(age)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    let deleted = prune_saved_responses(&app.db_pool, chrono::Duration::hours(48))
+        .await
+        .unwrap();
+
+    assert_eq!(deleted, 1);
+    let remaining: Vec<String> = sqlx::query!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.idempotency_key)
+        .collect();
+    assert_eq!(remaining, ["recent"]);
+}
+
+#[tokio::test]
+async fn check_config_lists_every_problem() {
+    let mut configuration = get_configuration().unwrap();
+    configuration.email_client.base_url = "localhost".into();
+    configuration.email_client.sender_email = "not-an-email".into();
+
+    let error = run_command(Command::CheckConfig, &configuration)
+        .await
+        .unwrap_err()
+        .to_string();
+
+    assert!(error.contains("2 problem(s)"));
+    assert!(error.contains("email_client.base_url"));
+    assert!(error.contains("email_client.sender_email"));
+}
+
+#[tokio::test]
+async fn serve_is_not_an_admin_command() {
+    let configuration = get_configuration().unwrap();
+
+    let outcome = run_command(Command::Serve, &configuration).await;
+
+    assert!(outcome.is_err());
+}
@@ -0,0 +1,94 @@
+use crate::helper::{assert_is_redirect_to, build_api_client, spawn_app, TestApp};
+
+async fn post_logout_with(app: &TestApp, body: &serde_json::Value) -> reqwest::Response {
+    app.api_client
+        .post(&format!("{}/admin/logout", &app.address))
+        .form(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn admin_forms_contain_a_csrf_token() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let token = app.csrf_token().await;
+    assert_eq!(token.len(), 32);
+
+    let field = format!(r#"name="csrf_token" value="{}""#, token);
+    assert!(app.get_change_password_html().await.contains(&field));
+    assert!(app.get_publish_newsletter_html().await.contains(&field));
+    assert!(app.get_sessions_html().await.contains(&field));
+}
+
+#[tokio::test]
+async fn a_post_without_a_csrf_token_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = post_logout_with(&app, &serde_json::json!({})).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    // 会话仍然有效
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn a_post_with_a_mismatched_csrf_token_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = post_logout_with(
+        &app,
+        &serde_json::json!({ "csrf_token": "not-the-token-of-this-session" }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_csrf_token_of_another_session_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // 另一个客户端登录后得到的令牌属于另一个会话
+    let other_client = build_api_client();
+    let response = other_client
+        .post(&format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = other_client
+        .get(&format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let marker = r#"name="csrf_token" value=""#;
+    let start = html_page.find(marker).unwrap() + marker.len();
+    let other_token = &html_page[start..start + 32];
+    assert_ne!(other_token, app.csrf_token().await);
+
+    let response = post_logout_with(&app, &serde_json::json!({ "csrf_token": other_token })).await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn a_post_with_the_session_csrf_token_is_accepted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let token = app.csrf_token().await;
+    let response = post_logout_with(&app, &serde_json::json!({ "csrf_token": token })).await;
+    assert_is_redirect_to(&response, "/login");
+}
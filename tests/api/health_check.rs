@@ -1,4 +1,4 @@
-use crate::helper::spawn_app;
+use crate::helper::{spawn_app, spawn_app_with};
 
 
 #[tokio::test]
@@ -14,4 +14,89 @@ async fn health_check_works() {
 
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn liveness_check_works() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!("{}/health/live", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(response.status().is_success());
+    assert_eq!(Some(0), response.content_length());
+}
+
+#[tokio::test]
+async fn readiness_check_reports_every_critical_component() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!("{}/health/ready", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["status"], "ok");
+    let checks = report["checks"].as_array().unwrap();
+    for name in ["database", "redis", "migrations"] {
+        let check = checks.iter().find(|c| c["name"] == name).unwrap();
+        assert_eq!(check["status"], "up");
+        assert_eq!(check["critical"], true);
+        assert!(check["latency_ms"].is_u64());
+    }
+    assert!(checks.iter().all(|c| c["name"] != "email_provider"));
+}
+
+#[tokio::test]
+async fn readiness_check_fails_with_pending_migrations() {
+    let app = spawn_app().await;
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(&format!("{}/health/ready", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 503);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["status"], "fail");
+    let migrations = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["name"] == "migrations")
+        .unwrap()
+        .clone();
+    assert_eq!(migrations["status"], "down");
+    // 失败原因只写入日志
+    assert!(migrations.get("error").is_none());
+}
+
+#[tokio::test]
+async fn an_unreachable_email_provider_degrades_readiness_without_failing_it() {
+    let app = spawn_app_with(|c| {
+        c.health.check_email_provider = true;
+        c.email_client.base_url = "http://127.0.0.1:1".into();
+    })
+    .await;
+
+    let response = reqwest::get(&format!("{}/health/ready", &app.address))
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["status"], "degraded");
+    let email_provider = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["name"] == "email_provider")
+        .unwrap()
+        .clone();
+    assert_eq!(email_provider["status"], "down");
+    assert_eq!(email_provider["critical"], false);
+}
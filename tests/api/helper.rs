@@ -2,12 +2,16 @@ use reqwest::Response;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings};
+use zero2prod::migrations::MIGRATOR;
 use zero2prod::startup::get_connection_pool;
 use zero2prod::startup::Application;
+use zero2prod::startup::HmacSecret;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 use once_cell::sync::Lazy;
 use sha3::Digest;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use argon2::password_hash::SaltString;
 use argon2::{Argon2, Algorithm, Params, PasswordHasher, Version};
 
@@ -18,10 +22,10 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
     let subscriber_name = "test".to_string();
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let (subscriber, _) = get_subscriber(subscriber_name, default_filter_level, std::io::stdout, None);
         init_subscriber(subscriber); 
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let (subscriber, _) = get_subscriber(subscriber_name, default_filter_level, std::io::sink, None);
         init_subscriber(subscriber);
     }
 });
@@ -39,6 +43,13 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    /// 在单独端口上提供'/metrics'时的端口
+    pub metrics_port: Option<u16>,
+    /// 触发后应用程序开始优雅关闭
+    pub shutdown: CancellationToken,
+    /// 应用程序关闭完成后结束
+    pub server: JoinHandle<Result<(), std::io::Error>>,
+    pub hmac_secret: HmacSecret,
 }
 
 pub struct ConfirmationLinks {
@@ -65,7 +76,131 @@ impl TestApp {
     {
         self.api_client
             .post(&format!("{}/admin/newsletters", &self.address))
-            .form(body)
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
+        self.get_sessions().await.text().await.unwrap()
+    }
+
+    pub async fn post_revoke_other_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/sessions/revoke_others", &self.address))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_user_sessions(&self, user_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/sessions/revoke_user", &self.address))
+            .form(&self.with_csrf_token(&serde_json::json!({ "user_id": user_id })).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// 通过管理页面创建API密钥，返回完整的密钥
+    /// - 调用前需要先登录
+    pub async fn create_api_key(&self, scopes: &[&str]) -> String {
+        let csrf_token = self.csrf_token().await;
+        let mut form = vec![("name", "test key"), ("csrf_token", csrf_token.as_str())];
+        form.extend(scopes.iter().map(|scope| ("scope", *scope)));
+        let html = self.api_client
+            .post(&format!("{}/admin/api_keys", &self.address))
+            .form(&form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap();
+        let marker = r#"<code id="api-key">"#;
+        let start = html.find(marker).expect("No API key in the response.") + marker.len();
+        let end = start + html[start..].find('<').unwrap();
+        html[start..end].to_string()
+    }
+
+    pub async fn get_api_keys_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/api_keys", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_api_key(&self, api_key_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/api_keys/revoke", &self.address))
+            .form(&self.with_csrf_token(&serde_json::json!({ "api_key_id": api_key_id })).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// 'path'是'/admin/subscribers'之后的部分，例如'/export'
+    pub async fn get_admin_subscribers(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/subscribers{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// 'query'是审计日志页面的查询字符串，例如'action=login'
+    pub async fn get_audit_log(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/audit?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_audit_log_html(&self, query: &str) -> String {
+        self.get_audit_log(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_admin_lists_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_list(&self, slug: &str, name: &str) -> reqwest::Response {
+        let body = serde_json::json!({ "slug": slug, "name": name, "description": "" });
+        self.api_client
+            .post(&format!("{}/admin/lists", &self.address))
+            .form(&self.with_csrf_token(&body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_delete_list(&self, list_id: Uuid) -> reqwest::Response {
+        let body = serde_json::json!({ "list_id": list_id });
+        self.api_client
+            .post(&format!("{}/admin/lists/delete", &self.address))
+            .form(&self.with_csrf_token(&body).await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -74,6 +209,7 @@ impl TestApp {
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/admin/logout", &self.address))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -108,7 +244,7 @@ impl TestApp {
     {
         self.api_client
             .post(&format!("{}/admin/password", &self.address))
-            .form(body)
+            .form(&self.with_csrf_token(body).await)
             .send()
             .await
             .expect("Failed to execute request")
@@ -126,6 +262,28 @@ impl TestApp {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
 
+    /// 从管理面板的表单中取出当前会话的CSRF令牌，未登录时返回空字符串
+    pub async fn csrf_token(&self) -> String {
+        let html = self.get_admin_dashboard_html().await;
+        let marker = r#"name="csrf_token" value=""#;
+        html.find(marker)
+            .map(|start| {
+                let value = &html[start + marker.len()..];
+                value[..value.find('"').unwrap()].to_string()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 在表单数据中附上当前会话的CSRF令牌
+    async fn with_csrf_token<Body>(&self, body: &Body) -> serde_json::Value
+        where
+            Body: serde::Serialize,
+    {
+        let mut body = serde_json::to_value(body).unwrap();
+        body["csrf_token"] = self.csrf_token().await.into();
+        body
+    }
+
     pub async fn get_login(&self) -> Response {
         self.api_client
             .get(&format!("{}/login", &self.address))
@@ -240,7 +398,7 @@ impl TestUser {
         }
     }
 
-    async fn argon2_store(&self, pool: &PgPool) {
+    pub async fn argon2_store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
    
         let password_hash = Argon2::new(
@@ -289,6 +447,14 @@ impl TestUser {
 
 /// 服务器的端口由Os随机分配,初始化应用配置，初始化数据库配置，启动服务
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// 与spawn_app相同，但允许测试在启动前调整配置
+pub async fn spawn_app_with<F>(configure: F) -> TestApp
+    where
+        F: FnOnce(&mut Settings),
+{
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
 
@@ -297,6 +463,7 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        configure(&mut c);
         c
     };
 
@@ -307,15 +474,11 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application.");
 
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    let metrics_port = application.metrics_port();
+    let shutdown = application.shutdown_trigger();
+    let server = tokio::spawn(application.run_until_stopped());
 
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        // 为客户端启用持久化 Cookie 存储
-        // 响应中接收到的 Cookie 将被保存，并包含在后续的附加请求中
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    let client = build_api_client();
 
     let test_app = TestApp {
         address: format!("http://127.0.0.1:{}", application_port),
@@ -324,11 +487,26 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client: client,
+        metrics_port,
+        shutdown,
+        server,
+        hmac_secret: HmacSecret(configuration.application.hmac_secret.clone()),
     };
     test_app.test_user.argon2_store(&test_app.db_pool).await;
     test_app
 }
 
+/// 每个客户端都有独立的Cookie存储，即独立的会话
+pub fn build_api_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        // 为客户端启用持久化 Cookie 存储
+        // 响应中接收到的 Cookie 将被保存，并包含在后续的附加请求中
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
 /// 使用Uuid创建随机的username和password存到users表
 async fn add_test_user(pool: &PgPool) {
     sqlx::query!(
@@ -363,7 +541,7 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
     let connection_pool = PgPool::connect_with(config.with_db())
         .await
         .expect("Failed to connect to postgres.");
-    sqlx::migrate!("./migrations")
+    MIGRATOR
         .run(&connection_pool)
         .await
         .expect("Failed to migrate the database");
@@ -34,4 +34,30 @@ async fn rederict_to_admin_dashboard_after_login_success() {
 
     let  html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}!", app.test_user.username)));
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn an_outdated_password_hash_is_upgraded_after_login() {
+    let app = spawn_app().await;
+    // 测试用户的密码哈希使用的是Argon2d
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    });
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let row = sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the stored password hash.");
+    assert!(row.password_hash.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+
+    // 升级后的哈希值依然可以用原密码登录
+    app.post_logout().await;
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,300 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// 以'topics'订阅并确认，返回订阅者ID
+pub async fn create_confirmed_subscriber_with_topics(
+    app: &TestApp,
+    email: &str,
+    topics: &[&str],
+) -> Uuid {
+    let mut body = format!("name=le%20guin&email={}", urlencoding::encode(email));
+    for topic in topics {
+        body.push_str(&format!("&topics={}", topic));
+    }
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_link = app.get_confirmation_links(&email_request).html;
+    reqwest::get(confirmation_link).await.unwrap().error_for_status().unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn subscriber_lists(app: &TestApp, subscriber_id: Uuid) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug
+        FROM list_memberships m
+        JOIN mailing_lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.slug)
+    .collect()
+}
+
+async fn post_publish_newsletter_to_lists(app: &TestApp, lists: &[&str]) -> reqwest::Response {
+    let csrf_token = app.csrf_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let mut form = vec![
+        ("title", "Newsletter title"),
+        ("text_content", "Newsletter body as plain text"),
+        ("html_content", "<p>Newsletter body as HTML</p>"),
+        ("idempotency_key", idempotency_key.as_str()),
+        ("csrf_token", csrf_token.as_str()),
+    ];
+    form.extend(lists.iter().map(|list| ("lists", *list)));
+    app.api_client
+        .post(&format!("{}/admin/newsletters", &app.address))
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn the_signup_form_offers_every_list_with_the_default_checked() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_list("product-updates", "Product updates").await;
+
+    let html_page = reqwest::get(&format!("{}/subscriptions", &app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains(r#"name="topics" value="newsletter" checked"#));
+    assert!(html_page.contains(r#"name="topics" value="product-updates">"#));
+}
+
+#[tokio::test]
+async fn subscribers_without_topics_join_the_default_list() {
+    let app = spawn_app().await;
+
+    let subscriber_id =
+        create_confirmed_subscriber_with_topics(&app, "ursula_le_guin@gmail.com", &[]).await;
+
+    assert_eq!(subscriber_lists(&app, subscriber_id).await, ["newsletter"]);
+}
+
+#[tokio::test]
+async fn subscribers_can_pick_several_topics() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_list("product-updates", "Product updates").await;
+
+    let subscriber_id = create_confirmed_subscriber_with_topics(
+        &app,
+        "ursula_le_guin@gmail.com",
+        &["product-updates", "newsletter", "product-updates"],
+    )
+    .await;
+
+    assert_eq!(
+        subscriber_lists(&app, subscriber_id).await,
+        ["newsletter", "product-updates"]
+    );
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_topic_is_rejected() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for topic in ["does-not-exist", "Not%20A%20Slug"] {
+        let body = format!("name=le%20guin&email=ursula_le_guin%40gmail.com&topics={}", topic);
+        let response = app.post_subscriptions(body).await;
+        assert_eq!(response.status().as_u16(), 400, "topic: {}", topic);
+    }
+
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(&format!("{}/admin/lists", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn lists_can_be_created_and_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_create_list("product-updates", "Product updates").await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains("The mailing list 'product-updates' has been created."));
+    assert!(html_page.contains("<td>Product updates</td>"));
+
+    let list_id = sqlx::query!("SELECT list_id FROM mailing_lists WHERE slug = 'product-updates'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+    let response = app.post_delete_list(list_id).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains("The mailing list 'product-updates' has been deleted."));
+    assert!(!html_page.contains("<td>Product updates</td>"));
+
+    let actions: Vec<String> = sqlx::query!(
+        "SELECT action FROM audit_log WHERE target = $1 ORDER BY occurred_at",
+        format!("mailing_list/{}", list_id),
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.action)
+    .collect();
+    assert_eq!(actions, ["create_mailing_list", "delete_mailing_list"]);
+}
+
+#[tokio::test]
+async fn invalid_or_duplicate_slugs_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    app.post_create_list("Product Updates", "Product updates").await;
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains("The slug must be 1 to 64 lowercase letters, digits or hyphens."));
+
+    app.post_create_list("newsletter", "Another newsletter").await;
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains("A mailing list with the slug 'newsletter' already exists."));
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM mailing_lists"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn the_default_list_cannot_be_deleted() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let list_id = sqlx::query!("SELECT list_id FROM mailing_lists WHERE is_default")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+
+    app.post_delete_list(list_id).await;
+
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains("The mailing list does not exist or is the default list."));
+    assert!(html_page.contains("<td>newsletter</td>"));
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_the_selected_lists_only() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_list("product-updates", "Product updates").await;
+    create_confirmed_subscriber_with_topics(&app, "newsletter_only@example.com", &["newsletter"])
+        .await;
+    create_confirmed_subscriber_with_topics(&app, "updates_only@example.com", &["product-updates"])
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = post_publish_newsletter_to_lists(&app, &["product-updates"]).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let recipient = sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM issue_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        "#,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .email;
+    assert_eq!(recipient, "updates_only@example.com");
+}
+
+#[tokio::test]
+async fn subscribers_in_several_selected_lists_receive_one_email() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_list("product-updates", "Product updates").await;
+    create_confirmed_subscriber_with_topics(
+        &app,
+        "ursula_le_guin@gmail.com",
+        &["newsletter", "product-updates"],
+    )
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = post_publish_newsletter_to_lists(&app, &["newsletter", "product-updates"]).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_publish_newsletter_to_lists(&app, &["does-not-exist"]).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_none());
+}
@@ -8,4 +8,21 @@ mod subscriptions_confirm;
 mod newsletter;
 mod login;
 mod admin_dashboard;
-mod change_password;
\ No newline at end of file
+mod change_password;
+mod sessions;
+mod csrf;
+mod security_headers;
+mod api_v1;
+mod oidc;
+mod audit;
+mod admin_subscribers;
+mod subscriber_data;
+mod mailing_lists;
+mod preferences;
+mod segments;
+mod tracking;
+mod metrics;
+mod migrations;
+mod cli;
+mod shutdown;
+mod read_replica;
@@ -0,0 +1,90 @@
+use crate::helper::{spawn_app, spawn_app_with, TestApp};
+
+/// base.yaml中配置的令牌
+const METRICS_TOKEN: &str = "my-metrics-token";
+
+async fn get_metrics(app: &TestApp, token: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new().get(&format!("{}/metrics", &app.address));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+/// 取出某个指标样本的值，例如'zero2prod_logins_total{outcome="failure"}'
+fn sample_value(metrics: &str, sample: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(sample)?.trim().parse().ok())
+        .unwrap_or_else(|| panic!("No sample {} in the metrics.", sample))
+}
+
+#[tokio::test]
+async fn metrics_on_the_application_port_require_the_token() {
+    let app = spawn_app().await;
+
+    assert_eq!(get_metrics(&app, None).await.status().as_u16(), 401);
+    assert_eq!(get_metrics(&app, Some("wrong-token")).await.status().as_u16(), 401);
+
+    let response = get_metrics(&app, Some(METRICS_TOKEN)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let metrics = response.text().await.unwrap();
+    for name in [
+        "zero2prod_db_pool_connections",
+        "zero2prod_emails_total",
+        "zero2prod_subscriptions_total",
+        "zero2prod_confirmations_total",
+        "zero2prod_logins_total",
+        "zero2prod_idempotency_hits_total",
+    ] {
+        assert!(metrics.contains(name), "missing {}", name);
+    }
+}
+
+#[tokio::test]
+async fn requests_are_counted_by_route_template() {
+    let app = spawn_app().await;
+    reqwest::get(&format!("{}/health_check", &app.address)).await.unwrap();
+    app.api_client
+        .get(&format!("{}/subscriptions/preferences", &app.address))
+        .query(&[("token", "forged.token")])
+        .send()
+        .await
+        .unwrap();
+
+    let metrics = get_metrics(&app, Some(METRICS_TOKEN)).await.text().await.unwrap();
+
+    assert!(metrics.contains(r#"method="GET",route="/health_check",status="200""#));
+    assert!(metrics.contains(r#"method="GET",route="/subscriptions/preferences",status="401""#));
+    assert!(metrics.contains(r#"zero2prod_http_request_duration_seconds_count{method="GET",route="/health_check"}"#));
+}
+
+#[tokio::test]
+async fn failed_logins_are_counted() {
+    let app = spawn_app().await;
+    let sample = r#"zero2prod_logins_total{outcome="failure"}"#;
+    let metrics = get_metrics(&app, Some(METRICS_TOKEN)).await.text().await.unwrap();
+    let before = sample_value(&metrics, sample);
+
+    app.post_login(&serde_json::json!({
+        "username": "random-username",
+        "password": "random-password",
+    }))
+    .await;
+
+    let metrics = get_metrics(&app, Some(METRICS_TOKEN)).await.text().await.unwrap();
+    assert!(sample_value(&metrics, sample) > before);
+}
+
+#[tokio::test]
+async fn metrics_can_be_served_on_a_separate_port_without_a_token() {
+    let app = spawn_app_with(|c| c.metrics.port = Some(0)).await;
+    let metrics_port = app.metrics_port.expect("No separate metrics port.");
+
+    assert_eq!(get_metrics(&app, Some(METRICS_TOKEN)).await.status().as_u16(), 404);
+    let response = reqwest::get(&format!("http://127.0.0.1:{}/metrics", metrics_port))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("zero2prod_emails_total"));
+}
@@ -0,0 +1,61 @@
+use sqlx::{Connection, Executor, PgConnection};
+use uuid::Uuid;
+use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::migrations::{
+    apply_migrations, pending_migrations, run_startup_migrations, MigrationMode, MIGRATOR,
+};
+use zero2prod::startup::get_connection_pool;
+use crate::helper::spawn_app;
+
+/// 创建一个尚未执行任何迁移的数据库
+async fn empty_database() -> DatabaseSettings {
+    let mut database = get_configuration().unwrap().database;
+    database.database_name = Uuid::new_v4().to_string();
+    let mut connection = PgConnection::connect_with(&database.without_db())
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, database.database_name).as_str())
+        .await
+        .expect("Failed to create database.");
+    database
+}
+
+#[tokio::test]
+async fn apply_runs_every_embedded_migration_on_an_empty_database() {
+    let database = empty_database().await;
+    let pool = get_connection_pool(&database);
+    let all_versions: Vec<i64> = MIGRATOR.iter().map(|m| m.version).collect();
+    assert_eq!(pending_migrations(&pool).await.unwrap(), all_versions);
+
+    let applied = apply_migrations(&pool).await.unwrap();
+
+    assert_eq!(applied, all_versions);
+    assert!(pending_migrations(&pool).await.unwrap().is_empty());
+    run_startup_migrations(&pool, MigrationMode::Verify).await.unwrap();
+}
+
+#[tokio::test]
+async fn verify_refuses_to_start_with_pending_migrations() {
+    let database = empty_database().await;
+    let pool = get_connection_pool(&database);
+
+    let outcome = run_startup_migrations(&pool, MigrationMode::Verify).await;
+
+    assert!(outcome.unwrap_err().to_string().contains("zero2prod migrate"));
+    // 'ignore'不检查，也不执行迁移
+    run_startup_migrations(&pool, MigrationMode::Ignore).await.unwrap();
+    assert_eq!(
+        pending_migrations(&pool).await.unwrap().len(),
+        MIGRATOR.iter().count()
+    );
+}
+
+#[tokio::test]
+async fn apply_is_a_no_op_on_an_up_to_date_database() {
+    let app = spawn_app().await;
+
+    let applied = apply_migrations(&app.db_pool).await.unwrap();
+
+    assert!(applied.is_empty());
+}
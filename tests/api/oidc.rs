@@ -0,0 +1,442 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use wiremock::matchers::{method, path};
+use wiremock::{Match, Mock, MockServer, Request, ResponseTemplate};
+use uuid::Uuid;
+use zero2prod::configuration::{OidcSettings, OidcUserMapping};
+
+const CLIENT_ID: &str = "zero2prod";
+const SIGNING_KEY_ID: &str = "test-key";
+/// 'fixtures/oidc_signing_key.pem'对应的RSA公钥(JWK格式)
+const SIGNING_KEY_MODULUS: &str = "hIzwF_gDUQqjwcOcV7cYaZrRjTGMSgLhHSgHMKTipB526fc-R-F3gU0g-9Crr5ptJ51nQ281hBDT1KJ79wpNfdnZYnkOCtbOnX2nZhEzZkwS2YzvG_MM22y9n0P4xt2DzN4LnVbIBzBX_rxkju91-FMgu5f81dW5kXOBcEwG5l9bUpSFHX0XwYApAdpFf9wVk3ZBkQtwd2qppf9cTTYxQ5nyFp137JP-UEb9Wu-iPwMzyiquEiwiZlhJuLrBoSY-B3ShZt59x_yByTY6aHsvHdz_fUh-2p_v60bLCfX1U4RLkVzCGQy17wEqL6JyeVvvuRUn1GE_mHKdxfJJL0bQCQ";
+
+/// 本地的模拟身份提供方：提供发现文档、JWKS和令牌端点
+struct MockIssuer {
+    server: MockServer,
+}
+
+impl MockIssuer {
+    async fn start() -> Self {
+        let server = MockServer::start().await;
+        let uri = server.uri();
+        Mock::given(method("GET"))
+            .and(path("/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "issuer": uri,
+                "authorization_endpoint": format!("{}/authorize", uri),
+                "token_endpoint": format!("{}/token", uri),
+                "jwks_uri": format!("{}/jwks", uri),
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jwks"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "keys": [{
+                    "kty": "RSA",
+                    "kid": SIGNING_KEY_ID,
+                    "use": "sig",
+                    "alg": "RS256",
+                    "n": SIGNING_KEY_MODULUS,
+                    "e": "AQAB",
+                }]
+            })))
+            .mount(&server)
+            .await;
+        Self { server }
+    }
+
+    fn settings(&self, user_mapping: OidcUserMapping, jit_provisioning: bool) -> OidcSettings {
+        OidcSettings {
+            issuer_url: self.server.uri(),
+            client_id: CLIENT_ID.into(),
+            client_secret: Secret::new("client-secret".into()),
+            display_name: "Corporate SSO".into(),
+            scopes: "openid email profile".into(),
+            user_mapping,
+            jit_provisioning,
+            signing_algorithms: Vec::new(),
+        }
+    }
+
+    fn id_token(&self, sub: &str, email: Option<&str>, email_verified: bool, nonce: &str) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(SIGNING_KEY_ID.into());
+        let key = EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc_signing_key.pem")).unwrap();
+        encode(&header, &self.claims(sub, email, email_verified, nonce), &key).unwrap()
+    }
+
+    /// 用client secret以HS256签名的ID Token
+    fn hs256_id_token(&self, sub: &str, email: Option<&str>, nonce: &str) -> String {
+        let key = EncodingKey::from_secret(b"client-secret");
+        encode(&Header::new(Algorithm::HS256), &self.claims(sub, email, true, nonce), &key).unwrap()
+    }
+
+    fn claims(
+        &self,
+        sub: &str,
+        email: Option<&str>,
+        email_verified: bool,
+        nonce: &str,
+    ) -> serde_json::Value {
+        let now = chrono::Utc::now().timestamp();
+        serde_json::json!({
+            "iss": self.server.uri(),
+            "aud": CLIENT_ID,
+            "sub": sub,
+            "email": email,
+            "email_verified": email_verified,
+            "nonce": nonce,
+            "iat": now,
+            "exp": now + 300,
+        })
+    }
+
+    /// 令牌端点只在收到与code_challenge匹配的code_verifier时才返回ID Token
+    async fn expect_token_exchange(&self, code_challenge: &str, id_token: String) {
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(PkceVerifierMatches(code_challenge.to_string()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "access-token",
+                "token_type": "Bearer",
+                "id_token": id_token,
+            })))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+    }
+}
+
+struct PkceVerifierMatches(String);
+
+impl Match for PkceVerifierMatches {
+    fn matches(&self, request: &Request) -> bool {
+        let form: Vec<(String, String)> = match serde_urlencoded::from_bytes(&request.body) {
+            Ok(form) => form,
+            Err(_) => return false,
+        };
+        form.iter()
+            .find(|(name, _)| name == "code_verifier")
+            .map(|(_, verifier)| {
+                base64::encode_config(Sha256::digest(verifier.as_bytes()), base64::URL_SAFE_NO_PAD)
+                    == self.0
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// 跳转到身份提供方时携带的参数
+struct AuthorizationRequest {
+    state: String,
+    nonce: String,
+    code_challenge: String,
+}
+
+async fn start_sso(app: &TestApp, issuer: &MockIssuer) -> AuthorizationRequest {
+    let response = app
+        .api_client
+        .get(&format!("{}/login/oidc", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 303);
+    let location = reqwest::Url::parse(
+        response.headers()["Location"].to_str().unwrap()
+    )
+    .unwrap();
+    assert!(location.as_str().starts_with(&format!("{}/authorize", issuer.server.uri())));
+
+    let param = |name: &str| {
+        location
+            .query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+            .unwrap()
+    };
+    assert_eq!(param("client_id"), CLIENT_ID);
+    assert_eq!(param("code_challenge_method"), "S256");
+    AuthorizationRequest {
+        state: param("state"),
+        nonce: param("nonce"),
+        code_challenge: param("code_challenge"),
+    }
+}
+
+async fn finish_sso(app: &TestApp, state: &str) -> reqwest::Response {
+    app.api_client
+        .get(&format!("{}/login/oidc/callback", &app.address))
+        .query(&[("code", "authorization-code"), ("state", state)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn set_test_user_email(app: &TestApp, email: &str) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        email,
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_login_form_offers_sso_only_when_it_is_configured() {
+    let app = spawn_app().await;
+    assert!(!app.get_login_html().await.contains("/login/oidc"));
+
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Email, false);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(r#"<a href="/login/oidc">Log in with Corporate SSO</a>"#));
+}
+
+#[tokio::test]
+async fn sso_is_not_available_when_it_is_not_configured() {
+    let app = spawn_app().await;
+
+    let response = app.api_client
+        .get(&format!("{}/login/oidc", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_user_is_logged_in_by_verified_email() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Email, false);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+    set_test_user_email(&app, "admin@example.com").await;
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.id_token("subject-1", Some("Admin@Example.com"), true, &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn an_unverified_email_is_not_used_to_find_a_user() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Email, false);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+    set_test_user_email(&app, "admin@example.com").await;
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.id_token("subject-1", Some("admin@example.com"), false, &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>No account is associated with this identity.</i></p>"));
+}
+
+#[tokio::test]
+async fn a_user_is_logged_in_by_subject() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Subject, false);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+    sqlx::query!(
+        "UPDATE users SET oidc_issuer = $1, oidc_subject = 'subject-1' WHERE user_id = $2",
+        issuer.server.uri(),
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.id_token("subject-1", None, false, &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn an_unknown_identity_is_rejected_without_provisioning() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Email, false);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.id_token("subject-2", Some("someone@example.com"), true, &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_unknown_identity_is_provisioned_just_in_time() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Email, true);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.id_token("subject-2", Some("someone@example.com"), true, &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("Welcome someone@example.com"));
+
+    let user = sqlx::query!(
+        "SELECT email, oidc_subject FROM users WHERE username = 'someone@example.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(user.email.as_deref(), Some("someone@example.com"));
+    assert_eq!(user.oidc_subject.as_deref(), Some("subject-2"));
+}
+
+#[tokio::test]
+async fn a_taken_username_gets_a_suffix_when_provisioning() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Subject, true);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+    // 已有用户以该邮箱为用户名，但没有设置邮箱
+    sqlx::query!(
+        "UPDATE users SET username = 'someone@example.com' WHERE user_id = $1",
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.id_token("subject-2", Some("someone@example.com"), true, &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let user = sqlx::query!("SELECT user_id, username FROM users WHERE oidc_subject = 'subject-2'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(user.user_id, app.test_user.user_id);
+    assert!(user.username.starts_with("someone@example.com-"));
+}
+
+#[tokio::test]
+async fn an_identity_whose_email_belongs_to_another_user_is_not_provisioned() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Subject, true);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+    set_test_user_email(&app, "someone@example.com").await;
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.id_token("subject-2", Some("someone@example.com"), true, &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>No account is associated with this identity.</i></p>"));
+    let provisioned: Option<Uuid> =
+        sqlx::query_scalar("SELECT user_id FROM users WHERE oidc_subject = 'subject-2'")
+            .fetch_optional(&app.db_pool)
+            .await
+            .unwrap();
+    assert!(provisioned.is_none());
+}
+
+#[tokio::test]
+async fn an_id_token_signed_with_the_client_secret_is_rejected() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Email, true);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.hs256_id_token("subject-2", Some("someone@example.com"), &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Single sign-on failed.</i></p>"));
+}
+
+#[tokio::test]
+async fn hs256_id_tokens_are_accepted_when_explicitly_configured() {
+    let issuer = MockIssuer::start().await;
+    let mut settings = issuer.settings(OidcUserMapping::Email, true);
+    settings.signing_algorithms = vec![Algorithm::HS256];
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.hs256_id_token("subject-2", Some("someone@example.com"), &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_mismatched_state_is_rejected() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Email, true);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+
+    let _request = start_sso(&app, &issuer).await;
+    Mock::given(path("/token"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&issuer.server)
+        .await;
+
+    let response = finish_sso(&app, "forged-state").await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Single sign-on failed.</i></p>"));
+}
+
+#[tokio::test]
+async fn an_id_token_with_another_nonce_is_rejected() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Email, true);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.id_token("subject-2", Some("someone@example.com"), true, "another-nonce");
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_login_state_cannot_be_replayed() {
+    let issuer = MockIssuer::start().await;
+    let settings = issuer.settings(OidcUserMapping::Email, true);
+    let app = spawn_app_with(|c| c.oidc = Some(settings)).await;
+
+    let request = start_sso(&app, &issuer).await;
+    let id_token = issuer.id_token("subject-2", Some("someone@example.com"), true, &request.nonce);
+    issuer.expect_token_exchange(&request.code_challenge, id_token).await;
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // 同一个state第二次回调时已经不在会话中
+    let response = finish_sso(&app, &request.state).await;
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,420 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::subscriber_data::email_tombstone_hash;
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+/// 发布一期简报，返回发给订阅者的邮件请求体
+async fn publish_and_capture_email(app: &TestApp) -> serde_json::Value {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_body()).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    serde_json::from_slice(&email_request.body).unwrap()
+}
+
+/// 从简报中取出偏好设置链接
+fn preferences_link(app: &TestApp, body: &str) -> reqwest::Url {
+    let raw_link = linkify::LinkFinder::new()
+        .links(body)
+        .map(|l| l.as_str().to_owned())
+        .find(|l| l.contains("/subscriptions/preferences"))
+        .expect("No preferences link in the newsletter.");
+    let mut link = reqwest::Url::parse(&raw_link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+/// 创建一个已确认的订阅者，返回其偏好设置链接中的令牌
+async fn subscribe_and_get_token(app: &TestApp) -> String {
+    create_confirmed_subscriber(app).await;
+    let email = publish_and_capture_email(app).await;
+    let link = preferences_link(app, email["TextBody"].as_str().unwrap());
+    link.query_pairs()
+        .find(|(k, _)| k == "token")
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+async fn get_preferences_html(app: &TestApp, token: &str) -> String {
+    app.api_client
+        .get(&format!("{}/subscriptions/preferences", &app.address))
+        .query(&[("token", token)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn post_preferences(app: &TestApp, form: &[(&str, &str)]) -> reqwest::Response {
+    app.api_client
+        .post(&format!("{}/subscriptions/preferences", &app.address))
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn post_change_email(app: &TestApp, token: &str, email: &str) -> reqwest::Response {
+    app.api_client
+        .post(&format!("{}/subscriptions/preferences/email", &app.address))
+        .form(&[("token", token), ("email", email)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn every_newsletter_links_to_the_preferences_page() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let email = publish_and_capture_email(&app).await;
+
+    let html_link = preferences_link(&app, email["HtmlBody"].as_str().unwrap());
+    let text_link = preferences_link(&app, email["TextBody"].as_str().unwrap());
+    assert_eq!(html_link, text_link);
+    let response = reqwest::get(html_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_current_settings() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+
+    let html_page = get_preferences_html(&app, &token).await;
+
+    assert!(html_page.contains(r#"name="name" value="le guin""#));
+    assert!(html_page.contains(r#"name="topics" value="newsletter" checked"#));
+    assert!(html_page.contains(r#"name="email_format" value="html" checked"#));
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+}
+
+#[tokio::test]
+async fn an_invalid_preferences_link_is_rejected() {
+    let app = spawn_app().await;
+
+    let html_page = app
+        .api_client
+        .get(&format!("{}/subscriptions/preferences?token=forged.token", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(html_page.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_and_switch_to_plain_text() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+
+    let response = post_preferences(
+        &app,
+        &[
+            ("token", &token),
+            ("name", "Ursula K. Le Guin"),
+            ("email_format", "text"),
+            ("topics", "newsletter"),
+            ("pause_days", ""),
+        ],
+    )
+    .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/subscriptions/preferences?token={}", urlencoding::encode(&token)),
+    );
+    let html_page = get_preferences_html(&app, &token).await;
+    assert!(html_page.contains("Your preferences have been saved."));
+
+    let saved = sqlx::query!("SELECT name, email_format FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.email_format, "text");
+
+    let email = publish_and_capture_email(&app).await;
+    assert!(email.get("HtmlBody").is_none());
+    assert!(email["TextBody"].as_str().unwrap().contains("Newsletter body as plain text"));
+}
+
+#[tokio::test]
+async fn paused_subscribers_receive_no_newsletters() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+
+    post_preferences(
+        &app,
+        &[
+            ("token", &token),
+            ("name", "le guin"),
+            ("email_format", "html"),
+            ("topics", "newsletter"),
+            ("pause_days", "30"),
+        ],
+    )
+    .await;
+    let html_page = get_preferences_html(&app, &token).await;
+    assert!(html_page.contains(r#"<option value="keep" selected>Stay paused until"#));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletters(newsletter_body()).await;
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn subscribers_can_switch_topics_but_must_keep_one() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+    app.test_user.login(&app).await;
+    app.post_create_list("product-updates", "Product updates").await;
+
+    post_preferences(
+        &app,
+        &[
+            ("token", &token),
+            ("name", "le guin"),
+            ("email_format", "html"),
+            ("pause_days", ""),
+        ],
+    )
+    .await;
+    let html_page = get_preferences_html(&app, &token).await;
+    assert!(html_page.contains("Select at least one topic, or unsubscribe instead."));
+
+    post_preferences(
+        &app,
+        &[
+            ("token", &token),
+            ("name", "le guin"),
+            ("email_format", "html"),
+            ("topics", "product-updates"),
+            ("pause_days", ""),
+        ],
+    )
+    .await;
+    let lists: Vec<String> = sqlx::query!(
+        r#"
+        SELECT l.slug
+        FROM list_memberships m
+        JOIN mailing_lists l ON l.list_id = m.list_id
+        "#,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.slug)
+    .collect();
+    assert_eq!(lists, ["product-updates"]);
+}
+
+#[tokio::test]
+async fn unsubscribing_stops_delivery_and_invalidates_old_confirmation_links() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+    let confirmation_token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+
+    let response = app
+        .api_client
+        .post(&format!("{}/subscriptions/preferences/unsubscribe", &app.address))
+        .form(&[("token", &token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("You have been unsubscribed."));
+    let html_page = get_preferences_html(&app, &token).await;
+    assert!(html_page.contains("You have unsubscribed from our newsletter."));
+
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        &app.address, confirmation_token
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_body()).await.error_for_status().unwrap();
+}
+
+#[tokio::test]
+async fn changing_the_email_requires_confirmation_from_the_new_address() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = post_change_email(&app, &token, "ursula@example.com").await;
+    assert_eq!(response.status().as_u16(), 303);
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula@example.com");
+
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email, "ursula_le_guin@gmail.com");
+
+    let confirmation_link = app.get_confirmation_links(&email_request).html;
+    let response = reqwest::get(confirmation_link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@example.com");
+    assert_eq!(saved.status, "confirmed");
+
+    // 修改邮箱的确认链接只能使用一次
+    let response = reqwest::get(confirmation_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_email_cannot_be_changed_to_an_address_already_subscribed() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    post_change_email(&app, &token, "Ursula_Le_Guin@gmail.com").await;
+
+    // 响应与成功时相同，不透露该邮箱已经订阅
+    let html_page = get_preferences_html(&app, &token).await;
+    assert!(html_page.contains("We have sent a confirmation link to Ursula_Le_Guin@gmail.com."));
+}
+
+#[tokio::test]
+async fn the_email_cannot_be_changed_to_an_erased_address() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+    sqlx::query!(
+        "INSERT INTO erased_subscribers (email_hash, erased_at, keyed) VALUES ($1, now(), true)",
+        email_tombstone_hash(&app.hmac_secret, "ursula@example.com"),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    post_change_email(&app, &token, "ursula@example.com").await;
+
+    let html_page = get_preferences_html(&app, &token).await;
+    assert!(html_page.contains("We have sent a confirmation link to ursula@example.com."));
+    let tokens = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens WHERE new_email IS NOT NULL"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(tokens, 0);
+}
+
+/// 申请修改邮箱，返回发到新邮箱的确认链接
+async fn request_email_change(app: &TestApp, token: &str, email: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = post_change_email(app, token, email).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+#[tokio::test]
+async fn an_email_change_is_rejected_if_the_address_was_subscribed_in_the_meantime() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+    let confirmation_link = request_email_change(&app, &token, "ursula@example.com").await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'Ursula@Example.com', 'someone else', now(), 'confirmed')
+        "#,
+        uuid::Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    let email = sqlx::query!("SELECT email FROM subscriptions WHERE name = 'le guin'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn an_email_change_is_rejected_if_the_address_was_erased_in_the_meantime() {
+    let app = spawn_app().await;
+    let token = subscribe_and_get_token(&app).await;
+    let confirmation_link = request_email_change(&app, &token, "ursula@example.com").await;
+    sqlx::query!(
+        "INSERT INTO erased_subscribers (email_hash, erased_at, keyed) VALUES ($1, now(), true)",
+        email_tombstone_hash(&app.hmac_secret, "ursula@example.com"),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert_eq!(email, "ursula_le_guin@gmail.com");
+}
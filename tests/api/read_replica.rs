@@ -0,0 +1,80 @@
+use sqlx::PgPool;
+use zero2prod::configuration::{get_configuration, DatabaseSettings, ReplicaSettings};
+use zero2prod::database::DatabasePools;
+use zero2prod::startup::{get_connection_pool, get_replica_pool};
+use crate::helper::{spawn_app, spawn_app_with, TestApp};
+use crate::newsletter::create_unconfirmed_subscriber;
+
+/// 测试应用的数据库配置，只读副本指向'replica_port'
+/// - 副本与主库使用同一个数据库
+async fn database_settings(app: &TestApp, replica_port: u16) -> DatabaseSettings {
+    let mut settings = get_configuration().unwrap().database;
+    settings.database_name = sqlx::query_scalar("SELECT current_database()")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    settings.replica = Some(ReplicaSettings {
+        host: settings.host.clone(),
+        port: replica_port,
+    });
+    settings
+}
+
+fn pools(settings: &DatabaseSettings) -> DatabasePools {
+    DatabasePools::new(get_connection_pool(settings), get_replica_pool(settings))
+}
+
+fn is_primary(pools: &DatabasePools, pool: &PgPool) -> bool {
+    std::ptr::eq(pools.primary(), pool)
+}
+
+#[tokio::test]
+async fn reads_go_to_the_replica() {
+    let app = spawn_app().await;
+    let settings = database_settings(&app, get_configuration().unwrap().database.port).await;
+    let pools = pools(&settings);
+
+    let pool = pools.read().await;
+
+    assert!(!is_primary(&pools, pool));
+    sqlx::query("SELECT 1").execute(pool).await.unwrap();
+}
+
+#[tokio::test]
+async fn reads_fall_back_to_the_primary_if_the_replica_is_unavailable() {
+    let app = spawn_app().await;
+    // 该端口上没有数据库
+    let settings = database_settings(&app, 1).await;
+    let pools = pools(&settings);
+
+    assert!(is_primary(&pools, pools.read().await));
+    // 在重试间隔内不再尝试副本
+    assert!(is_primary(&pools, pools.read().await));
+}
+
+#[tokio::test]
+async fn reads_use_the_primary_without_a_replica() {
+    let app = spawn_app().await;
+    let mut settings = database_settings(&app, 1).await;
+    settings.replica = None;
+    let pools = pools(&settings);
+
+    assert!(is_primary(&pools, pools.read().await));
+}
+
+#[tokio::test]
+async fn the_subscribers_page_works_while_the_replica_is_down() {
+    let app = spawn_app_with(|c| {
+        c.database.replica = Some(ReplicaSettings {
+            host: c.database.host.clone(),
+            port: 1,
+        })
+    })
+    .await;
+    create_unconfirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_subscribers("").await.text().await.unwrap();
+
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+}
@@ -0,0 +1,100 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use crate::mailing_lists::create_confirmed_subscriber_with_topics;
+use zero2prod::configuration::Environment;
+
+fn csp_nonce(response: &reqwest::Response) -> String {
+    let policy = response.headers()["Content-Security-Policy"].to_str().unwrap();
+    let start = policy.find("'nonce-").unwrap() + "'nonce-".len();
+    let end = start + policy[start..].find('\'').unwrap();
+    policy[start..end].to_string()
+}
+
+#[tokio::test]
+async fn security_headers_are_set_on_html_pages() {
+    let app = spawn_app().await;
+
+    let response = app.get_login().await;
+
+    let headers = response.headers();
+    assert!(headers.contains_key("Content-Security-Policy"));
+    assert_eq!(headers["X-Frame-Options"], "DENY");
+    assert_eq!(headers["Referrer-Policy"], "strict-origin-when-cross-origin");
+    assert_eq!(headers["X-Content-Type-Options"], "nosniff");
+}
+
+#[tokio::test]
+async fn security_headers_are_set_on_redirects_from_the_admin_area() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(response.headers()["X-Frame-Options"], "DENY");
+}
+
+#[tokio::test]
+async fn each_request_gets_a_fresh_csp_nonce() {
+    let app = spawn_app().await;
+
+    let nonce1 = csp_nonce(&app.get_login().await);
+    let nonce2 = csp_nonce(&app.get_login().await);
+
+    assert!(!nonce1.is_empty());
+    assert_ne!(nonce1, nonce2);
+}
+
+#[tokio::test]
+async fn inline_styles_carry_the_csp_nonce() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id =
+        create_confirmed_subscriber_with_topics(&app, "ursula_le_guin@gmail.com", &[]).await;
+
+    let response = app
+        .api_client
+        .get(&format!("{}/admin/subscribers/{}", &app.address, subscriber_id))
+        .send()
+        .await
+        .unwrap();
+
+    let nonce = csp_nonce(&response);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(r#"<style nonce="{}">"#, nonce)));
+    assert!(!html_page.contains("style=\""));
+}
+
+#[tokio::test]
+async fn hsts_is_not_sent_outside_of_production_without_ssl() {
+    let app = spawn_app().await;
+
+    let response = app.get_login().await;
+
+    assert!(!response.headers().contains_key("Strict-Transport-Security"));
+}
+
+#[tokio::test]
+async fn hsts_is_sent_in_production() {
+    let app = spawn_app_with(|c| {
+        c.environment = Environment::Production;
+        // 本地配置中的'/metrics'令牌不能用于production
+        c.metrics.bearer_token = None;
+    })
+    .await;
+
+    let response = app.get_login().await;
+
+    assert_eq!(
+        response.headers()["Strict-Transport-Security"],
+        "max-age=31536000; includeSubDomains"
+    );
+}
+
+#[tokio::test]
+async fn a_security_header_can_be_disabled() {
+    let app = spawn_app_with(|c| c.security_headers.frame_options = None).await;
+
+    let response = app.get_login().await;
+
+    assert!(!response.headers().contains_key("X-Frame-Options"));
+    assert!(response.headers().contains_key("Content-Security-Policy"));
+}
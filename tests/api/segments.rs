@@ -0,0 +1,254 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::mailing_lists::create_confirmed_subscriber_with_topics;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn post_api_tags(
+    app: &TestApp,
+    api_key: &str,
+    subscriber_id: Uuid,
+    body: &serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(&format!("{}/api/v1/subscribers/{}/tags", &app.address, subscriber_id))
+        .bearer_auth(api_key)
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// 创建一个已确认的订阅者并通过API打上'tags'
+async fn create_tagged_subscriber(app: &TestApp, api_key: &str, email: &str, tags: &[&str]) {
+    let subscriber_id = create_confirmed_subscriber_with_topics(app, email, &[]).await;
+    post_api_tags(app, api_key, subscriber_id, &serde_json::json!({ "add": tags }))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn post_publish_newsletter_to_segment(app: &TestApp, segment: &str) -> reqwest::Response {
+    let csrf_token = app.csrf_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    app.api_client
+        .post(&format!("{}/admin/newsletters", &app.address))
+        .form(&[
+            ("title", "Newsletter title"),
+            ("text_content", "Newsletter body as plain text"),
+            ("html_content", "<p>Newsletter body as HTML</p>"),
+            ("segment", segment),
+            ("idempotency_key", idempotency_key.as_str()),
+            ("csrf_token", csrf_token.as_str()),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_publish_newsletter_html_for_segment(app: &TestApp, segment: &str) -> String {
+    app.api_client
+        .get(&format!("{}/admin/newsletters", &app.address))
+        .query(&[("segment", segment)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn tags_can_be_added_and_removed_via_the_api() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:read", "subscribers:write"]).await;
+    let subscriber_id =
+        create_confirmed_subscriber_with_topics(&app, "ursula_le_guin@gmail.com", &[]).await;
+
+    let response = post_api_tags(
+        &app,
+        &api_key,
+        subscriber_id,
+        &serde_json::json!({ "add": ["churned", "beta", "vip"] }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["tags"], serde_json::json!(["beta", "churned", "vip"]));
+
+    let response = post_api_tags(
+        &app,
+        &api_key,
+        subscriber_id,
+        &serde_json::json!({ "remove": ["churned"] }),
+    )
+    .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["tags"], serde_json::json!(["beta", "vip"]));
+
+    let body: serde_json::Value = reqwest::Client::new()
+        .get(&format!("{}/api/v1/subscribers", &app.address))
+        .bearer_auth(&api_key)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["subscribers"][0]["tags"], serde_json::json!(["beta", "vip"]));
+}
+
+#[tokio::test]
+async fn tagging_via_the_api_requires_the_write_scope() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:read"]).await;
+    let subscriber_id =
+        create_confirmed_subscriber_with_topics(&app, "ursula_le_guin@gmail.com", &[]).await;
+
+    let response =
+        post_api_tags(&app, &api_key, subscriber_id, &serde_json::json!({ "add": ["beta"] })).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn invalid_tags_or_unknown_subscribers_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+    let subscriber_id =
+        create_confirmed_subscriber_with_topics(&app, "ursula_le_guin@gmail.com", &[]).await;
+
+    for tag in ["Beta", "not", "two words", ""] {
+        let response =
+            post_api_tags(&app, &api_key, subscriber_id, &serde_json::json!({ "add": [tag] })).await;
+        assert_eq!(response.status().as_u16(), 400, "tag: {:?}", tag);
+    }
+
+    let response =
+        post_api_tags(&app, &api_key, Uuid::new_v4(), &serde_json::json!({ "add": ["beta"] })).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn admins_can_tag_subscribers_on_the_detail_page() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let subscriber_id =
+        create_confirmed_subscriber_with_topics(&app, "ursula_le_guin@gmail.com", &[]).await;
+    let detail_path = format!("/admin/subscribers/{}", subscriber_id);
+
+    let csrf_token = app.csrf_token().await;
+    let response = app
+        .api_client
+        .post(&format!("{}{}/tags", &app.address, detail_path))
+        .form(&[("tags", "beta, churned"), ("csrf_token", csrf_token.as_str())])
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, &detail_path);
+
+    let response = app
+        .api_client
+        .post(&format!("{}{}/tags/remove", &app.address, detail_path))
+        .form(&[("tag", "churned"), ("csrf_token", csrf_token.as_str())])
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, &detail_path);
+
+    let html_page = app
+        .api_client
+        .get(&format!("{}{}", &app.address, detail_path))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The tag has been removed."));
+    assert!(html_page.contains(r#"name="tag" value="beta""#));
+    assert!(!html_page.contains(r#"name="tag" value="churned""#));
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_the_matching_segment_only() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+    create_tagged_subscriber(&app, &api_key, "beta@example.com", &["beta"]).await;
+    create_tagged_subscriber(&app, &api_key, "churned@example.com", &["beta", "churned"]).await;
+    create_tagged_subscriber(&app, &api_key, "untagged@example.com", &[]).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = post_publish_newsletter_to_segment(&app, "beta AND NOT churned").await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let recipient = sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM issue_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        "#,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .email;
+    assert_eq!(recipient, "beta@example.com");
+}
+
+#[tokio::test]
+async fn the_publish_form_shows_the_recipient_count_of_the_segment() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+    create_tagged_subscriber(&app, &api_key, "beta@example.com", &["beta"]).await;
+    create_tagged_subscriber(&app, &api_key, "churned@example.com", &["beta", "churned"]).await;
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("This issue will be sent to 2 confirmed subscriber(s)."));
+
+    let html_page = get_publish_newsletter_html_for_segment(&app, "beta and not churned").await;
+    assert!(html_page.contains("This issue will be sent to 1 confirmed subscriber(s)."));
+    assert!(html_page.contains(r#"name="segment" value="beta and not churned""#));
+
+    let html_page = get_publish_newsletter_html_for_segment(&app, "beta AND").await;
+    assert!(html_page.contains("The segment ends unexpectedly."));
+}
+
+#[tokio::test]
+async fn publishing_to_an_invalid_segment_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["newsletters:publish"]).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_publish_newsletter_to_segment(&app, "beta OR").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = reqwest::Client::new()
+        .post(&format!("{}/api/v1/newsletters", &app.address))
+        .bearer_auth(&api_key)
+        .json(&serde_json::json!({
+            "title": "Release notes",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "segment": "(beta",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
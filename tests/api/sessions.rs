@@ -0,0 +1,105 @@
+use crate::helper::{assert_is_redirect_to, build_api_client, spawn_app, TestApp, TestUser};
+
+/// 使用一个独立的客户端(独立的Cookie存储)登录指定用户
+async fn login_with_another_client(app: &TestApp, user: &TestUser) -> reqwest::Client {
+    let client = build_api_client();
+    let response = client
+        .post(&format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &user.username,
+            "password": &user.password,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard_with(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(&format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    let app = spawn_app().await;
+    let response = app.get_sessions().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logged_in_sessions_are_listed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let _other_client = login_with_another_client(&app, &app.test_user).await;
+
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("<i>This session</i>"));
+    assert!(html_page.contains(r#"action="/admin/sessions/revoke""#));
+}
+
+#[tokio::test]
+async fn revoking_other_sessions_logs_them_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_client = login_with_another_client(&app, &app.test_user).await;
+
+    let response = app.post_revoke_other_sessions().await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("<p><i>1 other session(s) have been revoked.</i></p>"));
+
+    // 当前会话不受影响
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    // 被撤销的会话需要重新登录
+    let response = get_dashboard_with(&app, &other_client).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_an_owner_can_revoke_the_sessions_of_another_user() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let seed_user_id = sqlx::query!("SELECT user_id FROM users WHERE username = 'admin'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .user_id;
+
+    let response = app.post_revoke_user_sessions(seed_user_id).await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn an_owner_can_revoke_the_sessions_of_another_user() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        "UPDATE users SET role = 'owner' WHERE user_id = $1",
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let other_user = TestUser::generate();
+    other_user.argon2_store(&app.db_pool).await;
+    let other_user_client = login_with_another_client(&app, &other_user).await;
+
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains(&format!("Revoke all sessions of {}", other_user.username)));
+
+    let response = app.post_revoke_user_sessions(other_user.user_id).await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    let response = get_dashboard_with(&app, &other_user_client).await;
+    assert_is_redirect_to(&response, "/login");
+    // owner自己的会话不受影响
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
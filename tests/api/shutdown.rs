@@ -0,0 +1,139 @@
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use crate::helper::{spawn_app_with, TestApp};
+
+/// 通过订阅和确认链接创建'count'个已确认的订阅者
+async fn create_confirmed_subscribers(app: &TestApp, count: usize) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    for i in 0..count {
+        let body = format!("name=reader%20{}&email=reader{}%40example.com", i, i);
+        app.post_subscriptions(body).await.error_for_status().unwrap();
+        let email_request = app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let confirmation_link = app.get_confirmation_links(&email_request).html;
+        reqwest::get(confirmation_link)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+}
+
+/// 在后台发布一期简报，返回请求的结果
+fn publish_in_background(
+    app: &TestApp,
+    idempotency_key: &str,
+) -> tokio::task::JoinHandle<reqwest::Result<reqwest::Response>> {
+    let request = app
+        .api_client
+        .post(&format!("{}/newsletters", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Idempotency-Key", idempotency_key)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }));
+    tokio::spawn(request.send())
+}
+
+async fn delivery_statuses(pool: &PgPool) -> Vec<(String, Option<String>)> {
+    sqlx::query_as("SELECT status, error_message FROM issue_deliveries")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+async fn saved_response_exists(pool: &PgPool, idempotency_key: &str) -> bool {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM idempotency WHERE idempotency_key = $1)")
+        .bind(idempotency_key)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_in_flight_deliveries() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.shutdown_grace_period_seconds = 10).await;
+    create_confirmed_subscribers(&app, 1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act
+    let publishing = publish_in_background(&app, &idempotency_key);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    app.shutdown.cancel();
+    let response = publishing.await.unwrap().unwrap();
+    app.server.await.unwrap().unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(delivery_statuses(&app.db_pool).await, vec![("sent".to_string(), None)]);
+    assert!(saved_response_exists(&app.db_pool, &idempotency_key).await);
+}
+
+#[tokio::test]
+async fn deliveries_are_interrupted_when_the_grace_period_is_over() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.shutdown_grace_period_seconds = 1;
+        c.email_client.timeout_milliseconds = 10_000;
+    })
+    .await;
+    create_confirmed_subscribers(&app, 3).await;
+    // 第一封邮件在宽限期结束之后、服务器的工作线程被销毁之后才发送完成
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(1500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act
+    let _publishing = publish_in_background(&app, &idempotency_key);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let shutdown_started = std::time::Instant::now();
+    app.shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(10), app.server)
+        .await
+        .expect("The shutdown did not complete.")
+        .unwrap()
+        .unwrap();
+
+    // Assert
+    // 宽限期结束时中断投递，不会等到所有邮件发送完成
+    assert!(shutdown_started.elapsed() < Duration::from_millis(2500));
+    // 正在发送的邮件完成后记为已发送，其余订阅者记为失败
+    let mut statuses = delivery_statuses(&app.db_pool).await;
+    statuses.sort();
+    let interrupted = Some("The delivery was interrupted by a shutdown.".to_string());
+    assert_eq!(
+        statuses,
+        vec![
+            ("failed".to_string(), interrupted.clone()),
+            ("failed".to_string(), interrupted),
+            ("sent".to_string(), None),
+        ]
+    );
+    // 投递任务在工作线程被销毁后仍继续执行，因此重试时仍会得到保存的响应
+    assert!(saved_response_exists(&app.db_pool, &idempotency_key).await);
+}
@@ -0,0 +1,286 @@
+use crate::helper::{spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use sha2::{Digest, Sha256};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::subscriber_data::{email_tombstone_hash, upgrade_legacy_tombstones};
+
+async fn post_data_request(app: &TestApp, email: &str, request_type: &str) -> reqwest::Response {
+    app.api_client
+        .post(&format!("{}/subscriptions/data", &app.address))
+        .form(&[("email", email), ("request_type", request_type)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// 申请数据请求链接，返回邮件中的链接
+async fn request_link(app: &TestApp, request_type: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = post_data_request(app, "ursula_le_guin@gmail.com", request_type).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+#[tokio::test]
+async fn the_data_request_form_is_available() {
+    let app = spawn_app().await;
+
+    let response = app.api_client
+        .get(&format!("{}/subscriptions/data", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions/data" method="post">"#));
+}
+
+#[tokio::test]
+async fn no_link_is_sent_to_an_unknown_email() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_data_request(&app, "nobody@example.com", "export").await;
+
+    // 与已订阅的邮箱返回相同的页面
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("If nobody@example.com is subscribed"));
+}
+
+#[tokio::test]
+async fn a_failed_link_email_gets_the_same_response() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_data_request(&app, "ursula_le_guin@gmail.com", "export").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("If ursula_le_guin@gmail.com is subscribed"));
+}
+
+#[tokio::test]
+async fn an_unknown_request_type_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = post_data_request(&app, "ursula_le_guin@gmail.com", "sell").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_emailed_link_exports_everything_stored_for_the_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let link = request_link(&app, "export").await;
+    assert_eq!(link.path(), "/subscriptions/data/export");
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .contains("subscriber-data.json"));
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscription"]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(export["subscription"]["status"], "confirmed");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert!(export["deliveries"].as_array().unwrap().is_empty());
+    assert_eq!(export["consents"][0]["source"], "signup_form");
+}
+
+#[tokio::test]
+async fn a_tampered_link_is_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let mut link = request_link(&app, "export").await;
+    let token = link.query_pairs().next().unwrap().1.into_owned();
+    let (_, signature) = token.split_once('.').unwrap();
+    let forged_payload = base64::encode_config(
+        format!("{}:someone_else@gmail.com", chrono::Utc::now().timestamp() + 60),
+        base64::URL_SAFE_NO_PAD,
+    );
+    link.query_pairs_mut()
+        .clear()
+        .append_pair("token", &format!("{}.{}", forged_payload, signature));
+
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_export_link_cannot_be_used_to_erase() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let mut link = request_link(&app, "export").await;
+    link.set_path("/subscriptions/data/erase");
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn opening_the_erasure_link_does_not_delete_anything() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let link = request_link(&app, "erase").await;
+    let html_page = reqwest::get(link).await.unwrap().text().await.unwrap();
+
+    assert!(html_page.contains(r#"<form action="/subscriptions/data/erase" method="post">"#));
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn erasure_removes_the_subscriber_and_keeps_a_tombstone() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let link = request_link(&app, "erase").await;
+    let token = link.query_pairs().next().unwrap().1.into_owned();
+    let response = app.api_client
+        .post(&format!("{}/subscriptions/data/erase", &app.address))
+        .form(&[("token", token.as_str())])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let remaining = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM subscriptions) AS "subscriptions!",
+            (SELECT COUNT(*) FROM subscription_tokens) AS "tokens!",
+            (SELECT COUNT(*) FROM subscription_consents) AS "consents!"
+        "#,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining.subscriptions, 0);
+    assert_eq!(remaining.tokens, 0);
+    assert_eq!(remaining.consents, 0);
+
+    let tombstone = sqlx::query!("SELECT email_hash FROM erased_subscribers")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        tombstone.email_hash,
+        email_tombstone_hash(&app.hmac_secret, "ursula_le_guin@gmail.com")
+    );
+    assert_ne!(
+        tombstone.email_hash,
+        hex::encode(Sha256::digest(b"ursula_le_guin@gmail.com"))
+    );
+}
+
+#[tokio::test]
+async fn an_export_after_erasure_finds_nothing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let export_link = request_link(&app, "export").await;
+    let erase_link = request_link(&app, "erase").await;
+
+    let token = erase_link.query_pairs().next().unwrap().1.into_owned();
+    app.api_client
+        .post(&format!("{}/subscriptions/data/erase", &app.address))
+        .form(&[("token", token.as_str())])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = reqwest::get(export_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn an_erased_email_cannot_subscribe_again() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        "INSERT INTO erased_subscribers (email_hash, erased_at, keyed) VALUES ($1, now(), true)",
+        email_tombstone_hash(&app.hmac_secret, "ursula_le_guin@gmail.com"),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com".into())
+        .await;
+
+    // 与正常注册返回相同的响应，但不保存邮箱也不发送确认邮件
+    assert_eq!(response.status().as_u16(), 200);
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn legacy_tombstones_are_replaced_with_keyed_hashes() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        "INSERT INTO erased_subscribers (email_hash, erased_at, keyed) VALUES ($1, now(), false)",
+        hex::encode(Sha256::digest(b"ursula_le_guin@gmail.com")),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let upgraded = upgrade_legacy_tombstones(&app.db_pool, &app.hmac_secret).await.unwrap();
+
+    assert_eq!(upgraded, 1);
+    let tombstone = sqlx::query!("SELECT email_hash, keyed FROM erased_subscribers")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        tombstone.email_hash,
+        email_tombstone_hash(&app.hmac_secret, "ursula_le_guin@gmail.com")
+    );
+    assert!(tombstone.keyed);
+    assert_eq!(upgrade_legacy_tombstones(&app.db_pool, &app.hmac_secret).await.unwrap(), 0);
+}
@@ -1,7 +1,7 @@
 use wiremock::{Mock, ResponseTemplate};
 use wiremock::matchers::{path, method};
 
-use crate::helper::spawn_app;
+use crate::helper::{spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -142,4 +142,79 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error_in_subscriptions_tab
 
     let response = app.post_subscriptions(body.into()).await;
     assert_eq!(response.status().as_u16(), 500)
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn subscribe_records_the_consent_evidence() {
+    let app = spawn_app_with(|c| c.consent.text_version = "v7".into()).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.api_client
+        .post(&format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "consent-test/1.0")
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("source", "homepage"),
+            ("consent_version", "2025-09-01"),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .error_for_status()
+        .unwrap();
+
+    let consent = sqlx::query!(
+        "SELECT source, consent_text_version, ip_address, user_agent, confirmed_at FROM subscription_consents",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the consent record.");
+    assert_eq!(consent.source, "homepage");
+    // 客户端提交的版本被忽略，记录的是服务端配置的版本
+    assert_eq!(consent.consent_text_version, "v7");
+    assert_eq!(consent.ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(consent.user_agent.as_deref(), Some("consent-test/1.0"));
+    assert!(consent.confirmed_at.is_none());
+}
+
+#[tokio::test]
+async fn subscribe_records_the_configured_consent_version_by_default() {
+    let app = spawn_app_with(|c| c.consent.text_version = "v7".into()).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+
+    let consent = sqlx::query!("SELECT source, consent_text_version FROM subscription_consents")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the consent record.");
+    assert_eq!(consent.source, "signup_form");
+    assert_eq!(consent.consent_text_version, "v7");
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_when_the_consent_details_are_invalid() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("name=le%20guin&email=ursula_le_guin%40gmail.com&source=%3Cscript%3E", "invalid source"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload had an {}.",
+            description
+        );
+    }
+}
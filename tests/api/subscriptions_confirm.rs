@@ -68,4 +68,37 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
 
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_records_the_consent_confirmation() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    app.api_client
+        .get(confirmation_links.html)
+        .header("User-Agent", "mail-client/2.0")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let consent = sqlx::query!(
+        "SELECT given_at, confirmed_at, confirmation_ip_address, confirmation_user_agent FROM subscription_consents",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the consent record.");
+    let confirmed_at = consent.confirmed_at.expect("The confirmation was not recorded.");
+    assert!(confirmed_at >= consent.given_at);
+    assert_eq!(consent.confirmation_ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(consent.confirmation_user_agent.as_deref(), Some("mail-client/2.0"));
+}
@@ -0,0 +1,152 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter::create_confirmed_subscriber;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter_body(track: bool) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Read more at https://example.com/post",
+        "html_content": r#"<p>Read <a href="https://example.com/post?a=1&amp;b=2">more</a> or <a href="mailto:editor@example.com">write to us</a></p>"#,
+        "track": track,
+    })
+}
+
+/// 发布一期简报，返回发给订阅者的HTML内容
+async fn publish_and_capture_html(app: &TestApp, track: bool) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_newsletters(newsletter_body(track)).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    body["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+/// 从HTML内容中取出包含'marker'的链接
+fn tracking_link(app: &TestApp, html: &str, marker: &str) -> reqwest::Url {
+    let raw_link = linkify::LinkFinder::new()
+        .links(html)
+        .map(|l| l.as_str().to_owned())
+        .find(|l| l.contains(marker))
+        .unwrap_or_else(|| panic!("No {} link in the newsletter.", marker));
+    let mut link = reqwest::Url::parse(&raw_link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+async fn get_issue_history_html(app: &TestApp) -> String {
+    app.api_client
+        .get(&format!("{}/admin/newsletters/history", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn issues_are_not_tracked_unless_requested() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let html = publish_and_capture_html(&app, false).await;
+
+    assert!(html.contains(r#"href="https://example.com/post?a=1&amp;b=2""#));
+    assert!(!html.contains("/t/o/"));
+    assert!(!html.contains("/t/c/"));
+}
+
+#[tokio::test]
+async fn tracked_issues_rewrite_http_links_and_embed_a_pixel() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let html = publish_and_capture_html(&app, true).await;
+
+    assert!(html.contains("/t/o/"));
+    assert!(html.contains("/t/c/"));
+    assert!(!html.contains("https://example.com/post"));
+    assert!(html.contains(r#"href="mailto:editor@example.com""#));
+    // 偏好设置链接不追踪
+    assert!(html.contains("/subscriptions/preferences?token="));
+}
+
+#[tokio::test]
+async fn opens_and_clicks_are_recorded_and_shown_in_the_issue_history() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let html = publish_and_capture_html(&app, true).await;
+
+    let pixel = tracking_link(&app, &html, "/t/o/");
+    for _ in 0..2 {
+        let response = reqwest::get(pixel.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["content-type"], "image/gif");
+    }
+    let click = tracking_link(&app, &html, "/t/c/");
+    let response = app.api_client.get(click).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(response.headers()["location"], "https://example.com/post?a=1&b=2");
+
+    let events: Vec<(String, Option<String>)> =
+        sqlx::query!("SELECT kind, url FROM tracking_events ORDER BY occurred_at")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.kind, r.url))
+            .collect();
+    assert_eq!(
+        events,
+        [
+            ("open".to_string(), None),
+            ("open".to_string(), None),
+            ("click".to_string(), Some("https://example.com/post?a=1&b=2".to_string())),
+        ]
+    );
+
+    app.test_user.login(&app).await;
+    let html_page = get_issue_history_html(&app).await;
+    assert!(html_page.contains("<td>Newsletter title</td>"));
+    assert!(html_page.contains("100.0% (1 unique, 2 total)"));
+    assert!(html_page.contains("100.0% (1 unique, 1 total)"));
+}
+
+#[tokio::test]
+async fn forged_tracking_links_are_rejected() {
+    let app = spawn_app().await;
+
+    for link in ["/t/o/forged.token", "/t/c/forged.token"] {
+        let response = app
+            .api_client
+            .get(&format!("{}{}", &app.address, link))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 404, "link: {}", link);
+    }
+    let events = sqlx::query!("SELECT kind FROM tracking_events")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(events.is_none());
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_issue_history() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(&format!("{}/admin/newsletters/history", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}